@@ -29,9 +29,24 @@ pub enum SyntaxNode {
     MacroDefinition(MacroDefinitionNode),
     Directive(DirectiveNode),
     Label(LabelNode),
+    Assignment(AssignmentNode),
     Error(SyntaxErrorNode),
 }
 
+impl SyntaxNode {
+    pub fn range(&self) -> Range {
+        match self {
+            SyntaxNode::Instruction(node) => node.range,
+            SyntaxNode::MacroInvocation(node) => node.range,
+            SyntaxNode::MacroDefinition(node) => node.range,
+            SyntaxNode::Directive(node) => node.range,
+            SyntaxNode::Label(node) => node.range,
+            SyntaxNode::Assignment(node) => node.range,
+            SyntaxNode::Error(node) => node.range,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct MacroDefinitionNode {
     pub name: Option<Identifier>,
@@ -90,6 +105,23 @@ pub struct InstructionNode {
     pub range: Range,
 }
 
+/// Symbol assignment `name = value`
+#[derive(Debug)]
+pub struct AssignmentNode {
+    pub name: Identifier,
+    pub operands: Vec<OperandListItem>,
+    pub range: Range,
+}
+
+impl AssignmentNode {
+    pub fn value(&self) -> Option<&ValueNode> {
+        self.operands.iter().find_map(|item| match item {
+            OperandListItem::Operand(value) => Some(value),
+            _ => None,
+        })
+    }
+}
+
 /// Macro call with parentheses
 #[derive(Debug)]
 pub struct MacroInvocationNode {
@@ -175,6 +207,65 @@ pub enum ValueNode {
     },
 }
 
+impl ValueNode {
+    pub fn range(&self) -> Range {
+        match self {
+            ValueNode::Register { range }
+            | ValueNode::Decimal { range, .. }
+            | ValueNode::Hexadecimal { range, .. }
+            | ValueNode::Octal { range, .. }
+            | ValueNode::Char { range, .. }
+            | ValueNode::Float { range, .. }
+            | ValueNode::Binary { range, .. }
+            | ValueNode::Symbol { range }
+            | ValueNode::MacroVariable { range }
+            | ValueNode::String { range, .. }
+            | ValueNode::OptionFlag { range }
+            | ValueNode::ElfTypeTag { range }
+            | ValueNode::NumericLabelReference { range }
+            | ValueNode::BinaryExpression { range, .. }
+            | ValueNode::UnaryExpression { range, .. }
+            | ValueNode::ParenthesizedExpression { range, .. }
+            | ValueNode::MalformedValue { range } => *range,
+        }
+    }
+
+    /// Calls `f` for this value and all values nested inside of it, in source order.
+    pub fn visit<'a>(&'a self, f: &mut impl FnMut(&'a ValueNode)) {
+        f(self);
+        match self {
+            ValueNode::String {
+                macro_variables, ..
+            } => {
+                for value in macro_variables {
+                    value.visit(f);
+                }
+            }
+            ValueNode::BinaryExpression { left, right, .. } => {
+                left.visit(f);
+                right.visit(f);
+            }
+            ValueNode::UnaryExpression { body, .. } => body.visit(f),
+            ValueNode::ParenthesizedExpression { head, body, .. } => {
+                if let Some(head) = head {
+                    head.visit(f);
+                }
+                visit_operands(body, f);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` for every value in an operand list, including nested values.
+pub fn visit_operands<'a>(operands: &'a [OperandListItem], f: &mut impl FnMut(&'a ValueNode)) {
+    for operand in operands {
+        if let OperandListItem::Operand(value) = operand {
+            value.visit(f);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum OperatorKind {
     Additive,
@@ -27,7 +27,7 @@ fn parse_file<'a>(text: &str, node: &Node<'a>) -> Ast {
         .filter_map(|child| match child.kind() {
             "instruction" => Some(parse_instruction(text, &child, false)),
             "directive" => Some(parse_directive(text, &child)),
-            "call_instruction" => Some(parse_instruction(text, &child, true)),
+            "call_statement" => Some(parse_instruction(text, &child, true)),
             "macro_label" => Some(parse_label(text, &child, LabelKind::Macro)),
             "label" => Some(parse_label(text, &child, LabelKind::Normal)),
             "numeric_label" => Some(parse_label(text, &child, LabelKind::Numeric)),
@@ -59,9 +59,7 @@ fn parse_directive<'a>(text: &str, node: &Node<'a>) -> SyntaxNode {
             range: node.range(),
         })
     } else {
-        let mnemonic = Identifier {
-            range: mnemonic.range(),
-        };
+        let mnemonic = parse_mnemonic(text, &mnemonic);
         let operands = parse_operands(text, node.child_by_field_name("operands"));
         SyntaxNode::Directive(DirectiveNode {
             mnemonic,
@@ -107,6 +105,7 @@ fn parse_label<'a>(text: &str, node: &Node<'a>, kind: LabelKind) -> SyntaxNode {
     }
 
     name_range.end_byte -= 1;
+    name_range.end_point.column -= 1;
 
     SyntaxNode::Label(LabelNode {
         kind,
@@ -116,7 +115,12 @@ fn parse_label<'a>(text: &str, node: &Node<'a>, kind: LabelKind) -> SyntaxNode {
 }
 
 fn parse_instruction<'a>(text: &str, node: &Node<'a>, is_call: bool) -> SyntaxNode {
-    let mnemonic_node = node.child_by_field_name("mnemonic");
+    // Call statements name the invoked macro in the "name" field
+    let mnemonic_node = if is_call {
+        node.child_by_field_name("name")
+    } else {
+        node.child_by_field_name("mnemonic")
+    };
     let operands_node = node.child_by_field_name("operands");
     let range = node.range();
 
@@ -124,10 +128,31 @@ fn parse_instruction<'a>(text: &str, node: &Node<'a>, is_call: bool) -> SyntaxNo
         return SyntaxNode::Error(SyntaxErrorNode { range });
     };
 
-    let mnemonic = Identifier {
-        range: mnemonic_node.range(),
-    };
-    let operands = parse_operands(text, operands_node);
+    let mnemonic = parse_mnemonic(text, &mnemonic_node);
+    let mut operands = parse_operands(text, operands_node);
+
+    // `name = value` is parsed as an instruction followed by an assignment
+    let is_assignment = text[mnemonic.range.end_byte..]
+        .trim_start_matches([' ', '\t'])
+        .starts_with('=');
+    if is_assignment && !is_call {
+        operands = operands
+            .into_iter()
+            .map(|item| match item {
+                OperandListItem::Operand(ValueNode::BinaryExpression {
+                    right, operator, ..
+                }) if matches!(operator.kind, OperatorKind::Assignment) => {
+                    OperandListItem::Operand(*right)
+                }
+                item => item,
+            })
+            .collect();
+        return SyntaxNode::Assignment(AssignmentNode {
+            name: mnemonic,
+            operands,
+            range,
+        });
+    }
 
     if is_call {
         SyntaxNode::MacroInvocation(MacroInvocationNode {
@@ -144,6 +169,25 @@ fn parse_instruction<'a>(text: &str, node: &Node<'a>, is_call: bool) -> SyntaxNo
     }
 }
 
+/// Mnemonic nodes following a label on the same line include the separating whitespace
+fn parse_mnemonic(text: &str, node: &Node) -> Identifier {
+    let mut range = node.range();
+    let node_text = node_text(text, node);
+    let whitespace = &node_text[..node_text.len() - node_text.trim_start().len()];
+
+    for c in whitespace.bytes() {
+        if c == b'\n' {
+            range.start_point.row += 1;
+            range.start_point.column = 0;
+        } else {
+            range.start_point.column += 1;
+        }
+    }
+    range.start_byte += whitespace.len();
+
+    Identifier { range }
+}
+
 fn parse_operands(text: &str, node: Option<Node>) -> Vec<OperandListItem> {
     let Some(operands_node) = node else {
         return Vec::with_capacity(0);
@@ -153,11 +197,9 @@ fn parse_operands(text: &str, node: Option<Node>) -> Vec<OperandListItem> {
         .children(&mut operands_node.walk())
         .filter_map(|node| match node.kind() {
             "," => Some(OperandListItem::Comma(node.range())),
-            "_expression" => Some(OperandListItem::Operand(parse_expression(
-                text,
-                &node.first_named_child_for_byte(0)?,
-            ))),
             "ERROR" => Some(OperandListItem::MissingOperand(node.range())),
+            "comment" => None,
+            _ if node.is_named() => Some(OperandListItem::Operand(parse_expression(text, &node))),
             _ => None,
         })
         .collect()
@@ -194,6 +236,7 @@ fn parse_string<'a>(text: &str, node: &Node<'a>) -> ValueNode {
     let macro_variables = node
         .named_children(&mut node.walk())
         .map(|node| {
+            let range = node.range();
             if node.kind() == "string_macro_variable" {
                 ValueNode::MacroVariable { range }
             } else {
@@ -252,9 +295,13 @@ fn parse_string_concat<'a>(text: &str, node: &Node<'a>) -> ValueNode {
     let macro_variables = node
         .named_children(&mut node.walk())
         .filter_map(|node| match node.kind() {
-            "macro_variable" => Some(ValueNode::MacroVariable { range }),
+            "macro_variable" => Some(ValueNode::MacroVariable {
+                range: node.range(),
+            }),
             "string" => None,
-            _ => Some(ValueNode::MalformedValue { range }),
+            _ => Some(ValueNode::MalformedValue {
+                range: node.range(),
+            }),
         })
         .collect();
 
@@ -424,7 +471,7 @@ fn parse_parenthesized_expr(text: &str, node: &Node) -> ValueNode {
 
     ValueNode::ParenthesizedExpression {
         head: head_node.map(|node| Box::new(parse_expression(text, &node))),
-        body: parse_operands(text, Some(*node)),
+        body: parse_operands(text, body_node),
         range,
     }
 }
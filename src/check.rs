@@ -0,0 +1,251 @@
+//! Headless analysis for the `check` command, printing diagnostics for people and CI tools.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use tower_lsp_server::ls_types::*;
+
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::settings::Settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// `file:line:column: message [code]`
+    Human,
+    /// array of diagnostics with 1-based positions
+    Json,
+    /// SARIF 2.1.0 log, as read by code scanning services
+    Sarif,
+}
+
+pub struct FileDiagnostics {
+    pub path: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Analyzes the text of a file the same way as documents opened in the editor.
+pub async fn check_file(
+    path: &Path,
+    text: String,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+) -> FileDiagnostics {
+    let uri = std::path::absolute(path)
+        .ok()
+        .and_then(Uri::from_file_path)
+        .unwrap_or_else(|| "file:///check.asm".parse().unwrap());
+    let mut doc = Document::new(uri, 0, text);
+    let mut diagnostics = doc.analyze(definitions, settings).await;
+    diagnostics.sort_by_key(|diagnostic| diagnostic.range.start);
+    FileDiagnostics {
+        path: path.to_path_buf(),
+        diagnostics,
+    }
+}
+
+pub fn has_errors(files: &[FileDiagnostics]) -> bool {
+    files
+        .iter()
+        .flat_map(|file| &file.diagnostics)
+        .any(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR))
+}
+
+pub fn format(files: &[FileDiagnostics], format: Format) -> String {
+    match format {
+        Format::Human => human(files),
+        Format::Json => serde_json::to_string_pretty(&json(files)).unwrap(),
+        Format::Sarif => serde_json::to_string_pretty(&sarif(files)).unwrap(),
+    }
+}
+
+fn human(files: &[FileDiagnostics]) -> String {
+    let mut output = String::new();
+    for file in files {
+        for diagnostic in &file.diagnostics {
+            let start = diagnostic.range.start;
+            // Continuation lines, such as the valid forms of an instruction, are indented
+            let message = diagnostic.message.replace('\n', "\n    ");
+            output.push_str(&format!(
+                "{}:{}:{}: {}{}\n",
+                file.path.display(),
+                start.line + 1,
+                start.character + 1,
+                message,
+                code(diagnostic).map_or(String::new(), |code| format!(" [{}]", code)),
+            ));
+        }
+    }
+
+    let count = |severity| {
+        files
+            .iter()
+            .flat_map(|file| &file.diagnostics)
+            .filter(|diagnostic| diagnostic.severity == Some(severity))
+            .count()
+    };
+    let (errors, warnings) = (
+        count(DiagnosticSeverity::ERROR),
+        count(DiagnosticSeverity::WARNING),
+    );
+    if errors + warnings > 0 {
+        let plural = |count: usize, noun: &str| {
+            format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+        };
+        output.push_str(&format!(
+            "found {} and {}\n",
+            plural(errors, "error"),
+            plural(warnings, "warning")
+        ));
+    }
+    output
+}
+
+fn json(files: &[FileDiagnostics]) -> Value {
+    let diagnostics: Vec<Value> = files
+        .iter()
+        .flat_map(|file| {
+            file.diagnostics.iter().map(|diagnostic| {
+                let range = diagnostic.range;
+                json!({
+                    "file": file.path,
+                    "line": range.start.line + 1,
+                    "column": range.start.character + 1,
+                    "endLine": range.end.line + 1,
+                    "endColumn": range.end.character + 1,
+                    "severity": severity(diagnostic),
+                    "code": code(diagnostic),
+                    "message": diagnostic.message,
+                })
+            })
+        })
+        .collect();
+    Value::Array(diagnostics)
+}
+
+fn sarif(files: &[FileDiagnostics]) -> Value {
+    let mut rules: Vec<&str> = files
+        .iter()
+        .flat_map(|file| &file.diagnostics)
+        .filter_map(code)
+        .collect();
+    rules.sort();
+    rules.dedup();
+
+    let results: Vec<Value> = files
+        .iter()
+        .flat_map(|file| {
+            let uri = file.path.to_string_lossy().replace('\\', "/");
+            file.diagnostics.iter().map(move |diagnostic| {
+                let range = diagnostic.range;
+                let level = match diagnostic.severity {
+                    Some(DiagnosticSeverity::ERROR) => "error",
+                    Some(DiagnosticSeverity::WARNING) => "warning",
+                    _ => "note",
+                };
+                json!({
+                    "ruleId": code(diagnostic),
+                    "level": level,
+                    "message": { "text": diagnostic.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": uri },
+                            "region": {
+                                "startLine": range.start.line + 1,
+                                "startColumn": range.start.character + 1,
+                                "endLine": range.end.line + 1,
+                                "endColumn": range.end.character + 1,
+                            },
+                        },
+                    }],
+                })
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn code(diagnostic: &Diagnostic) -> Option<&str> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => Some(code),
+        _ => None,
+    }
+}
+
+fn severity(diagnostic: &Diagnostic) -> &'static str {
+    match diagnostic.severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "information",
+        _ => "hint",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::Dialect;
+
+    async fn check(text: &str) -> Vec<FileDiagnostics> {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+        vec![
+            check_file(
+                Path::new("src/main.asm"),
+                text.to_string(),
+                &definitions,
+                &settings,
+            )
+            .await,
+        ]
+    }
+
+    #[tokio::test]
+    async fn formats_diagnostics() {
+        let files = check(".text\nmain:\n  j missing\n").await;
+        assert!(has_errors(&files));
+        assert_eq!(
+            format(&files, Format::Human),
+            "src/main.asm:3:5: error: undefined label `missing` [E010]\nfound 1 error and 0 warnings\n"
+        );
+
+        let json: Value = serde_json::from_str(&format(&files, Format::Json)).unwrap();
+        assert_eq!(json[0]["line"], 3);
+        assert_eq!(json[0]["severity"], "error");
+        assert_eq!(json[0]["code"], "E010");
+
+        let sarif: Value = serde_json::from_str(&format(&files, Format::Sarif)).unwrap();
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "E010");
+        assert_eq!(result["level"], "error");
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["region"]["startColumn"],
+            5
+        );
+        assert_eq!(sarif["runs"][0]["tool"]["driver"]["rules"][0]["id"], "E010");
+    }
+
+    #[tokio::test]
+    async fn passes_clean_files() {
+        let files = check(".text\nmain:\n  li $v0, 10\n  syscall\n").await;
+        assert!(!has_errors(&files));
+        assert_eq!(format(&files, Format::Human), "");
+    }
+}
@@ -0,0 +1,402 @@
+//! Command line modes besides serving the language server over stdio.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use serde_json::{json, Value};
+use tower_lsp_server::ls_types::Uri;
+
+use crate::check::{check_file, format, has_errors, Format};
+use crate::config::ProjectConfig;
+use crate::document::Document;
+use crate::lang::{Dialect, LanguageDefinitions};
+use crate::run::{float_registers, registers, MAX_STEPS};
+use crate::settings::Settings;
+use crate::simulator::{Console, Simulator, Status};
+
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+#[command(group(
+    ArgGroup::new("run_options")
+        .args(["config", "no_project_config", "dialect", "abi", "max_steps"])
+        .multiple(true)
+        .requires("run")
+))]
+struct Cli {
+    /// Run a program in the simulator and print the final registers
+    #[arg(long, value_name = "FILE")]
+    run: Option<PathBuf>,
+
+    #[command(flatten)]
+    settings: SettingsArgs,
+
+    /// Number of instructions after which the program is stopped
+    #[arg(long, default_value_t = MAX_STEPS)]
+    max_steps: u64,
+
+    /// Accepted for editors that pass it; the language server always uses stdio
+    #[arg(long, hide = true)]
+    stdio: bool,
+
+    /// Serves the language server on stdio if no command is given
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the Debug Adapter Protocol on stdio
+    Dap,
+    /// Check files and print their diagnostics; exits with 1 if there are errors
+    Check(CheckArgs),
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// Assembly files to check
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+    #[command(flatten)]
+    settings: SettingsArgs,
+    /// ISA version, e.g. mips32r2
+    #[arg(long = "version", value_name = "VERSION")]
+    isa_version: Option<String>,
+}
+
+/// Flags shared by `check` and `--run`. The ISA version is only a flag of `check`, since
+/// `--version` prints the version of the server otherwise.
+#[derive(Args)]
+struct SettingsArgs {
+    /// JSON file with the settings of the editor, optionally nested in `mipsls`; the project
+    /// configuration and the flags take precedence over it
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Ignore `.mipsls.toml` and `mipsls.json` files in the directories of the files and above
+    #[arg(long)]
+    no_project_config: bool,
+    /// Assembler dialect: gas, mars or spim
+    #[arg(long)]
+    dialect: Option<String>,
+    /// Calling convention: none, o32, n32 or n64
+    #[arg(long)]
+    abi: Option<String>,
+}
+
+/// Handles the command line arguments, returning the exit code if the server should not start.
+pub async fn main() -> Option<i32> {
+    let cli = Cli::parse();
+    if let Some(path) = cli.run {
+        return Some(run_file(&path, &cli.settings, cli.max_steps));
+    }
+    match cli.command? {
+        Command::Dap => Some(crate::dap::serve()),
+        Command::Check(args) => Some(check(args).await),
+    }
+}
+
+/// Settings of a file, with the same precedence as in the editor: the configuration file in place
+/// of the client settings, then the project configuration found from the directory of the file,
+/// then the flags.
+fn file_settings(
+    args: &SettingsArgs,
+    isa_version: Option<&str>,
+    mut settings: Settings,
+    file: &Path,
+    configs: &mut HashMap<PathBuf, Option<ProjectConfig>>,
+) -> Result<Settings, String> {
+    if let Some(path) = &args.config {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let value: Value =
+            serde_json::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let value = value.get("mipsls").cloned().unwrap_or(value);
+        settings
+            .parse(value)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+
+    let file = std::path::absolute(file).map_err(|err| format!("{}: {}", file.display(), err))?;
+    let directory = file.parent().unwrap_or(Path::new("")).to_path_buf();
+    if !args.no_project_config {
+        if !configs.contains_key(&directory) {
+            let config = ProjectConfig::discover(&directory).map_err(|err| err.to_string())?;
+            configs.insert(directory.clone(), config);
+        }
+        if let Some(config) = &configs[&directory] {
+            config
+                .apply(&mut settings, Some(&file))
+                .map_err(|err| format!("{}: {}", config.path.display(), err))?;
+        }
+    }
+
+    let flags = json!({
+        "dialect": args.dialect,
+        "version": isa_version,
+        "abi": args.abi,
+    });
+    settings.parse(flags).map_err(|err| err.to_string())?;
+    Ok(settings)
+}
+
+/// Exits with 0 if no file has errors, 1 if some have and 2 if the files cannot be checked.
+async fn check(args: CheckArgs) -> i32 {
+    let mut configs = HashMap::new();
+    let mut definitions = HashMap::new();
+
+    let mut files = Vec::new();
+    let mut unreadable = false;
+    for path in &args.files {
+        let settings = match file_settings(
+            &args.settings,
+            args.isa_version.as_deref(),
+            Settings::default(),
+            path,
+            &mut configs,
+        ) {
+            Ok(settings) => settings,
+            Err(message) => {
+                eprintln!("error: {}", message);
+                return 2;
+            }
+        };
+        let definitions = definitions
+            .entry((
+                settings.dialect,
+                settings.version.label(),
+                settings.extensions.clone(),
+            ))
+            .or_insert_with(|| {
+                let mut definitions = LanguageDefinitions::new();
+                definitions.parse(&settings);
+                definitions
+            });
+
+        match std::fs::read_to_string(path) {
+            Ok(text) => files.push(check_file(path, text, definitions, &settings).await),
+            Err(err) => {
+                eprintln!("error: {}: {}", path.display(), err);
+                unreadable = true;
+            }
+        }
+    }
+
+    let mut report = format(&files, args.format);
+    if args.format != Format::Human {
+        report.push('\n');
+    }
+    // A closed pipe, as with `| head`, is not an error of the check
+    let _ = std::io::stdout().write_all(report.as_bytes());
+    if unreadable {
+        2
+    } else if has_errors(&files) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Console of the terminal the server was started from.
+struct StdConsole;
+
+impl Console for StdConsole {
+    fn write(&mut self, text: &str) {
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(text.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+        }
+    }
+
+    fn read_char(&mut self) -> Option<char> {
+        let mut byte = [0];
+        match std::io::stdin().read(&mut byte) {
+            Ok(1) => Some(byte[0] as char),
+            _ => None,
+        }
+    }
+}
+
+/// Runs a program with the settings `check` would use, printing the final registers to stderr.
+/// The dialect defaults to MARS, whose system calls and memory layout the simulator follows.
+fn run_file(path: &Path, args: &SettingsArgs, max_steps: u64) -> i32 {
+    let base = Settings {
+        dialect: Dialect::Mars,
+        ..Settings::default()
+    };
+    let settings = match file_settings(args, None, base, path, &mut HashMap::new()) {
+        Ok(settings) => settings,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            return 1;
+        }
+    };
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return 1;
+        }
+    };
+    let uri = std::path::absolute(path)
+        .ok()
+        .and_then(Uri::from_file_path)
+        .unwrap_or_else(|| "file:///program.asm".parse().unwrap());
+    let mut doc = Document::new(uri, 0, text);
+    doc.update_model();
+
+    let mut definitions = LanguageDefinitions::new();
+    definitions.parse(&settings);
+
+    let mut simulator = Simulator::new(&doc, &definitions, &settings);
+    simulator.run(&mut StdConsole, max_steps);
+
+    eprintln!();
+    for register in registers(&simulator.machine)
+        .iter()
+        .chain(&float_registers(&simulator.machine))
+    {
+        if register.value != 0 {
+            eprintln!("{:>5} = 0x{:08x}", register.name, register.value);
+        }
+    }
+    match &simulator.status {
+        Status::Exited(code) => *code,
+        Status::Failed(error) => {
+            match error.statement_index {
+                Some(index) => {
+                    let line = doc.ts_range_to_ls(&doc.ast.items[index].range());
+                    eprintln!(
+                        "{}:{}: {}",
+                        path.display(),
+                        line.start.line + 1,
+                        error.message
+                    );
+                }
+                None => eprintln!("{}: {}", path.display(), error.message),
+            }
+            1
+        }
+        Status::Running => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn parses_commands() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "mips-language-server",
+            "check",
+            "--format",
+            "sarif",
+            "--dialect",
+            "gas",
+            "--version",
+            "mips32r2",
+            "--no-project-config",
+            "a.s",
+            "b.s",
+        ])
+        .unwrap();
+        let Some(Command::Check(args)) = cli.command else {
+            panic!("expected the check command");
+        };
+        assert_eq!(args.files, [PathBuf::from("a.s"), PathBuf::from("b.s")]);
+        assert_eq!(args.format, Format::Sarif);
+        let settings = file_settings(
+            &args.settings,
+            args.isa_version.as_deref(),
+            Settings::default(),
+            &args.files[0],
+            &mut HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(settings.dialect, crate::lang::Dialect::Gas);
+        assert!(std::ptr::eq(settings.version, &crate::version::MIPS32R2));
+
+        assert!(Cli::try_parse_from(["mips-language-server", "check"]).is_err());
+        assert!(Cli::try_parse_from(["mips-language-server"])
+            .unwrap()
+            .command
+            .is_none());
+        let cli = Cli::try_parse_from(["mips-language-server", "--stdio"]).unwrap();
+        assert!(cli.command.is_none() && cli.run.is_none());
+
+        let cli = Cli::try_parse_from([
+            "mips-language-server",
+            "--run",
+            "a.s",
+            "--dialect",
+            "spim",
+            "--max-steps",
+            "1000",
+        ])
+        .unwrap();
+        assert_eq!(cli.settings.dialect.as_deref(), Some("spim"));
+        assert_eq!(cli.max_steps, 1000);
+        assert!(Cli::try_parse_from(["mips-language-server", "--dialect", "spim"]).is_err());
+    }
+
+    #[test]
+    fn flags_take_precedence_over_project_config() {
+        let root = std::env::temp_dir().join(format!("mipsls-cli-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(
+            root.join(".mipsls.toml"),
+            "dialect = \"mars\"\nabi = \"o32\"\n",
+        )
+        .unwrap();
+
+        let file = root.join("src/main.asm");
+        let cli = Cli::try_parse_from([
+            "mips-language-server".as_ref(),
+            "check".as_ref(),
+            "--dialect".as_ref(),
+            "gas".as_ref(),
+            file.as_os_str(),
+        ])
+        .unwrap();
+        let Some(Command::Check(mut args)) = cli.command else {
+            panic!("expected the check command");
+        };
+        let settings = file_settings(
+            &args.settings,
+            None,
+            Settings::default(),
+            &file,
+            &mut HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(settings.dialect, Dialect::Gas);
+        assert_eq!(settings.abi, crate::lang::Abi::O32);
+
+        args.settings.no_project_config = true;
+        let settings = file_settings(
+            &args.settings,
+            None,
+            Settings::default(),
+            &file,
+            &mut HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(settings.abi, crate::lang::Abi::None);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
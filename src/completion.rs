@@ -14,7 +14,8 @@ use crate::server::Backend;
 
 impl Backend {
     /// Analyzes the document at the current cursor position and provides matching completions.
-    /// Kinds of completions: instruction, directive (when starting with dot) or register (when starting with $).
+    /// Kinds of completions: instruction, directive (when starting with dot), register (when starting with $)
+    /// or syscall service (after `li $v0,` with MARS and SPIM).
     pub async fn handle_completion(
         &self,
         params: CompletionParams,
@@ -61,7 +62,7 @@ impl Backend {
 
         let starting_index = char_index_to_utf16(line_content, char_index);
 
-        let definitions = &self.definitions.read().await;
+        let (_, definitions) = &self.document_settings(&doc.uri).await;
 
         let range = Range {
             start: Position {
@@ -74,6 +75,11 @@ impl Backend {
         // Generate completion items
         // Split up in: directive, register and instruction
         match starting_char {
+            _ if !definitions.syscalls.is_empty()
+                && is_syscall_service(line_content, char_index) =>
+            {
+                complete_syscall(definitions, range)
+            }
             '.' => complete_directive(definitions, range),
             '$' => complete_register(
                 definitions,
@@ -240,3 +246,63 @@ fn complete_instruction(
 
     completion_response(items, true)
 }
+
+/// Returns true if the word starting at `char_index` is the value loaded by `li $v0, `.
+fn is_syscall_service(line_content: &str, char_index: usize) -> bool {
+    let before: String = line_content.chars().take(char_index).collect();
+    let Some(before) = before.trim_end().strip_suffix(',') else {
+        return false;
+    };
+    let before = before.trim_end();
+    let Some(before) = before
+        .strip_suffix("$v0")
+        .or_else(|| before.strip_suffix("$2"))
+    else {
+        return false;
+    };
+    before.ends_with(char::is_whitespace)
+        && before
+            .trim_end()
+            .rsplit(|c: char| c.is_whitespace() || c == ':')
+            .next()
+            .is_some_and(|mnemonic| mnemonic.eq_ignore_ascii_case("li"))
+}
+
+fn complete_syscall(
+    definitions: &LanguageDefinitions,
+    range: Range,
+) -> jsonrpc::Result<Option<CompletionResponse>> {
+    let items = definitions
+        .syscalls
+        .iter()
+        .map(|(number, syscall)| {
+            let mut item = completion_item(
+                format!("syscall {}: {}", number, syscall.name),
+                syscall.name.clone(),
+                syscall.description.clone(),
+                CompletionItemKind::CONSTANT,
+                number.to_string(),
+                range,
+            );
+            item.filter_text = Some(format!("{} {}", number, syscall.name));
+            item.sort_text = Some(format!("{:03}", number));
+            item
+        })
+        .collect();
+
+    completion_response(items, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_syscall_service_operand() {
+        assert!(is_syscall_service("  li $v0, ", 10));
+        assert!(is_syscall_service("main: li\t$2,4", 12));
+        assert!(!is_syscall_service("  li $v0 ", 9));
+        assert!(!is_syscall_service("  li $t0, ", 10));
+        assert!(!is_syscall_service("  foli $v0, ", 12));
+    }
+}
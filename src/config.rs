@@ -0,0 +1,358 @@
+//! Project configuration, read from the nearest `.mipsls.toml` or `mipsls.json` in the directory
+//! of a file or one of its parents, such as the workspace root, so that a project is set up the
+//! same way in every editor.
+//!
+//! The file accepts the keys of the client settings, plus `overrides` that apply further settings
+//! to the files matching their globs:
+//!
+//! ```toml
+//! dialect = "gas"
+//! includePaths = ["include"]
+//!
+//! [[overrides]]
+//! files = ["exercises/**/*.asm"]
+//! dialect = "mars"
+//! disabledDiagnostics = ["E011"]
+//! ```
+//!
+//! Settings are layered from lowest to highest precedence: the defaults, the client settings,
+//! the project file, then every matching override in the order they are written.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde_json::Value;
+use tower_lsp_server::ls_types::{MessageType, Uri};
+
+use crate::lang::LanguageDefinitions;
+use crate::server::Backend;
+use crate::settings::{Settings, SettingsError};
+
+/// Names of configuration files, in the order they are looked for in each directory.
+pub const FILE_NAMES: [&str; 2] = [".mipsls.toml", "mipsls.json"];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Syntax(PathBuf, String),
+    Glob(PathBuf, globset::Error),
+    Settings(PathBuf, SettingsError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Syntax(path, message) => write!(f, "{}: {}", path.display(), message),
+            ConfigError::Glob(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Settings(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProjectConfig {
+    /// Path of the configuration file
+    pub path: PathBuf,
+    settings: Value,
+    overrides: Vec<Override>,
+}
+
+/// Settings for the files matching any of the globs, relative to the configuration file.
+#[derive(Debug)]
+struct Override {
+    files: GlobSet,
+    settings: Value,
+}
+
+pub fn is_config_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| FILE_NAMES.contains(&name))
+}
+
+impl ProjectConfig {
+    /// Finds the nearest configuration file in the directory or one of its parents.
+    pub fn find(directory: &Path) -> Option<PathBuf> {
+        directory
+            .ancestors()
+            .flat_map(|dir| FILE_NAMES.iter().map(move |name| dir.join(name)))
+            .find(|path| path.is_file())
+    }
+
+    /// Loads the nearest configuration file, if there is one.
+    pub fn discover(directory: &Path) -> Result<Option<Self>, ConfigError> {
+        Self::find(directory)
+            .map(|path| Self::load(&path))
+            .transpose()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::Read(path.to_path_buf(), err))?;
+        let value = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&text)
+                .map_err(|err| ConfigError::Syntax(path.to_path_buf(), err.message().into()))?
+        } else {
+            serde_json::from_str(&text)
+                .map_err(|err| ConfigError::Syntax(path.to_path_buf(), err.to_string()))?
+        };
+        Self::from_value(path, value)
+    }
+
+    fn from_value(path: &Path, value: Value) -> Result<Self, ConfigError> {
+        let syntax = |message: &str| ConfigError::Syntax(path.to_path_buf(), message.into());
+        let Value::Object(mut settings) = value else {
+            return Err(syntax("expected a table of settings"));
+        };
+
+        let overrides = match settings.remove("overrides") {
+            Some(Value::Array(overrides)) => overrides,
+            Some(_) => return Err(syntax("`overrides` must be a list of tables")),
+            None => Vec::new(),
+        };
+        let mut config = ProjectConfig {
+            path: path.to_path_buf(),
+            settings: Value::Null,
+            overrides: Vec::new(),
+        };
+        config.settings = config.check_settings(settings)?;
+
+        for value in overrides {
+            let Value::Object(mut settings) = value else {
+                return Err(syntax("`overrides` must be a list of tables"));
+            };
+            let patterns = match settings.remove("files") {
+                Some(Value::String(pattern)) => vec![Value::String(pattern)],
+                Some(Value::Array(patterns)) => patterns,
+                _ => return Err(syntax("every override needs a list of `files` globs")),
+            };
+
+            let mut files = GlobSetBuilder::new();
+            for pattern in patterns {
+                let Value::String(pattern) = pattern else {
+                    return Err(syntax("`files` globs must be strings"));
+                };
+                let glob = GlobBuilder::new(&pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|err| ConfigError::Glob(path.to_path_buf(), err))?;
+                files.add(glob);
+            }
+            let files = files
+                .build()
+                .map_err(|err| ConfigError::Glob(path.to_path_buf(), err))?;
+
+            let settings = config.check_settings(settings)?;
+            config.overrides.push(Override { files, settings });
+        }
+        Ok(config)
+    }
+
+    /// Resolves relative include paths against the directory of the file and rejects settings
+    /// that would fail when applied later.
+    fn check_settings(
+        &self,
+        mut settings: serde_json::Map<String, Value>,
+    ) -> Result<Value, ConfigError> {
+        if let Some(Value::Array(paths)) = settings.get_mut("includePaths") {
+            for path in paths {
+                if let Value::String(include) = path {
+                    *include = self.directory().join(&*include).to_string_lossy().into();
+                }
+            }
+        }
+
+        let settings = Value::Object(settings);
+        Settings::default()
+            .parse(settings.clone())
+            .map_err(|err| ConfigError::Settings(self.path.clone(), err))?;
+        Ok(settings)
+    }
+
+    /// Directory the configuration file is in, which globs and include paths are relative to.
+    pub fn directory(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    /// Applies the project settings and the overrides matching the file on top of the settings.
+    pub fn apply(&self, settings: &mut Settings, file: Option<&Path>) -> Result<(), SettingsError> {
+        settings.parse(self.settings.clone())?;
+
+        let Some(relative) = file.and_then(|file| file.strip_prefix(self.directory()).ok()) else {
+            return Ok(());
+        };
+        for entry in &self.overrides {
+            if entry.files.is_match(relative) {
+                settings.parse(entry.settings.clone())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend {
+    /// Settings of a document, which are the client settings with the project configuration
+    /// applied, and the definitions for them.
+    pub async fn document_settings(&self, uri: &Uri) -> (Settings, Arc<LanguageDefinitions>) {
+        let mut settings = self.settings.read().await.clone();
+        let path = uri.to_file_path().map(|path| path.into_owned());
+        if let Some(config) = self.project_config(path.as_deref()).await {
+            if let Err(e) = config.apply(&mut settings, path.as_deref()) {
+                log!("Failed to apply {}: {}", config.path.display(), e);
+            }
+        }
+
+        let definitions = self.definitions_for(&settings).await;
+        (settings, definitions)
+    }
+
+    /// Looks up the nearest configuration from the directory of the file, so that directories
+    /// inside a workspace folder can have their own.
+    async fn project_config(&self, path: Option<&Path>) -> Option<Arc<ProjectConfig>> {
+        let directory = path?.parent()?.to_path_buf();
+
+        if let Some(config) = self.configs.read().await.get(&directory) {
+            return config.clone();
+        }
+
+        let config = match ProjectConfig::discover(&directory) {
+            Ok(config) => config.map(Arc::new),
+            Err(e) => {
+                // Reported once, the file is not read again until it changes
+                self.client.show_message(MessageType::ERROR, e).await;
+                None
+            }
+        };
+        self.configs.write().await.insert(directory, config.clone());
+        config
+    }
+
+    /// Forgets the loaded configuration files and analyzes the open documents with the new ones.
+    pub async fn reload_project_configs(&self) {
+        log!("Reloading project configuration");
+        self.configs.write().await.clear();
+        self.analyze_open_documents().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{Abi, Dialect};
+    use crate::version;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("mipsls-config-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("project/exercises/week1")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn applies_overrides_over_client_settings() {
+        let root = directory("toml");
+        std::fs::write(
+            root.join(".mipsls.toml"),
+            "dialect = \"gas\"\nversion = \"mips32r2\"\nincludePaths = [\"include\"]\n\n\
+             [[overrides]]\nfiles = [\"project/exercises/**/*.asm\"]\ndialect = \"mars\"\n\
+             disabledDiagnostics = [\"E011\"]\n\n\
+             [[overrides]]\nfiles = [\"*.asm\"]\nabi = \"n32\"\n",
+        )
+        .unwrap();
+
+        // Found from a workspace folder below the file
+        let config = ProjectConfig::discover(&root.join("project"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.path, root.join(".mipsls.toml"));
+
+        let mut client = Settings::default();
+        client.abi = Abi::O32;
+        client.encoding_hints = true;
+
+        let mut settings = client.clone();
+        let file = root.join("project/exercises/week1/loop.asm");
+        config.apply(&mut settings, Some(&file)).unwrap();
+        assert_eq!(settings.dialect, Dialect::Mars);
+        assert!(std::ptr::eq(settings.version, &version::MIPS32R2));
+        assert_eq!(settings.include_paths, vec![root.join("include")]);
+        assert_eq!(settings.disabled_diagnostics, vec!["E011".to_string()]);
+        // `*` does not match across directories, and the client keeps what the file leaves out
+        assert_eq!(settings.abi, Abi::O32);
+        assert!(settings.encoding_hints);
+
+        let mut settings = client.clone();
+        config
+            .apply(&mut settings, Some(&root.join("main.asm")))
+            .unwrap();
+        assert_eq!(settings.dialect, Dialect::Gas);
+        assert_eq!(settings.abi, Abi::N32);
+        assert!(settings.disabled_diagnostics.is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn loads_json_and_reports_errors() {
+        let root = directory("json");
+        let path = root.join("project/mipsls.json");
+        std::fs::write(
+            &path,
+            r#"{ "dialect": "spim", "overrides": [{ "files": "**/week1/*.s", "abi": "o32" }] }"#,
+        )
+        .unwrap();
+
+        let config = ProjectConfig::discover(&root.join("project/exercises"))
+            .unwrap()
+            .unwrap();
+        let mut settings = Settings::default();
+        let file = root.join("project/exercises/week1/loop.s");
+        config.apply(&mut settings, Some(&file)).unwrap();
+        assert_eq!(settings.dialect, Dialect::Spim);
+        assert_eq!(settings.abi, Abi::O32);
+
+        std::fs::write(&path, r#"{ "dialect": "armasm" }"#).unwrap();
+        assert_eq!(
+            ProjectConfig::load(&path).unwrap_err().to_string(),
+            format!("{}: unknown dialect: `armasm`", path.display())
+        );
+        std::fs::write(&path, r#"{ "overrides": [{ "abi": "o32" }] }"#).unwrap();
+        assert!(matches!(
+            ProjectConfig::load(&path),
+            Err(ConfigError::Syntax(..))
+        ));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn prefers_nested_configuration_in_the_workspace() {
+        use tower_lsp_server::LspService;
+
+        let root = directory("nested");
+        let folder = root.join("project");
+        std::fs::write(folder.join(".mipsls.toml"), "dialect = \"gas\"\n").unwrap();
+        std::fs::write(
+            folder.join("exercises/mipsls.json"),
+            r#"{ "dialect": "mars" }"#,
+        )
+        .unwrap();
+
+        let (service, _socket) = LspService::new(Backend::new);
+        let backend = service.inner();
+        *backend.workspace_folders.write().await = vec![folder.clone()];
+
+        let uri = Uri::from_file_path(folder.join("exercises/week1/loop.asm")).unwrap();
+        let (settings, _) = backend.document_settings(&uri).await;
+        assert_eq!(settings.dialect, Dialect::Mars);
+        let uri = Uri::from_file_path(folder.join("main.asm")).unwrap();
+        let (settings, _) = backend.document_settings(&uri).await;
+        assert_eq!(settings.dialect, Dialect::Gas);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
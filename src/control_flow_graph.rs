@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::document::Document;
+use crate::semantic::cfg::Cfg;
+use crate::server::Backend;
+use crate::settings::Settings;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlFlowGraphParams {
+    pub text_document: TextDocumentIdentifier,
+    #[serde(default)]
+    pub format: GraphFormat,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    #[default]
+    Json,
+    Dot,
+}
+
+/// Response of the `mipsls/controlFlowGraph` request.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ControlFlowGraph {
+    Json { routines: Vec<RoutineGraph> },
+    Dot { dot: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RoutineGraph {
+    /// `None` for code in front of the first routine label
+    pub name: Option<String>,
+    pub blocks: Vec<BlockGraph>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockGraph {
+    pub id: usize,
+    pub labels: Vec<String>,
+    pub range: Range,
+    /// blocks reached by branches and jumps
+    pub targets: Vec<usize>,
+    pub fall_through: Option<usize>,
+    pub calls: Vec<String>,
+    /// `return`, `terminate` or `indirect`
+    pub exit: Option<String>,
+}
+
+impl Backend {
+    pub async fn handle_control_flow_graph(
+        &self,
+        params: ControlFlowGraphParams,
+    ) -> jsonrpc::Result<ControlFlowGraph> {
+        log!("mipsls/controlFlowGraph");
+
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+        let (settings, _) = self.document_settings(&doc.uri).await;
+
+        Ok(control_flow_graph(&doc, &settings, params.format))
+    }
+}
+
+/// Describes the basic blocks of every routine, either as data or as a Graphviz graph.
+pub fn control_flow_graph(
+    doc: &Document,
+    settings: &Settings,
+    format: GraphFormat,
+) -> ControlFlowGraph {
+    let cfg = Cfg::build(doc, settings);
+    match format {
+        GraphFormat::Json => ControlFlowGraph::Json {
+            routines: cfg
+                .routines
+                .iter()
+                .map(|routine| RoutineGraph {
+                    name: routine.name.as_ref().map(|name| name.to_string()),
+                    blocks: routine
+                        .blocks
+                        .iter()
+                        .map(|&id| {
+                            let block = &cfg.blocks[id];
+                            BlockGraph {
+                                id,
+                                labels: block.labels.iter().map(|l| l.to_string()).collect(),
+                                range: block
+                                    .range(doc)
+                                    .map(|range| doc.ts_range_to_ls(&range))
+                                    .unwrap_or_default(),
+                                targets: block.targets.clone(),
+                                fall_through: block.fall_through,
+                                calls: block.calls.iter().map(|c| c.to_string()).collect(),
+                                exit: block.exit.map(|exit| exit.name().to_string()),
+                            }
+                        })
+                        .collect(),
+                })
+                .collect(),
+        },
+        GraphFormat::Dot => ControlFlowGraph::Dot {
+            dot: dot(doc, &cfg),
+        },
+    }
+}
+
+fn dot(doc: &Document, cfg: &Cfg) -> String {
+    let model = &doc.semantic_model;
+    let mut dot = String::from("digraph cfg {\n  node [shape=box, fontname=\"monospace\"];\n");
+
+    for (index, routine) in cfg.routines.iter().enumerate() {
+        dot.push_str(&format!("  subgraph cluster_{} {{\n", index));
+        if let Some(name) = &routine.name {
+            dot.push_str(&format!("    label=\"{}\";\n", escape(name)));
+        }
+        for &id in &routine.blocks {
+            let block = &cfg.blocks[id];
+            let mut label = String::new();
+            for name in &block.labels {
+                label.push_str(&format!("{}:\\l", escape(name)));
+            }
+            for &instruction in &block.instructions {
+                let range = doc.ast.items[model.instructions[instruction].statement_index].range();
+                let text = doc.text[range.start_byte..range.end_byte]
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                label.push_str(&format!("  {}\\l", escape(&text)));
+            }
+            dot.push_str(&format!("    b{} [label=\"{}\"];\n", id, label));
+        }
+        dot.push_str("  }\n");
+    }
+
+    for (id, block) in cfg.blocks.iter().enumerate() {
+        for target in &block.targets {
+            dot.push_str(&format!("  b{} -> b{};\n", id, target));
+        }
+        if let Some(next) = block.fall_through {
+            dot.push_str(&format!("  b{} -> b{} [style=dashed];\n", id, next));
+        }
+        for call in &block.calls {
+            let callee = cfg
+                .routines
+                .iter()
+                .find(|routine| routine.name.as_ref() == Some(call));
+            if let Some(callee) = callee {
+                dot.push_str(&format!("  b{} -> b{} [style=dotted];\n", id, callee.entry));
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn document(text: &str) -> Document {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+        doc
+    }
+
+    const PROGRAM: &str =
+        ".text\nmain:\n  jal f\n  li $v0, 10\n  syscall\nf:\n  beqz $a0, 1f\n  move $v0, $a0\n1:\n  jr $ra\n";
+
+    #[test]
+    fn describes_blocks_per_routine() {
+        let doc = document(PROGRAM);
+        let ControlFlowGraph::Json { routines } =
+            control_flow_graph(&doc, &Settings::default(), GraphFormat::Json)
+        else {
+            panic!("expected JSON graph");
+        };
+        let summary: Vec<_> = routines
+            .iter()
+            .map(|routine| {
+                (
+                    routine.name.as_deref(),
+                    routine
+                        .blocks
+                        .iter()
+                        .map(|block| {
+                            (
+                                block.id,
+                                block.range.start.line,
+                                block.targets.clone(),
+                                block.fall_through,
+                                block.exit.as_deref(),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("main"), vec![(0, 2, vec![], None, Some("terminate"))]),
+                (
+                    Some("f"),
+                    vec![
+                        (1, 6, vec![3], Some(2), None),
+                        (2, 7, vec![], Some(3), None),
+                        (3, 9, vec![], None, Some("return")),
+                    ]
+                ),
+            ]
+        );
+        assert_eq!(routines[0].blocks[0].calls, vec!["f".to_string()]);
+    }
+
+    #[test]
+    fn renders_dot() {
+        let doc = document(PROGRAM);
+        let ControlFlowGraph::Dot { dot } =
+            control_flow_graph(&doc, &Settings::default(), GraphFormat::Dot)
+        else {
+            panic!("expected DOT graph");
+        };
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("    label=\"main\";\n"));
+        assert!(dot.contains("    b0 [label=\"main:\\l  jal f\\l  li $v0, 10\\l  syscall\\l\"];\n"));
+        assert!(dot.contains("  b0 -> b1 [style=dotted];\n"));
+        assert!(dot.contains("  b1 -> b3;\n  b1 -> b2 [style=dashed];\n"));
+    }
+}
@@ -0,0 +1,118 @@
+//! Debug Adapter Protocol server driving the simulator, started with `mips-language-server dap`.
+//!
+//! Messages are read on a separate thread, so that `pause` and `disconnect` reach the session
+//! while the program runs. The program executes in slices between the messages.
+
+mod session;
+mod watch;
+
+use std::io::{BufRead, Read, Write};
+use std::sync::mpsc::{self, TryRecvError};
+
+use serde_json::Value;
+
+use session::Session;
+
+/// Instructions executed before checking for new messages.
+const SLICE: u64 = 10_000;
+
+/// Serves a debug session over stdio and returns the exit code of the process.
+pub fn serve() -> i32 {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        while let Some(message) = read_message(&mut stdin) {
+            if let Some(message) = message {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    let mut session = Session::new();
+    while !session.finished {
+        let request = if session.running() {
+            match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            }
+        };
+
+        let mut messages = match request {
+            Some(request) => session.handle(&request),
+            None => Vec::new(),
+        };
+        if session.running() {
+            messages.extend(session.resume(SLICE));
+        }
+        for message in messages {
+            if write_message(&mut stdout, &message).is_err() {
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+/// Reads a message with its `Content-Length` header; `Some(None)` for a body that is not JSON and
+/// `None` at the end of the input.
+fn read_message(reader: &mut impl BufRead) -> Option<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Some(None);
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(serde_json::from_slice(&body).ok())
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_messages_with_content_length() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &serde_json::json!({ "seq": 1 })).unwrap();
+        write_message(&mut buffer, &serde_json::json!({ "seq": 2 })).unwrap();
+        assert!(buffer.starts_with(b"Content-Length: 9\r\n\r\n{\"seq\":1}"));
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(
+            read_message(&mut reader),
+            Some(Some(serde_json::json!({ "seq": 1 })))
+        );
+        assert_eq!(
+            read_message(&mut reader),
+            Some(Some(serde_json::json!({ "seq": 2 })))
+        );
+        assert_eq!(read_message(&mut reader), None);
+    }
+}
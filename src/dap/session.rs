@@ -0,0 +1,788 @@
+//! State of a debug session and the handling of its requests.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use smol_str::SmolStr;
+use tower_lsp_server::ls_types::Uri;
+
+use crate::document::Document;
+use crate::lang::{Dialect, LanguageDefinitions};
+use crate::run::{float_registers, registers};
+use crate::semantic::eval::Evaluator;
+use crate::semantic::Section;
+use crate::settings::Settings;
+use crate::simulator::program::Operand;
+use crate::simulator::{BufferConsole, Simulator, Status};
+
+use super::watch;
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_REFERENCE: i64 = 1;
+const FLOAT_REGISTERS_REFERENCE: i64 = 2;
+const DATA_REFERENCE: i64 = 3;
+/// Largest block of memory returned by a `readMemory` request.
+const MAX_MEMORY_READ: u64 = 1 << 16;
+
+/// How execution continues until the program stops again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resume {
+    Continue,
+    /// stop after one instruction
+    Step,
+    /// stop once the call stack is not deeper than `depth`
+    StepOver {
+        depth: usize,
+    },
+    /// stop once the call stack is shallower than `depth`
+    StepOut {
+        depth: usize,
+    },
+}
+
+/// Call made by `jal` and its relatives that has not returned yet.
+struct Frame {
+    call_site: u32,
+    return_address: u32,
+}
+
+/// Program being debugged.
+struct Debuggee {
+    path: PathBuf,
+    doc: Document,
+    settings: Settings,
+    simulator: Simulator,
+    console: BufferConsole,
+    /// calls in progress, innermost last
+    frames: Vec<Frame>,
+    breakpoints: HashSet<u32>,
+    /// addresses of the instructions by their line, in source order
+    lines: Vec<(u32, u32)>,
+    /// addresses of the labels in `.text`, in ascending order
+    routines: Vec<(u32, SmolStr)>,
+    /// labels and constants available to watch expressions
+    symbols: HashMap<SmolStr, i64>,
+    stop_on_entry: bool,
+    /// whether the runtime error the program stopped with was shown
+    failure_reported: bool,
+}
+
+pub struct Session {
+    seq: i64,
+    /// whether lines and columns are numbered from 1 on the client
+    lines_start_at_1: bool,
+    columns_start_at_1: bool,
+    debuggee: Option<Debuggee>,
+    resume: Option<Resume>,
+    /// whether the first instruction of a resume runs even if it has a breakpoint
+    leaving_breakpoint: bool,
+    /// whether the client disconnected
+    pub finished: bool,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            seq: 0,
+            lines_start_at_1: true,
+            columns_start_at_1: true,
+            debuggee: None,
+            resume: None,
+            leaving_breakpoint: false,
+            finished: false,
+        }
+    }
+
+    /// Whether the program is executing and [`Session::resume`] should be called.
+    pub fn running(&self) -> bool {
+        self.resume.is_some()
+    }
+
+    /// Handles a request and returns the response followed by any events.
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        log!("dap/{}", command);
+
+        // The response precedes its events, so it takes the next number
+        self.seq += 1;
+        let seq = self.seq;
+        let mut events = Vec::new();
+        let result = match command {
+            "initialize" => {
+                self.lines_start_at_1 = arguments["linesStartAt1"].as_bool().unwrap_or(true);
+                self.columns_start_at_1 = arguments["columnsStartAt1"].as_bool().unwrap_or(true);
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsTerminateRequest": true,
+                }))
+            }
+            "launch" => self.launch(arguments).map(|()| {
+                // Breakpoints are resolved against the program, so they are accepted only now
+                events.push(self.event("initialized", Value::Null));
+                Value::Null
+            }),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                match &self.debuggee {
+                    Some(debuggee) if debuggee.stop_on_entry => {
+                        events.push(self.stopped("entry", None));
+                    }
+                    Some(_) => self.start(Resume::Continue),
+                    None => {}
+                }
+                Ok(Value::Null)
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Floating Point Registers", "variablesReference": FLOAT_REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Data", "variablesReference": DATA_REFERENCE, "expensive": false },
+                ]
+            })),
+            "variables" => self.variables(arguments["variablesReference"].as_i64()),
+            "continue" => {
+                self.start(Resume::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let depth = self.debuggee.as_ref().map_or(0, |d| d.frames.len());
+                self.start(match command {
+                    "next" => Resume::StepOver { depth },
+                    "stepOut" if depth > 0 => Resume::StepOut { depth },
+                    "stepOut" => Resume::Continue,
+                    _ => Resume::Step,
+                });
+                Ok(Value::Null)
+            }
+            "pause" => {
+                if self.resume.take().is_some() {
+                    events.extend(self.output());
+                    events.push(self.stopped("pause", None));
+                }
+                Ok(Value::Null)
+            }
+            "evaluate" => self.evaluate(arguments["expression"].as_str().unwrap_or_default()),
+            "readMemory" => self.read_memory(arguments),
+            "terminate" => {
+                self.resume = None;
+                events.push(self.event("terminated", Value::Null));
+                Ok(Value::Null)
+            }
+            "disconnect" => {
+                self.finished = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("`{}` is not supported", command)),
+        };
+
+        let mut messages = vec![response(seq, request, command, result)];
+        messages.extend(events);
+        messages
+    }
+
+    /// Executes up to `steps` instructions of a resumed program and returns the events if it
+    /// stopped.
+    pub fn resume(&mut self, steps: u64) -> Vec<Value> {
+        let (Some(mode), Some(debuggee)) = (self.resume, self.debuggee.as_mut()) else {
+            self.resume = None;
+            return Vec::new();
+        };
+
+        let mut reason = None;
+        for _ in 0..steps {
+            if debuggee.simulator.status != Status::Running {
+                break;
+            }
+            let pc = debuggee.simulator.machine.pc;
+            if !self.leaving_breakpoint && debuggee.breakpoints.contains(&pc) {
+                reason = Some("breakpoint");
+                break;
+            }
+            self.leaving_breakpoint = false;
+
+            debuggee.step();
+            let depth = debuggee.frames.len();
+            let done = match mode {
+                Resume::Continue => false,
+                Resume::Step => true,
+                Resume::StepOver { depth: limit } => depth <= limit,
+                Resume::StepOut { depth: limit } => depth < limit,
+            };
+            if done {
+                reason = Some("step");
+                break;
+            }
+        }
+
+        let running = debuggee.simulator.status == Status::Running;
+        if running && reason.is_none() {
+            return self.output();
+        }
+        self.resume = None;
+        let mut messages = self.output();
+        match reason {
+            Some(reason) if running => messages.push(self.stopped(reason, None)),
+            _ => messages.extend(self.end()),
+        }
+        messages
+    }
+
+    fn start(&mut self, mode: Resume) {
+        if self.debuggee.is_some() {
+            self.resume = Some(mode);
+            self.leaving_breakpoint = true;
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<(), String> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or("the launch configuration needs a `program`")?;
+        let path = std::path::absolute(path).map_err(|err| err.to_string())?;
+        let text =
+            std::fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        let mut settings = Settings::default();
+        settings.dialect = match arguments["dialect"].as_str() {
+            Some(dialect) => Dialect::parse(dialect).map_err(|err| format!("{:?}", err))?,
+            None => Dialect::Mars,
+        };
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+
+        let uri = Uri::from_file_path(&path).ok_or("the program path is not valid")?;
+        let mut doc = Document::new(uri, 0, text);
+        doc.update_model();
+        let simulator = Simulator::new(&doc, &definitions, &settings);
+
+        let program = &simulator.program;
+        let mut lines: Vec<(u32, u32)> = program
+            .instructions
+            .iter()
+            .map(|(address, instruction)| {
+                let range = doc.ast.items[instruction.statement_index].range();
+                (range.start_point.row as u32, *address)
+            })
+            .collect();
+        lines.sort();
+        let mut routines: Vec<(u32, SmolStr)> = doc
+            .semantic_model
+            .labels
+            .iter()
+            .filter(|(_, label)| label.section == Section::Text)
+            .filter_map(|(name, _)| Some((*program.labels.get(name)? as u32, name.clone())))
+            .collect();
+        routines.sort();
+
+        let mut symbols = program.labels.clone();
+        let evaluator = Evaluator::new(&doc, &settings).with_labels(&program.labels);
+        for name in doc.semantic_model.constants.keys() {
+            if let Some(Ok(value)) = evaluator.constant(name, usize::MAX) {
+                symbols.insert(name.clone(), value);
+            }
+        }
+
+        self.debuggee = Some(Debuggee {
+            path,
+            settings,
+            simulator,
+            console: BufferConsole::new(arguments["input"].as_str().unwrap_or_default()),
+            frames: Vec::new(),
+            breakpoints: HashSet::new(),
+            lines,
+            routines,
+            symbols,
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+            failure_reported: false,
+            doc,
+        });
+        Ok(())
+    }
+
+    /// Places breakpoints on the first instruction at or after each requested line. Breakpoints
+    /// in other sources are not verified and leave the ones of the program in place.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let line_base = self.lines_start_at_1 as u32;
+        let debuggee = self.debuggee.as_mut().ok_or("no program is running")?;
+        let requested = arguments["breakpoints"].as_array().into_iter().flatten();
+        let same_program = arguments["source"]["path"]
+            .as_str()
+            .is_some_and(|path| same_file(Path::new(path), &debuggee.path));
+        if !same_program {
+            let breakpoints: Vec<Value> = requested
+                .map(|breakpoint| {
+                    json!({
+                        "verified": false,
+                        "line": breakpoint["line"],
+                        "message": "breakpoints are only supported in the program",
+                    })
+                })
+                .collect();
+            return Ok(json!({ "breakpoints": breakpoints }));
+        }
+
+        debuggee.breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+            let row = line.saturating_sub(line_base);
+            let found = debuggee
+                .lines
+                .iter()
+                .find(|(instruction_row, _)| *instruction_row >= row);
+            breakpoints.push(match found {
+                Some((row, address)) => {
+                    debuggee.breakpoints.insert(*address);
+                    json!({ "verified": true, "line": row + line_base })
+                }
+                None => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no instruction at or after this line",
+                }),
+            });
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let debuggee = self.debuggee.as_ref().ok_or("no program is running")?;
+        let addresses = std::iter::once(debuggee.simulator.machine.pc)
+            .chain(debuggee.frames.iter().rev().map(|frame| frame.call_site));
+        let frames: Vec<Value> = addresses
+            .enumerate()
+            .map(|(id, address)| {
+                let position = debuggee
+                    .simulator
+                    .program
+                    .instructions
+                    .get(&address)
+                    .map(|instruction| {
+                        debuggee.doc.ast.items[instruction.statement_index].range()
+                            .start_point
+                    });
+                json!({
+                    "id": id,
+                    "name": debuggee.routine(address),
+                    "source": {
+                        "name": debuggee.path.file_name().map(|name| name.to_string_lossy()),
+                        "path": debuggee.path,
+                    },
+                    "line": position.map_or(0, |p| p.row as u32 + self.lines_start_at_1 as u32),
+                    "column": position.map_or(0, |p| p.column as u32 + self.columns_start_at_1 as u32),
+                    "instructionPointerReference": format!("0x{:08x}", address),
+                })
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&self, reference: Option<i64>) -> Result<Value, String> {
+        let debuggee = self.debuggee.as_ref().ok_or("no program is running")?;
+        let machine = &debuggee.simulator.machine;
+        let variables: Vec<Value> = match reference {
+            Some(REGISTERS_REFERENCE) => registers(machine)
+                .iter()
+                .map(|register| word_variable(&register.name, register.value))
+                .collect(),
+            Some(FLOAT_REGISTERS_REFERENCE) => float_registers(machine)
+                .iter()
+                .map(|register| {
+                    json!({
+                        "name": register.name,
+                        "value": format!("{} (0x{:08x})", f32::from_bits(register.value), register.value),
+                        "variablesReference": 0,
+                    })
+                })
+                .collect(),
+            Some(DATA_REFERENCE) => {
+                let mut labels: Vec<(&SmolStr, u32)> = debuggee
+                    .doc
+                    .semantic_model
+                    .labels
+                    .iter()
+                    .filter(|(_, label)| label.section != Section::Text)
+                    .filter_map(|(name, _)| {
+                        Some((name, *debuggee.simulator.program.labels.get(name)? as u32))
+                    })
+                    .collect();
+                labels.sort_by_key(|(name, address)| (*address, *name));
+                labels
+                    .into_iter()
+                    .map(|(name, address)| {
+                        let mut variable =
+                            word_variable(name, machine.memory.load_word(address));
+                        variable["memoryReference"] = json!(format!("0x{:08x}", address));
+                        variable
+                    })
+                    .collect()
+            }
+            _ => return Err("unknown variables reference".to_string()),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn evaluate(&self, expression: &str) -> Result<Value, String> {
+        let debuggee = self.debuggee.as_ref().ok_or("no program is running")?;
+        let value = watch::evaluate(
+            expression,
+            &debuggee.simulator.machine,
+            &debuggee.settings,
+            &debuggee.symbols,
+        )? as u32;
+        Ok(json!({
+            "result": format_word(value),
+            "variablesReference": 0,
+            "memoryReference": format!("0x{:08x}", value),
+        }))
+    }
+
+    fn read_memory(&self, arguments: &Value) -> Result<Value, String> {
+        let debuggee = self.debuggee.as_ref().ok_or("no program is running")?;
+        let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+        let base = match reference.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => reference.parse(),
+        }
+        .map_err(|_| format!("invalid memory reference `{}`", reference))?;
+        let address = base.wrapping_add(arguments["offset"].as_i64().unwrap_or(0) as u32);
+        let count = arguments["count"]
+            .as_u64()
+            .unwrap_or(0)
+            .min(MAX_MEMORY_READ) as u32;
+        let bytes: Vec<u8> = (0..count)
+            .map(|offset| {
+                debuggee
+                    .simulator
+                    .machine
+                    .memory
+                    .load_byte(address.wrapping_add(offset))
+            })
+            .collect();
+        Ok(json!({
+            "address": format!("0x{:08x}", address),
+            "data": base64(&bytes),
+        }))
+    }
+
+    /// Output of the program since the last call.
+    fn output(&mut self) -> Vec<Value> {
+        let Some(debuggee) = self.debuggee.as_mut() else {
+            return Vec::new();
+        };
+        if debuggee.console.output.is_empty() {
+            return Vec::new();
+        }
+        let output = std::mem::take(&mut debuggee.console.output);
+        vec![self.event("output", json!({ "category": "stdout", "output": output }))]
+    }
+
+    /// Events for a program that stopped running: runtime errors stop at the failing instruction
+    /// first, so its state can be inspected.
+    fn end(&mut self) -> Vec<Value> {
+        let Some(debuggee) = self.debuggee.as_mut() else {
+            return Vec::new();
+        };
+        let exit_code = match &debuggee.simulator.status {
+            Status::Failed(error) if !debuggee.failure_reported => {
+                debuggee.failure_reported = true;
+                let message = error.message.clone();
+                return vec![
+                    self.event(
+                        "output",
+                        json!({ "category": "stderr", "output": format!("{}\n", message) }),
+                    ),
+                    self.stopped("exception", Some(&message)),
+                ];
+            }
+            Status::Exited(code) => *code,
+            _ => 1,
+        };
+        vec![
+            self.event("exited", json!({ "exitCode": exit_code })),
+            self.event("terminated", Value::Null),
+        ]
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<&str>) -> Value {
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "description": text,
+                "text": text,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        self.seq += 1;
+        json!({ "seq": self.seq, "type": "event", "event": event, "body": body })
+    }
+}
+
+impl Debuggee {
+    /// Executes one instruction and keeps track of the calls in progress.
+    fn step(&mut self) {
+        let pc = self.simulator.machine.pc;
+        let Some(instruction) = self.simulator.program.instructions.get(&pc) else {
+            self.simulator.step(&mut self.console);
+            return;
+        };
+        let next = pc.wrapping_add(instruction.size);
+        let call = matches!(
+            instruction.mnemonic.as_str(),
+            "jal" | "jalr" | "bal" | "bgezal" | "bltzal"
+        );
+        let jump_register = instruction.mnemonic == "jr"
+            && matches!(instruction.operands.as_deref(), Ok([Operand::Gpr(_)]));
+
+        self.simulator.step(&mut self.console);
+        let target = self.simulator.machine.pc;
+        if call && target != next {
+            self.frames.push(Frame {
+                call_site: pc,
+                return_address: next,
+            });
+        } else if jump_register
+            && self
+                .frames
+                .last()
+                .is_some_and(|frame| frame.return_address == target)
+        {
+            self.frames.pop();
+        }
+    }
+
+    /// Name of the routine an address belongs to: the closest label in `.text` before it.
+    fn routine(&self, address: u32) -> String {
+        self.routines
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= address)
+            .map_or_else(
+                || format!("0x{:08x}", address),
+                |(_, name)| name.to_string(),
+            )
+    }
+}
+
+fn response(seq: i64, request: &Value, command: &str, result: Result<Value, String>) -> Value {
+    let mut response = json!({
+        "seq": seq,
+        "type": "response",
+        "request_seq": request["seq"],
+        "command": command,
+        "success": result.is_ok(),
+    });
+    match result {
+        Ok(body) => response["body"] = body,
+        Err(message) => {
+            response["message"] = json!(message);
+            response["body"] = json!({ "error": { "id": 1, "format": message } });
+        }
+    }
+    response
+}
+
+fn word_variable(name: &str, value: u32) -> Value {
+    json!({
+        "name": name,
+        "value": format_word(value),
+        "variablesReference": 0,
+    })
+}
+
+fn format_word(value: u32) -> String {
+    format!("0x{:08x} ({})", value, value as i32)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (index, byte)| {
+            word | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(word >> (18 - 6 * index) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Client {
+        session: Session,
+        seq: i64,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            let mut messages = self.session.handle(&request);
+            while self.session.running() {
+                messages.extend(self.session.resume(1000));
+            }
+            messages
+        }
+
+        /// Events of a request, by name and body.
+        fn events(&mut self, command: &str, arguments: Value) -> Vec<(String, Value)> {
+            let messages = self.request(command, arguments);
+            assert_eq!(messages[0]["success"], true, "{}", messages[0]);
+            messages[1..]
+                .iter()
+                .map(|event| {
+                    (
+                        event["event"].as_str().unwrap().to_string(),
+                        event["body"].clone(),
+                    )
+                })
+                .collect()
+        }
+
+        fn top_line(&mut self) -> Value {
+            self.request("stackTrace", json!({ "threadId": 1 }))[0]["body"]["stackFrames"][0]
+                ["line"]
+                .clone()
+        }
+
+        fn evaluate(&mut self, expression: &str) -> Value {
+            self.request("evaluate", json!({ "expression": expression }))[0]["body"]["result"]
+                .clone()
+        }
+    }
+
+    fn launch(text: &str) -> (Client, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "mipsls-dap-{}-{:?}.asm",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::write(&path, text).unwrap();
+        let mut client = Client {
+            session: Session::new(),
+            seq: 0,
+        };
+        client.request("initialize", json!({}));
+        let events = client.events("launch", json!({ "program": path, "input": "3\n" }));
+        assert_eq!(events[0].0, "initialized");
+        (client, path)
+    }
+
+    const PROGRAM: &str = ".data\nvalue: .word 5\n.eqv COUNT, 2\n.text\nmain:\n  li $v0, 5\n  syscall\n  move $a0, $v0\n  jal double\n  move $s0, $v0\n  li $v0, 1\n  move $a0, $s0\n  syscall\n  li $v0, 10\n  syscall\ndouble:\n  addu $v0, $a0, $a0\n  jr $ra\n";
+
+    #[test]
+    fn stops_at_breakpoints_and_steps_over_calls() {
+        let (mut client, path) = launch(PROGRAM);
+        let response = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [{ "line": 9 }, { "line": 16 }] }),
+        );
+        assert_eq!(
+            response[0]["body"]["breakpoints"],
+            json!([{ "verified": true, "line": 9 }, { "verified": true, "line": 17 }])
+        );
+        // Breakpoints in other files keep the ones of the program
+        let response = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": "/other.asm" }, "breakpoints": [{ "line": 3 }] }),
+        );
+        assert_eq!(response[0]["body"]["breakpoints"][0]["verified"], false);
+
+        let events = client.events("configurationDone", json!({}));
+        assert_eq!(events[0].1["reason"], "breakpoint");
+        assert_eq!(client.top_line(), 9);
+
+        // Stepping over the call stops at the breakpoint inside it first
+        let events = client.events("next", json!({ "threadId": 1 }));
+        assert_eq!(events[0].1["reason"], "breakpoint");
+        let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+        let frames = &trace[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "double");
+        assert_eq!(frames[0]["line"], 17);
+        assert_eq!(frames[1]["name"], "main");
+        assert_eq!(frames[1]["line"], 9);
+
+        client.events("stepOut", json!({ "threadId": 1 }));
+        assert_eq!(client.top_line(), 10);
+        assert_eq!(client.evaluate("$v0"), "0x00000006 (6)");
+
+        client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [] }),
+        );
+        let events = client.events("continue", json!({ "threadId": 1 }));
+        assert_eq!(events[0].1["output"], "6");
+        assert_eq!(events[1], ("exited".to_string(), json!({ "exitCode": 0 })));
+        assert_eq!(events[2].0, "terminated");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn shows_registers_memory_and_watch_expressions() {
+        let (mut client, path) = launch(PROGRAM);
+        client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [{ "line": 10 }] }),
+        );
+        client.events("configurationDone", json!({}));
+
+        let registers = client.request("variables", json!({ "variablesReference": 1 }));
+        let registers = &registers[0]["body"]["variables"];
+        assert_eq!(registers[2]["name"], "v0");
+        assert_eq!(registers[2]["value"], "0x00000006 (6)");
+
+        let data = client.request("variables", json!({ "variablesReference": 3 }));
+        let data = &data[0]["body"]["variables"][0];
+        assert_eq!(data["name"], "value");
+        assert_eq!(data["value"], "0x00000005 (5)");
+        assert_eq!(data["memoryReference"], "0x10010000");
+
+        let memory = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x10010000", "offset": 0, "count": 4 }),
+        );
+        assert_eq!(memory[0]["body"]["data"], "BQAAAA==");
+
+        assert_eq!(
+            client.evaluate("value + COUNT * $a0"),
+            "0x10010006 (268500998)"
+        );
+        assert_eq!(client.evaluate("value($zero) - 1"), "0x00000004 (4)");
+        assert_eq!(client.evaluate("-$v0"), "0xfffffffa (-6)");
+        let error = client.request("evaluate", json!({ "expression": "$v0 +" }));
+        assert_eq!(error[0]["success"], false);
+        std::fs::remove_file(path).unwrap();
+    }
+}
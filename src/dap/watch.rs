@@ -0,0 +1,141 @@
+//! Watch expressions, written like operands: `$t0`, `buffer + 4`, `8($sp)`, `value - $a0`.
+//!
+//! Registers and memory operands are replaced by their current values, and the resulting constant
+//! expression is folded with the evaluator of the language server, so the operators follow the
+//! same semantics as in the source.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use smol_str::SmolStr;
+use tower_lsp_server::ls_types::Uri;
+use tree_sitter::Range;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::Register;
+use crate::semantic::eval::{EvalError, Evaluator};
+use crate::settings::Settings;
+use crate::simulator::Machine;
+
+/// Text the expression is placed after to parse it as an operand.
+const PREFIX: &str = "  nop ";
+
+/// Evaluates an expression with the labels and constants of the program and the current state of
+/// the machine.
+pub fn evaluate(
+    expression: &str,
+    machine: &Machine,
+    settings: &Settings,
+    symbols: &HashMap<SmolStr, i64>,
+) -> Result<i64, String> {
+    let doc = parse(expression);
+    let value = single_operand(&doc).ok_or_else(|| invalid(expression))?;
+
+    let mut replacements = Vec::new();
+    substitute(&doc, value, machine, settings, symbols, &mut replacements)?;
+    let mut text = expression.to_string();
+    for (range, replacement) in replacements.iter().rev() {
+        let start = range.start_byte - PREFIX.len();
+        let end = range.end_byte - PREFIX.len();
+        text.replace_range(start..end, replacement);
+    }
+
+    let doc = parse(&text);
+    let value = single_operand(&doc).ok_or_else(|| invalid(expression))?;
+    Evaluator::new(&doc, settings)
+        .with_labels(symbols)
+        .evaluate(value, 0)
+        .map_err(|err| match err {
+            EvalError::DivisionByZero(_) => "division by zero".to_string(),
+            EvalError::Overflow(_) => "the value does not fit into a word".to_string(),
+            EvalError::NotConstant => invalid(expression),
+        })
+}
+
+fn invalid(expression: &str) -> String {
+    format!("`{}` cannot be evaluated", expression.trim())
+}
+
+fn parse(expression: &str) -> Document {
+    let mut doc = Document::new(
+        Uri::from_str("file:///watch.asm").unwrap(),
+        0,
+        format!("{}{}\n", PREFIX, expression.trim_end()),
+    );
+    doc.update_model();
+    doc
+}
+
+fn single_operand(doc: &Document) -> Option<&ValueNode> {
+    let [SyntaxNode::Instruction(node)] = doc.ast.items.as_slice() else {
+        return None;
+    };
+    match node.operands.as_slice() {
+        [OperandListItem::Operand(value)] => Some(value),
+        _ => None,
+    }
+}
+
+/// Collects the registers and memory operands of an expression with the text of their values.
+fn substitute(
+    doc: &Document,
+    value: &ValueNode,
+    machine: &Machine,
+    settings: &Settings,
+    symbols: &HashMap<SmolStr, i64>,
+    replacements: &mut Vec<(Range, String)>,
+) -> Result<(), String> {
+    match value {
+        ValueNode::Register { range } => {
+            let value = register(machine, get_text_in_ts_range(&doc.text, *range))?;
+            replacements.push((*range, format!("({})", value as i32)));
+        }
+        ValueNode::ParenthesizedExpression { head, body, range } => {
+            if let [OperandListItem::Operand(ValueNode::Register { range: base })] = body.as_slice()
+            {
+                // `offset(base)` and `(base)` read the word at the address
+                let base = register(machine, get_text_in_ts_range(&doc.text, *base))?;
+                let offset = match head {
+                    Some(head) => evaluate(
+                        get_text_in_ts_range(&doc.text, head.range()),
+                        machine,
+                        settings,
+                        symbols,
+                    )?,
+                    None => 0,
+                };
+                let address = base.wrapping_add(offset as u32);
+                let word = machine.memory.load_word(address);
+                replacements.push((*range, format!("({})", word as i32)));
+                return Ok(());
+            }
+            if let Some(head) = head {
+                substitute(doc, head, machine, settings, symbols, replacements)?;
+            }
+            for item in body {
+                if let OperandListItem::Operand(value) = item {
+                    substitute(doc, value, machine, settings, symbols, replacements)?;
+                }
+            }
+        }
+        ValueNode::BinaryExpression { left, right, .. } => {
+            substitute(doc, left, machine, settings, symbols, replacements)?;
+            substitute(doc, right, machine, settings, symbols, replacements)?;
+        }
+        ValueNode::UnaryExpression { body, .. } => {
+            substitute(doc, body, machine, settings, symbols, replacements)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn register(machine: &Machine, name: &str) -> Result<u32, String> {
+    match Register::parse(name) {
+        Some(Register::Gpr(number)) => Ok(machine.registers[number as usize]),
+        Some(Register::Fpr(number)) => Ok(machine.fpr[number as usize]),
+        None => Err(format!("unknown register `{}`", name)),
+    }
+}
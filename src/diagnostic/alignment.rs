@@ -0,0 +1,180 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::semantic::eval::Evaluator;
+use crate::semantic::layout::{data_size, Layout};
+use crate::settings::Settings;
+
+use super::create_diagnostic;
+
+/// Reports data and memory accesses at addresses that are not a multiple of their size, which
+/// raise address exceptions at runtime.
+pub fn check_alignment(
+    doc: &Document,
+    settings: &Settings,
+    layout: &Layout,
+    diags: &mut Vec<Diagnostic>,
+) {
+    check_data(doc, layout, diags);
+    check_accesses(doc, settings, layout, diags);
+}
+
+/// Data is aligned automatically, unless `.align 0` turned that off.
+fn check_data(doc: &Document, layout: &Layout, diags: &mut Vec<Diagnostic>) {
+    for directive in &doc.semantic_model.directives {
+        let index = directive.statement_index;
+        let (Some(SyntaxNode::Directive(node)), Some(placement)) =
+            (doc.ast.items.get(index), layout.statements[index])
+        else {
+            continue;
+        };
+        let mnemonic = get_text_in_ts_range(&doc.text, node.mnemonic.range);
+        let Some(size) = data_size(&mnemonic.to_ascii_lowercase()) else {
+            continue;
+        };
+        if placement.address % size == 0 || placement.size == 0 {
+            continue;
+        }
+
+        diags.push(create_diagnostic(
+            doc,
+            &node.mnemonic.range,
+            "E015",
+            &format!(
+                "warning: `{}` data at {} is not aligned to {} bytes; add `.align {}` before it",
+                mnemonic,
+                describe_address(layout, placement.address),
+                size,
+                size.trailing_zeros()
+            ),
+            DiagnosticSeverity::WARNING,
+            None,
+        ));
+    }
+}
+
+fn check_accesses(
+    doc: &Document,
+    settings: &Settings,
+    layout: &Layout,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let text = &doc.text;
+    let evaluator = Evaluator::new(doc, settings).with_labels(&layout.labels);
+
+    for instruction in &doc.semantic_model.instructions {
+        let index = instruction.statement_index;
+        let Some(SyntaxNode::Instruction(node)) = doc.ast.items.get(index) else {
+            continue;
+        };
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+        let Some(size) = access_size(&mnemonic.to_ascii_lowercase()) else {
+            continue;
+        };
+        // The address is the last operand: `lw rt, label+4`
+        let Some(OperandListItem::Operand(value)) = instruction
+            .real_operand_indices
+            .last()
+            .and_then(|&operand| node.operands.get(operand))
+        else {
+            continue;
+        };
+        let Some(address) = constant_address(text, &evaluator, value, index) else {
+            continue;
+        };
+        if address % size == 0 {
+            continue;
+        }
+
+        diags.push(create_diagnostic(
+            doc,
+            &value.range(),
+            "E016",
+            &format!(
+                "warning: `{}` accesses {}, which is not aligned to {} bytes",
+                mnemonic,
+                describe_address(layout, address),
+                size
+            ),
+            DiagnosticSeverity::WARNING,
+            None,
+        ));
+    }
+}
+
+/// Evaluates memory operands without a base register, or with `$zero` as base.
+fn constant_address(
+    text: &str,
+    evaluator: &Evaluator,
+    value: &ValueNode,
+    statement_index: usize,
+) -> Option<i64> {
+    match value {
+        ValueNode::ParenthesizedExpression {
+            head: Some(head),
+            body,
+            ..
+        } => {
+            let zero_base = matches!(body.as_slice(), [OperandListItem::Operand(ValueNode::Register { range })]
+                if matches!(get_text_in_ts_range(text, *range), "$zero" | "$0"));
+            if !zero_base {
+                return None;
+            }
+            evaluator.evaluate(head, statement_index).ok()
+        }
+        ValueNode::ParenthesizedExpression { head: None, .. } => None,
+        value => evaluator.evaluate(value, statement_index).ok(),
+    }
+}
+
+fn describe_address(layout: &Layout, address: i64) -> String {
+    if layout.absolute {
+        format!("address {:#010x}", address)
+    } else {
+        format!("offset {:#x}", address)
+    }
+}
+
+/// Number of bytes accessed by loads and stores that require natural alignment.
+fn access_size(mnemonic: &str) -> Option<i64> {
+    match mnemonic {
+        "lh" | "lhu" | "sh" => Some(2),
+        "lw" | "lwu" | "sw" | "ll" | "sc" | "lwc1" | "swc1" | "l.s" | "s.s" => Some(4),
+        "ld" | "sd" | "lld" | "scd" | "ldc1" | "sdc1" | "l.d" | "s.d" => Some(8),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::lang::Dialect;
+
+    async fn diagnostics(text: &str, settings: &Settings) -> Vec<Diagnostic> {
+        test_diagnostics(text, settings, &["E015", "E016"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_misaligned_data_and_accesses() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let text = ".data\nname: .asciiz \"abc\"\nvalue: .word 1\n.align 0\ntext: .ascii \"x\"\nhalf: .half 2\n.text\nmain:\n  lw $t0, value\n  lw $t0, value+2\n  lh $t1, half\n  lb $t2, value+1\n  sw $t0, value+6($zero)\n";
+        let diags = diagnostics(text, &settings).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (5, "warning: `.half` data at address 0x10010009 is not aligned to 2 bytes; add `.align 1` before it"),
+                (9, "warning: `lw` accesses address 0x10010006, which is not aligned to 4 bytes"),
+                (10, "warning: `lh` accesses address 0x10010009, which is not aligned to 2 bytes"),
+                (12, "warning: `sw` accesses address 0x1001000a, which is not aligned to 4 bytes"),
+            ]
+        );
+    }
+}
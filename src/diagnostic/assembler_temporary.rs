@@ -0,0 +1,110 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::semantic::effects::{self, Location};
+use crate::semantic::expand::Expander;
+use crate::semantic::layout::Layout;
+use crate::settings::Settings;
+
+use super::{create_diagnostic, create_single_related_information};
+
+/// Reports explicit uses of `$at` without `.set noat` in documents whose pseudo-instructions
+/// expand to code using `$at`, since the expansions overwrite it.
+pub fn check_assembler_temporary(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    layout: &Layout,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let model = &doc.semantic_model;
+    let expander = Expander::new(doc, definitions, settings, layout);
+    let Some(pseudo) = (0..model.instructions.len()).find(|&instruction| {
+        model.instructions[instruction].options.at
+            && expander
+                .expand(instruction)
+                .is_some_and(|expansion| expansion.uses_at)
+    }) else {
+        return;
+    };
+    let pseudo_statement = &doc.ast.items[model.instructions[pseudo].statement_index];
+    let pseudo_mnemonic = match pseudo_statement {
+        SyntaxNode::Instruction(node) => get_text_in_ts_range(&doc.text, node.mnemonic.range),
+        _ => return,
+    };
+
+    for instruction in &model.instructions {
+        if !instruction.options.at {
+            continue;
+        }
+        let Some(SyntaxNode::Instruction(node)) = doc.ast.items.get(instruction.statement_index)
+        else {
+            continue;
+        };
+        for item in &node.operands {
+            let OperandListItem::Operand(value) = item else {
+                continue;
+            };
+            value.visit(&mut |value| {
+                let ValueNode::Register { range } = value else {
+                    return;
+                };
+                let name = get_text_in_ts_range(&doc.text, *range);
+                if effects::location(name) != Some(Location::Gpr(1)) {
+                    return;
+                }
+                diags.push(create_diagnostic(
+                    doc,
+                    range,
+                    "E028",
+                    &format!(
+                        "warning: `{}` is used by the assembler to expand pseudo-instructions; add `.set noat` before using it",
+                        name
+                    ),
+                    DiagnosticSeverity::WARNING,
+                    create_single_related_information(
+                        doc,
+                        &pseudo_statement.range(),
+                        &format!("`{}` expands to code using `$at`", pseudo_mnemonic),
+                    ),
+                ));
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::lang::Dialect;
+
+    async fn diagnostics(text: &str) -> Vec<(u32, String)> {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        test_diagnostics(text, &settings, &["E028"])
+            .await
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reports_at_without_noat() {
+        let text =
+            ".text\nmain:\n  blt $t0, $t1, main\n  move $at, $t0\n  .set noat\n  lw $t0, 0($1)\n";
+        assert_eq!(
+            diagnostics(text).await,
+            vec![(3, "warning: `$at` is used by the assembler to expand pseudo-instructions; add `.set noat` before using it".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn accepts_at_without_pseudo_instructions() {
+        let text = ".text\nmain:\n  addu $t0, $t1, $t2\n  move $at, $t0\n";
+        assert!(diagnostics(text).await.is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::SyntaxNode;
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{Abi, LanguageDefinitions};
+use crate::semantic::abi::preserved_registers;
+use crate::semantic::cfg::{Cfg, Exit};
+use crate::semantic::frame::Frame;
+use crate::settings::Settings;
+
+use super::{create_diagnostic, create_single_related_information};
+
+/// Reports routines called with `jal` that return without restoring `$ra` or the callee-saved
+/// registers of the configured ABI.
+pub fn check_calling_convention(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    cfg: &Cfg,
+    diags: &mut Vec<Diagnostic>,
+) {
+    if settings.abi == Abi::None {
+        return;
+    }
+    let preserved = preserved_registers(settings.abi, definitions);
+    let instruction_range = |instruction: usize| {
+        doc.ast.items[doc.semantic_model.instructions[instruction].statement_index].range()
+    };
+
+    for (index, routine) in cfg.routines.iter().enumerate() {
+        let Some(name) = &routine.name else {
+            continue;
+        };
+        if !cfg.blocks.iter().any(|block| block.calls.contains(name)) {
+            continue;
+        }
+
+        let frame = Frame::compute(doc, settings, cfg, index, preserved);
+        for &block in &routine.blocks {
+            let (Some(Exit::Return), Some(state)) =
+                (cfg.blocks[block].exit, frame.exits.get(&block))
+            else {
+                continue;
+            };
+            let Some(range) = return_range(doc, &cfg.blocks[block].instructions) else {
+                continue;
+            };
+
+            for location in preserved.iter() {
+                let Some(&instruction) = state.modified.get(&location) else {
+                    continue;
+                };
+                diags.push(create_diagnostic(
+                    doc,
+                    &range,
+                    "E024",
+                    &format!(
+                        "warning: `{}` returns without restoring `{}`, which the {} ABI preserves across calls",
+                        name, location, settings.abi
+                    ),
+                    DiagnosticSeverity::WARNING,
+                    create_single_related_information(
+                        doc,
+                        &instruction_range(instruction),
+                        &format!("`{}` modified here", location),
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Range of the `jr` that returns from a block.
+pub(super) fn return_range(doc: &Document, instructions: &[usize]) -> Option<tree_sitter::Range> {
+    instructions.iter().rev().find_map(|&instruction| {
+        let statement = doc.semantic_model.instructions[instruction].statement_index;
+        match doc.ast.items.get(statement) {
+            Some(SyntaxNode::Instruction(node))
+                if get_text_in_ts_range(&doc.text, node.mnemonic.range)
+                    .to_ascii_lowercase()
+                    .starts_with("jr") =>
+            {
+                Some(node.range)
+            }
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+
+    async fn diagnostics(text: &str, abi: Abi) -> Vec<Diagnostic> {
+        let mut settings = Settings::default();
+        settings.abi = abi;
+        test_diagnostics(text, &settings, &["E024"]).await
+    }
+
+    const PROGRAM: &str = ".text\nmain:\n  jal f\n  li $v0, 10\n  syscall\nf:\n  addiu $sp, $sp, -8\n  sw $ra, 4($sp)\n  move $s0, $a0\n  jal g\n  beqz $v0, 1f\n  lw $ra, 4($sp)\n  addiu $sp, $sp, 8\n  jr $ra\n1:\n  lw $ra, 4($sp)\n  jr $ra\ng:\n  move $v0, $a0\n  jr $ra\n";
+
+    #[tokio::test]
+    async fn reports_registers_not_restored() {
+        let diags = diagnostics(PROGRAM, Abi::O32).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| {
+                let related = d.related_information.as_ref().unwrap();
+                (
+                    d.range.start.line,
+                    d.message.as_str(),
+                    related[0].location.range.start.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (13, "warning: `f` returns without restoring `$s0`, which the o32 ABI preserves across calls", 8),
+                (16, "warning: `f` returns without restoring `$s0`, which the o32 ABI preserves across calls", 8),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_routines_without_abi() {
+        assert!(diagnostics(PROGRAM, Abi::None).await.is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::SyntaxNode;
+use crate::document::Document;
+use crate::semantic::cfg::Cfg;
+
+use super::{create_diagnostic, create_single_related_information};
+
+/// Reports code that no path reaches and routines that run into the next one because they do
+/// not end in a jump, return or exit.
+pub fn check_control_flow(doc: &Document, cfg: &Cfg, diags: &mut Vec<Diagnostic>) {
+    let model = &doc.semantic_model;
+    let statement =
+        |instruction: usize| &doc.ast.items[model.instructions[instruction].statement_index];
+    let unreachable = cfg.unreachable();
+
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        let (Some(range), Some(&last)) = (block.range(doc), block.instructions.last()) else {
+            continue;
+        };
+
+        if unreachable[index] {
+            let mut diagnostic = create_diagnostic(
+                doc,
+                &range,
+                "E020",
+                "warning: unreachable code",
+                DiagnosticSeverity::WARNING,
+                None,
+            );
+            diagnostic.tags = Some(vec![DiagnosticTag::UNNECESSARY]);
+            diags.push(diagnostic);
+        }
+
+        let Some(next) = block.fall_through else {
+            continue;
+        };
+        let routine = &cfg.routines[cfg.blocks[next].routine];
+        let (Some(name), true) = (
+            &routine.name,
+            routine.entry == next && block.routine != cfg.blocks[next].routine,
+        ) else {
+            continue;
+        };
+        let message = match &cfg.routines[block.routine].name {
+            Some(from) => format!(
+                "warning: execution falls through from `{}` into `{}`",
+                from, name
+            ),
+            None => format!("warning: execution falls through into `{}`", name),
+        };
+        let related = model.labels.get(name).and_then(|label| {
+            match doc.ast.items.get(label.statement_index) {
+                Some(SyntaxNode::Label(node)) => {
+                    create_single_related_information(doc, &node.name.range, "start of the routine")
+                }
+                _ => None,
+            }
+        });
+        diags.push(create_diagnostic(
+            doc,
+            &statement(last).range(),
+            "E021",
+            &message,
+            DiagnosticSeverity::WARNING,
+            related,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::settings::Settings;
+
+    async fn diagnostics(text: &str) -> Vec<Diagnostic> {
+        test_diagnostics(text, &Settings::default(), &["E020", "E021"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_unreachable_code_and_fall_through() {
+        let text = ".text\nmain:\n  jal f\n  li $v0, 10\n  syscall\n  addi $t0, $t0, 1\n  move $a0, $t0\nf:\n  beqz $a0, 1f\n  jr $ra\n1:\n  addi $a0, $a0, -1\n.globl g\ng:\n  j g\n  nop\n";
+        let diags = diagnostics(text).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| (d.range.start.line, d.range.end.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (5, 6, "warning: unreachable code"),
+                (
+                    6,
+                    6,
+                    "warning: execution falls through from `main` into `f`"
+                ),
+                (11, 11, "warning: execution falls through from `f` into `g`"),
+                (15, 15, "warning: unreachable code"),
+            ]
+        );
+        assert_eq!(diags[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+        let related = diags[1].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.range.start.line, 7);
+    }
+}
@@ -0,0 +1,97 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{OperandListItem, SyntaxNode};
+use crate::document::Document;
+use crate::semantic::eval::{EvalError, Evaluator};
+use crate::settings::Settings;
+
+use super::{create_diagnostic, severity_prefix};
+
+/// Reports constant expressions that divide by zero or overflow.
+pub fn check_expressions(doc: &Document, settings: &Settings, diags: &mut Vec<Diagnostic>) {
+    let model = &doc.semantic_model;
+    let evaluator = Evaluator::new(doc, settings);
+    // Assemblers reject these expressions
+    let severity = DiagnosticSeverity::ERROR;
+
+    for (index, statement) in doc.ast.items.iter().enumerate() {
+        // Macro bodies are only evaluated on expansion
+        if model.macro_body[index] {
+            continue;
+        }
+        let operands = match statement {
+            SyntaxNode::Instruction(node) => &node.operands,
+            SyntaxNode::Directive(node) => &node.operands,
+            SyntaxNode::Assignment(node) => &node.operands,
+            _ => continue,
+        };
+
+        for item in operands {
+            let OperandListItem::Operand(value) = item else {
+                continue;
+            };
+            let (range, code, message) = match evaluator.evaluate(value, index) {
+                Err(EvalError::DivisionByZero(range)) => {
+                    (range, "E013", "division by zero".to_string())
+                }
+                Err(EvalError::Overflow(range)) => (
+                    range,
+                    "E014",
+                    format!(
+                        "constant expression overflows {}-bit value",
+                        evaluator.bits()
+                    ),
+                ),
+                _ => continue,
+            };
+            diags.push(create_diagnostic(
+                doc,
+                &range,
+                code,
+                &format!("{}: {}", severity_prefix(severity), message),
+                severity,
+                None,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::lang::Dialect;
+
+    async fn diagnostics(text: &str, settings: &Settings) -> Vec<Diagnostic> {
+        test_diagnostics(text, settings, &["E013", "E014"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_division_by_zero_and_overflow() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let text = ".eqv ZERO, 0\n.eqv BIG, 0x10000 * 0x10000\n.data\n.word 8 / ZERO, BIG, 4 % 2\n.text\n  li $t0, 1 << 40\n";
+        let diags = diagnostics(text, &settings).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, 10, "error: constant expression overflows 32-bit value"),
+                (3, 8, "error: division by zero"),
+                (5, 12, "error: constant expression overflows 32-bit value"),
+            ]
+        );
+        assert!(diags
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::ERROR)));
+    }
+}
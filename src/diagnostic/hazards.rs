@@ -0,0 +1,228 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{InstructionNode, OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::semantic::effects::{self, instruction_effects, is_control_transfer, Effects};
+use crate::semantic::Instruction;
+use crate::settings::Settings;
+use crate::version::VersionLabel;
+
+use super::{create_diagnostic, create_single_related_information};
+
+/// Loads whose result is not available to the next instruction on MIPS I.
+const DELAYED_LOADS: &[&str] = &[
+    "lb", "lbu", "lh", "lhu", "lw", "lwl", "lwr", "lwc1", "mfc0", "mfc1", "cfc1",
+];
+
+/// An instruction together with its statement and effects.
+struct Step<'a> {
+    node: &'a InstructionNode,
+    mnemonic: String,
+    effects: Effects,
+    reorder: bool,
+    delay_slot: bool,
+}
+
+/// Reports pipeline hazards the assembler does not avoid in `.set noreorder` code: control
+/// transfers in delay slots, the load delay of MIPS I and the HI/LO hazards before MIPS IV.
+pub fn check_hazards(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let load_delay = !settings.version.has_ancestor(VersionLabel::Mips2);
+    let hi_lo_hazard = !settings.version.has_ancestor(VersionLabel::Mips4)
+        && !settings.version.has_ancestor(VersionLabel::Mips32r1);
+
+    for sequence in sequences(doc, definitions) {
+        for (index, step) in sequence.iter().enumerate() {
+            if step.reorder {
+                continue;
+            }
+            let Some(next) = sequence.get(index + 1) else {
+                continue;
+            };
+
+            // A transfer that is itself in a delay slot has no delay slot of its own
+            if is_control_transfer(&step.mnemonic)
+                && !step.delay_slot
+                && is_control_transfer(&next.mnemonic)
+            {
+                diags.push(create_diagnostic(
+                    doc,
+                    &next.node.mnemonic.range,
+                    "E017",
+                    &format!(
+                        "warning: `{}` in the delay slot of `{}` has unpredictable behavior",
+                        next.mnemonic, step.mnemonic
+                    ),
+                    DiagnosticSeverity::WARNING,
+                    create_single_related_information(
+                        doc,
+                        &step.node.range,
+                        "branch with the delay slot",
+                    ),
+                ));
+            }
+
+            if load_delay && DELAYED_LOADS.contains(&step.mnemonic.as_str()) {
+                // `lwl` and `lwr` may be paired on the same register
+                let paired = matches!(
+                    (step.mnemonic.as_str(), next.mnemonic.as_str()),
+                    ("lwl", "lwr") | ("lwr", "lwl")
+                );
+                let used = step
+                    .effects
+                    .writes
+                    .iter()
+                    .any(|location| next.effects.reads.contains(location));
+                if used && !paired {
+                    diags.push(create_diagnostic(
+                        doc,
+                        &next.node.range,
+                        "E018",
+                        &format!(
+                            "warning: result of `{}` is used in the load delay slot, where it is not yet available on MIPS I",
+                            step.mnemonic
+                        ),
+                        DiagnosticSeverity::WARNING,
+                        create_single_related_information(doc, &step.node.range, "load"),
+                    ));
+                }
+            }
+
+            if hi_lo_hazard && matches!(step.mnemonic.as_str(), "mfhi" | "mflo") {
+                let read = step.effects.reads.first().copied();
+                let clobbering = sequence[index + 1..]
+                    .iter()
+                    .take(2)
+                    .find(|later| read.is_some_and(|read| later.effects.writes.contains(&read)));
+                if let Some(later) = clobbering {
+                    diags.push(create_diagnostic(
+                        doc,
+                        &later.node.mnemonic.range,
+                        "E019",
+                        &format!(
+                            "warning: `{}` modifies {} within two instructions after `{}`, which corrupts the value read before MIPS IV",
+                            later.mnemonic,
+                            if read == Some(effects::Location::Hi) { "HI" } else { "LO" },
+                            step.mnemonic
+                        ),
+                        DiagnosticSeverity::WARNING,
+                        create_single_related_information(doc, &step.node.range, "read of HI/LO"),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Splits the instructions into runs that execute one after another. Macros and section changes
+/// end a run, since the instructions in between are not known.
+fn sequences<'a>(doc: &'a Document, definitions: &LanguageDefinitions) -> Vec<Vec<Step<'a>>> {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+    let mut sequences: Vec<Vec<Step>> = vec![Vec::new()];
+    let mut previous: Option<&Instruction> = None;
+
+    for instruction in &model.instructions {
+        let index = instruction.statement_index;
+        let node = match doc.ast.items.get(index) {
+            Some(SyntaxNode::Instruction(node)) if !model.macro_body[index] => node,
+            _ => {
+                sequences.push(Vec::new());
+                continue;
+            }
+        };
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range).to_ascii_lowercase();
+        let known = definitions.instructions.contains_key(&mnemonic);
+        if !known || previous.is_some_and(|previous| previous.section != instruction.section) {
+            sequences.push(Vec::new());
+        }
+        previous = Some(instruction);
+        if !known {
+            continue;
+        }
+
+        let operands: Vec<&ValueNode> = node
+            .operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+        let effects = instruction_effects(text, &mnemonic, &operands);
+        let sequence = sequences.last_mut().expect("not empty");
+        let delay_slot = sequence.last().is_some_and(|previous| {
+            is_control_transfer(&previous.mnemonic) && !previous.delay_slot && !previous.reorder
+        });
+        sequence.push(Step {
+            node,
+            mnemonic,
+            effects,
+            reorder: instruction.options.reorder,
+            delay_slot,
+        });
+    }
+
+    sequences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::lang::Dialect;
+    use crate::version;
+
+    async fn diagnostics(text: &str, settings: &Settings) -> Vec<Diagnostic> {
+        test_diagnostics(text, settings, &["E017", "E018", "E019"]).await
+    }
+
+    const PROGRAM: &str = ".text\n.set noreorder\nf:\n  lw $t0, 0($a0)\n  addu $v0, $t0, $t1\n  beq $v0, $zero, f\n  j f\n  mflo $t2\n  mult $t2, $t3\n  jr $ra\n  nop\n.set reorder\n  lw $t0, 0($a0)\n  addu $v0, $t0, $t1\n";
+
+    #[tokio::test]
+    async fn reports_hazards_in_noreorder_code() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        settings.version = &version::MIPS1;
+        let diags = diagnostics(PROGRAM, &settings).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| (d.range.start.line, d.code.clone().unwrap()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (4, NumberOrString::String("E018".into())),
+                (6, NumberOrString::String("E017".into())),
+                (8, NumberOrString::String("E019".into())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_hazards_of_the_configured_version() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        settings.version = &version::MIPS32R2;
+        let diags = diagnostics(PROGRAM, &settings).await;
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].code, Some(NumberOrString::String("E017".into())));
+    }
+
+    #[tokio::test]
+    async fn ignores_the_delay_slot_of_a_transfer_in_a_delay_slot() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        let text = ".text\n.set noreorder\nmain:\n  b 1f\n  j main\n1: jr $ra\n  nop\n";
+        let diags = diagnostics(text, &settings).await;
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].range.start.line, 4);
+        assert!(diags[0].message.contains("`j` in the delay slot of `b`"));
+    }
+}
@@ -0,0 +1,301 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{InstructionVariant, LanguageDefinitions};
+use crate::operands::{self, OperandKind, ValueClass};
+use crate::semantic::eval::Evaluator;
+use crate::settings::Settings;
+
+use super::create_diagnostic;
+
+/// An immediate operand whose value cannot be encoded.
+struct OutOfRange<'a> {
+    value: &'a ValueNode,
+    number: i64,
+    kind: OperandKind,
+}
+
+/// Reports constant operands that do not fit into the immediate field of the instruction.
+pub fn check_immediates(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+    let evaluator = Evaluator::new(doc, settings);
+
+    for instruction in &model.instructions {
+        let Some(SyntaxNode::Instruction(node)) = doc.ast.items.get(instruction.statement_index)
+        else {
+            continue;
+        };
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+        if model.macros.contains_key(mnemonic) {
+            continue;
+        }
+        let Some(definition) = definitions.instructions.get(&mnemonic.to_ascii_lowercase()) else {
+            continue;
+        };
+
+        let index = instruction.statement_index;
+        let values: Vec<&ValueNode> = instruction
+            .real_operand_indices
+            .iter()
+            .filter_map(|&index| match node.operands.get(index) {
+                Some(OperandListItem::Operand(value)) => Some(value),
+                _ => None,
+            })
+            .collect();
+        let classes: Vec<ValueClass> = values
+            .iter()
+            .map(|value| ValueClass::of(text, value))
+            .collect();
+
+        // Variants rejecting the operand kinds are reported by the operand check
+        let candidates: Vec<&InstructionVariant> = definition
+            .variants
+            .iter()
+            .filter(|variant| operands::variant_accepts(variant, &classes))
+            .collect();
+        let mut native = candidates.iter().filter(|variant| !variant.pseudo);
+        let Some(first) = native.next() else {
+            continue;
+        };
+        let machine = is_machine_instruction(&definition.variants);
+        if machine {
+            check_offsets(doc, &evaluator, index, first, &values, diags);
+        }
+        let Some(error) = out_of_range(&evaluator, index, first, &values) else {
+            continue;
+        };
+        if native.any(|variant| out_of_range(&evaluator, index, variant, &values).is_none()) {
+            continue;
+        }
+
+        let pseudo = candidates.iter().find(|variant| {
+            variant.pseudo && out_of_range(&evaluator, index, variant, &values).is_none()
+        });
+
+        let diagnostic = match pseudo {
+            // Pseudo-ops such as `li` choose the expansion for the value, nothing is substituted
+            Some(_) if !machine => continue,
+            Some(pseudo) => create_diagnostic(
+                doc,
+                &error.value.range(),
+                "E012",
+                &format!(
+                    "warning: {}; assembled as pseudo-instruction `{} {}` instead",
+                    describe(&error),
+                    mnemonic,
+                    pseudo.operands.join(", ")
+                ),
+                DiagnosticSeverity::WARNING,
+                None,
+            ),
+            None => {
+                // Report the widest range accepted by any variant
+                let error = candidates
+                    .iter()
+                    .filter(|variant| variant.pseudo)
+                    .filter_map(|variant| out_of_range(&evaluator, index, variant, &values))
+                    .max_by_key(|error| error.kind.value_range().map(|(min, max)| max - min))
+                    .unwrap_or(error);
+                create_diagnostic(
+                    doc,
+                    &error.value.range(),
+                    "E012",
+                    &format!("error: {}", describe(&error)),
+                    DiagnosticSeverity::ERROR,
+                    None,
+                )
+            }
+        };
+        diags.push(diagnostic);
+    }
+}
+
+/// Returns true if the mnemonic names a machine instruction, not only a pseudo-op like `li` whose
+/// variants are listed as native where a single instruction suffices.
+fn is_machine_instruction(variants: &[InstructionVariant]) -> bool {
+    variants.iter().any(|variant| {
+        !variant.pseudo && (variant.encoding.is_some() || variant.encoding_r6.is_some())
+    })
+}
+
+/// Reports `offset(base)` operands whose offset does not fit into the 16-bit signed field, which
+/// assemblers replace by computing the address in `$at`.
+fn check_offsets(
+    doc: &Document,
+    evaluator: &Evaluator,
+    statement_index: usize,
+    variant: &InstructionVariant,
+    values: &[&ValueNode],
+    diags: &mut Vec<Diagnostic>,
+) {
+    for (spec, value) in variant.operands.iter().zip(values) {
+        if OperandKind::parse(spec) != OperandKind::Memory {
+            continue;
+        }
+        let ValueNode::ParenthesizedExpression {
+            head: Some(head), ..
+        } = value
+        else {
+            continue;
+        };
+        let Ok(offset) = evaluator.evaluate(head, statement_index) else {
+            continue;
+        };
+        if (i16::MIN as i64..=i16::MAX as i64).contains(&offset) {
+            continue;
+        }
+
+        let (message, severity) = if i32::try_from(offset).is_ok() {
+            (
+                format!(
+                    "warning: offset {} does not fit into a 16-bit signed offset (-32768 to 32767); the address is computed in `$at` instead",
+                    offset
+                ),
+                DiagnosticSeverity::WARNING,
+            )
+        } else {
+            (
+                format!("error: offset {} does not fit into a word", offset),
+                DiagnosticSeverity::ERROR,
+            )
+        };
+        diags.push(create_diagnostic(
+            doc,
+            &head.range(),
+            "E012",
+            &message,
+            severity,
+            None,
+        ));
+    }
+}
+
+fn describe(error: &OutOfRange) -> String {
+    let (min, max) = error.kind.value_range().expect("immediate operand");
+    format!(
+        "{} does not fit into a {} ({} to {})",
+        error.number,
+        error.kind.description(),
+        min,
+        max
+    )
+}
+
+/// Returns the first constant operand that is out of range for the variant.
+fn out_of_range<'a>(
+    evaluator: &Evaluator,
+    statement_index: usize,
+    variant: &InstructionVariant,
+    values: &[&'a ValueNode],
+) -> Option<OutOfRange<'a>> {
+    variant
+        .operands
+        .iter()
+        .zip(values)
+        .find_map(|(spec, value)| {
+            let kind = OperandKind::parse(spec);
+            let (min, max) = kind.value_range()?;
+            let number = evaluator.evaluate(value, statement_index).ok()?;
+            (number < min || number > max).then_some(OutOfRange {
+                value,
+                number,
+                kind,
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::lang::Dialect;
+    use crate::settings::Settings;
+
+    async fn diagnostics(text: &str, settings: &Settings) -> Vec<Diagnostic> {
+        test_diagnostics(text, settings, &["E012"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_immediates_out_of_range() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        let text = "  addi $t0, $t1, 32768\n  addi $t0, $t1, -32768\n  andi $t0, $t1, -1\n  ori $t0, $t1, 65535\n  sll $t0, $t1, 32\n  dsll $t0, $t1, 40\n  dsll $t0, $t1, 64\n";
+        let diags = diagnostics(text, &settings).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (0, "error: 32768 does not fit into a 16-bit signed immediate (-32768 to 32767)"),
+                (2, "error: -1 does not fit into a 16-bit unsigned immediate (0 to 65535)"),
+                (4, "error: 32 does not fit into a 5-bit unsigned immediate (0 to 31)"),
+                (5, "warning: 40 does not fit into a 5-bit unsigned immediate (0 to 31); assembled as pseudo-instruction `dsll rd, rt, sa6` instead"),
+                (6, "error: 64 does not fit into a 6-bit unsigned immediate (0 to 63)"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn accepts_values_fitting_any_variant() {
+        let diags = diagnostics("  li $t0, 40000\n  li $t0, -5\n", &Settings::default()).await;
+        assert!(diags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mentions_accepting_pseudo_instructions() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let diags = diagnostics("  addi $t0, $t1, 100000\n", &settings).await;
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert!(diags[0]
+            .message
+            .ends_with("assembled as pseudo-instruction `addi rt, rs, imm32_s` instead"));
+    }
+
+    #[tokio::test]
+    async fn accepts_values_chosen_by_pseudo_ops() {
+        for dialect in [Dialect::Gas, Dialect::Unspecified] {
+            let mut settings = Settings::default();
+            settings.dialect = dialect;
+            let diags = diagnostics(
+                "  li $t0, 0x12345
+  li $t0, -40000
+",
+                &settings,
+            )
+            .await;
+            assert!(diags.is_empty(), "{}: {:?}", dialect, diags);
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_memory_offsets_out_of_range() {
+        let text = "  lw $t0, 40000($t1)
+  sw $t0, -32768($t1)
+  lb $t0, 0x100000000($t1)
+";
+        let diags = diagnostics(text, &Settings::default()).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| (d.range.start, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (Position::new(0, 10), "warning: offset 40000 does not fit into a 16-bit signed offset (-32768 to 32767); the address is computed in `$at` instead"),
+                (Position::new(2, 10), "error: offset 4294967296 does not fit into a word"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::semantic::layout::string_bytes;
+use crate::settings::Settings;
+
+use super::create_diagnostic;
+
+/// Reports `.include` directives naming a file that is neither next to the document nor in one of
+/// the include paths. Relative include paths are resolved against the directory of the document.
+pub fn check_includes(doc: &Document, settings: &Settings, diags: &mut Vec<Diagnostic>) {
+    // Documents that are not saved to disk have nothing to be relative to
+    let Some(path) = doc.uri.to_file_path() else {
+        return;
+    };
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    for item in &doc.ast.items {
+        let SyntaxNode::Directive(node) = item else {
+            continue;
+        };
+        let mnemonic = get_text_in_ts_range(&doc.text, node.mnemonic.range);
+        if !mnemonic.eq_ignore_ascii_case(".include") {
+            continue;
+        }
+        let Some(OperandListItem::Operand(ValueNode::String { range, .. })) = node.operands.first()
+        else {
+            continue;
+        };
+
+        let literal = get_text_in_ts_range(&doc.text, *range);
+        let name = String::from_utf8_lossy(&string_bytes(literal)).into_owned();
+        let found = std::iter::once(directory.to_path_buf())
+            .chain(
+                settings
+                    .include_paths
+                    .iter()
+                    .map(|include| directory.join(include)),
+            )
+            .any(|include| include.join(&name).is_file());
+        if found {
+            continue;
+        }
+
+        diags.push(create_diagnostic(
+            doc,
+            range,
+            "E029",
+            &format!(
+                "warning: included file `{}` is not found in the directory of the file or the include paths",
+                name
+            ),
+            DiagnosticSeverity::WARNING,
+            None,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics_at;
+    use crate::lang::Dialect;
+    use std::path::PathBuf;
+
+    async fn diagnostics(path: &Path, text: &str, settings: &Settings) -> Vec<Diagnostic> {
+        let uri = Uri::from_file_path(path).unwrap();
+        test_diagnostics_at(uri, text, settings, &["E029"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_missing_includes() {
+        let root = std::env::temp_dir().join(format!("mipsls-includes-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::write(root.join("src/macros.asm"), "").unwrap();
+        std::fs::write(root.join("lib/io.asm"), "").unwrap();
+
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let text = ".include \"macros.asm\"\n.include \"io.asm\"\n.text\nmain:\n  nop\n";
+        let path = root.join("src/main.asm");
+
+        let diags = diagnostics(&path, text, &settings).await;
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].range.start, Position::new(1, 9));
+        assert_eq!(
+            diags[0].message,
+            "warning: included file `io.asm` is not found in the directory of the file or the include paths"
+        );
+
+        settings.include_paths = vec![PathBuf::from("../lib")];
+        assert!(diagnostics(&path, text, &settings).await.is_empty());
+
+        // Diagnostics can be turned off by their code
+        settings.include_paths.clear();
+        settings.disabled_diagnostics = vec!["E029".to_string()];
+        assert!(diagnostics(&path, text, &settings).await.is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
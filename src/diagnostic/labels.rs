@@ -0,0 +1,204 @@
+use std::collections::HashSet;
+
+use smol_str::{SmolStr, ToSmolStr};
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{visit_operands, LabelKind, OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::Dialect;
+use crate::semantic::layout::data_size;
+use crate::settings::Settings;
+
+use super::{create_diagnostic, severity_prefix};
+
+/// Labels where execution starts; they are used by the runtime.
+const ENTRY_POINTS: &[&str] = &["main", "__start"];
+
+/// Directives whose first operand declares a symbol instead of referencing one.
+const DECLARING_DIRECTIVES: &[&str] = &[
+    ".comm", ".equ", ".equiv", ".eqv", ".extern", ".globl", ".global", ".lcomm", ".set", ".weak",
+    ".weakext",
+];
+
+/// Reports references to labels that are never defined and labels that are never referenced.
+pub fn check_labels(doc: &Document, settings: &Settings, diags: &mut Vec<Diagnostic>) {
+    let declared = declared_symbols(doc);
+    check_undefined(doc, settings, &declared, diags);
+    check_unused(doc, &declared, diags);
+}
+
+/// Collects names defined by other means than labels, e.g. `.eqv`, `.extern` or `name = value`.
+fn declared_symbols(doc: &Document) -> HashSet<SmolStr> {
+    let text = &doc.text;
+    let mut declared = HashSet::new();
+
+    for statement in &doc.ast.items {
+        match statement {
+            SyntaxNode::Directive(node) => {
+                let mnemonic = get_text_in_ts_range(text, node.mnemonic.range).to_ascii_lowercase();
+                if !DECLARING_DIRECTIVES.contains(&mnemonic.as_str()) {
+                    continue;
+                }
+                // `.globl` and `.extern` may declare several symbols at once
+                let all = matches!(mnemonic.as_str(), ".globl" | ".global" | ".extern");
+                for item in &node.operands {
+                    if let OperandListItem::Operand(ValueNode::Symbol { range }) = item {
+                        declared.insert(get_text_in_ts_range(text, *range).to_smolstr());
+                    }
+                    if !all && matches!(item, OperandListItem::Operand(_)) {
+                        break;
+                    }
+                }
+            }
+            SyntaxNode::Assignment(node) => {
+                declared.insert(get_text_in_ts_range(text, node.name.range).to_smolstr());
+            }
+            _ => {}
+        }
+    }
+
+    declared
+}
+
+fn check_undefined(
+    doc: &Document,
+    settings: &Settings,
+    declared: &HashSet<SmolStr>,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+
+    // GAS resolves undefined symbols at link time
+    let severity = match settings.dialect {
+        Dialect::Gas => DiagnosticSeverity::WARNING,
+        _ => DiagnosticSeverity::ERROR,
+    };
+
+    for (index, statement) in doc.ast.items.iter().enumerate() {
+        // Labels inside of macros are only resolved on expansion
+        if model.macro_body[index] {
+            continue;
+        }
+        let operands = match statement {
+            // Arguments of macro calls are not necessarily labels
+            SyntaxNode::Instruction(node)
+                if !model
+                    .macros
+                    .contains_key(get_text_in_ts_range(text, node.mnemonic.range)) =>
+            {
+                &node.operands
+            }
+            // Data such as `.word handler` refers to labels, other directives take names of
+            // sections, options or symbols they declare
+            SyntaxNode::Directive(node)
+                if data_size(
+                    &get_text_in_ts_range(text, node.mnemonic.range).to_ascii_lowercase(),
+                )
+                .is_some() =>
+            {
+                &node.operands
+            }
+            _ => continue,
+        };
+
+        visit_operands(operands, &mut |value| {
+            let ValueNode::Symbol { range } = value else {
+                return;
+            };
+            let name = get_text_in_ts_range(text, *range);
+            if model.labels.contains_key(name) || declared.contains(name) {
+                return;
+            }
+            diags.push(create_diagnostic(
+                doc,
+                range,
+                "E010",
+                &format!("{}: undefined label `{}`", severity_prefix(severity), name),
+                severity,
+                None,
+            ));
+        });
+    }
+}
+
+fn check_unused(doc: &Document, declared: &HashSet<SmolStr>, diags: &mut Vec<Diagnostic>) {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+
+    for (name, label) in &model.labels {
+        if model.symbol_references.contains_key(name)
+            || declared.contains(name)
+            || ENTRY_POINTS.contains(&name.as_str())
+            || model.macro_body[label.statement_index]
+        {
+            continue;
+        }
+        let Some(SyntaxNode::Label(node)) = doc.ast.items.get(label.statement_index) else {
+            continue;
+        };
+        if !matches!(node.kind, LabelKind::Normal) {
+            continue;
+        }
+
+        let mut diagnostic = create_diagnostic(
+            doc,
+            &node.name.range,
+            "E011",
+            &format!("hint: label `{}` is never referenced", name),
+            DiagnosticSeverity::HINT,
+            None,
+        );
+        diagnostic.tags = Some(vec![DiagnosticTag::UNNECESSARY]);
+        diags.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+
+    async fn diagnostics(text: &str, settings: &Settings) -> Vec<Diagnostic> {
+        let mut diags = test_diagnostics(text, settings, &["E010", "E011"]).await;
+        diags.sort_by_key(|diagnostic| diagnostic.range.start);
+        diags
+    }
+
+    #[tokio::test]
+    async fn reports_undefined_and_unused_labels() {
+        let text = ".data\nmsg: .asciiz \"hi\"\nunused: .word 0\n.eqv SIZE, 4\n.text\n.globl helper\nmain:\n  la $a0, msg\n  li $t0, SIZE\n  beq $t0, $zero, done\n  j missing\ndone:\n  jr $ra\nhelper:\n  jr $ra\n";
+        let diags = diagnostics(text, &Settings::default()).await;
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "hint: label `unused` is never referenced",
+                "error: undefined label `missing`",
+            ]
+        );
+        assert_eq!(diags[0].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(diags[1].severity, Some(DiagnosticSeverity::ERROR));
+
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        let diags = diagnostics("main:\n  jal printf\n", &settings).await;
+        assert_eq!(diags[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diags[0].message, "warning: undefined label `printf`");
+    }
+
+    #[tokio::test]
+    async fn reports_undefined_labels_in_data() {
+        let text = ".data
+table: .word main, other
+.set noreorder
+.text
+main:
+  la $t0, table
+";
+        let diags = diagnostics(text, &Settings::default()).await;
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["error: undefined label `other`"]);
+    }
+}
@@ -0,0 +1,152 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{visit_operands, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::semantic::cfg::Cfg;
+use crate::semantic::effects::{self, Location};
+use crate::semantic::liveness::Liveness;
+
+use super::create_diagnostic;
+
+/// Reports register writes no instruction reads afterwards, and reads of temporaries and return
+/// values that no path in the routine writes before.
+pub fn check_liveness(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    cfg: &Cfg,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let liveness = Liveness::compute(doc, definitions, cfg);
+
+    for &(instruction, location) in &liveness.dead_writes {
+        let Some(range) = register_range(doc, instruction, location, false) else {
+            continue;
+        };
+        let mut diagnostic = create_diagnostic(
+            doc,
+            &range,
+            "E022",
+            &format!("warning: value written to `{}` is never read", location),
+            DiagnosticSeverity::WARNING,
+            None,
+        );
+        diagnostic.tags = Some(vec![DiagnosticTag::UNNECESSARY]);
+        diags.push(diagnostic);
+    }
+
+    for read in &liveness.undefined_reads {
+        let Some(range) = register_range(doc, read.instruction, read.location, true) else {
+            continue;
+        };
+        let routine = cfg
+            .blocks
+            .iter()
+            .find(|block| block.instructions.contains(&read.instruction))
+            .and_then(|block| cfg.routines[block.routine].name.as_ref());
+        let message = match (&read.after_call, routine) {
+            (Some(callee), _) => format!(
+                "warning: `{}` is read after calling `{}`, which does not set it",
+                read.location, callee
+            ),
+            (None, Some(routine)) => format!(
+                "warning: `{}` may be read before it is written in `{}`",
+                read.location, routine
+            ),
+            (None, None) => format!(
+                "warning: `{}` may be read before it is written",
+                read.location
+            ),
+        };
+        diags.push(create_diagnostic(
+            doc,
+            &range,
+            "E023",
+            &message,
+            DiagnosticSeverity::WARNING,
+            None,
+        ));
+    }
+}
+
+/// Range of the operand naming a register, or of the mnemonic for implicit registers like HI.
+/// Reads are searched from the end, since the first operand is usually written.
+fn register_range(
+    doc: &Document,
+    instruction: usize,
+    location: Location,
+    read: bool,
+) -> Option<tree_sitter::Range> {
+    let statement = doc.semantic_model.instructions[instruction].statement_index;
+    let Some(SyntaxNode::Instruction(node)) = doc.ast.items.get(statement) else {
+        return None;
+    };
+    let mut range = None;
+    visit_operands(&node.operands, &mut |value| {
+        if let ValueNode::Register { range: register } = value {
+            if (read || range.is_none())
+                && effects::location(get_text_in_ts_range(&doc.text, *register)) == Some(location)
+            {
+                range = Some(*register);
+            }
+        }
+    });
+    Some(range.unwrap_or(node.mnemonic.range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::settings::Settings;
+
+    async fn diagnostics(text: &str) -> Vec<Diagnostic> {
+        test_diagnostics(text, &Settings::default(), &["E022", "E023"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_unused_writes_and_reads_before_writes() {
+        let text = ".text\nmain:\n  jal count\n  move $a0, $v0\n  jal show\n  addu $t1, $v0, $t0\n  li $v0, 10\n  syscall\ncount:\n  addi $t0, $t0, 1\n  move $v0, $t0\n  jr $ra\nshow:\n  sw $a0, 0($gp)\n  mflo $t3\n  jr $ra\n";
+        let diags = diagnostics(text).await;
+        let messages: Vec<_> = diags
+            .iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (5, 7, "warning: value written to `$t1` is never read"),
+                (14, 7, "warning: value written to `$t3` is never read"),
+                (
+                    5,
+                    12,
+                    "warning: `$v0` is read after calling `show`, which does not set it"
+                ),
+                (
+                    9,
+                    12,
+                    "warning: `$t0` may be read before it is written in `count`"
+                ),
+                (
+                    14,
+                    2,
+                    "warning: `LO` may be read before it is written in `show`"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_unknown_instructions() {
+        let text = ".text\nmain:\n  addd $t0, $t1, $t2\n  li $v0, 10\n  syscall\n";
+        let diags = test_diagnostics(text, &Settings::default(), &["E022"]).await;
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+}
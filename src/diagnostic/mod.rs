@@ -12,12 +12,34 @@ use crate::document::Document;
 use crate::lang::LanguageDefinitions;
 use crate::lang::{Directive, Instruction, Registers};
 use crate::server::Backend;
+use crate::settings::Settings;
 
 use crate::document;
 use crate::semantic;
+use crate::semantic::cfg::Cfg;
+use crate::semantic::layout::Layout;
+
+mod alignment;
+mod assembler_temporary;
+mod calling_convention;
+mod control_flow;
+mod expressions;
+mod hazards;
+mod immediates;
+mod includes;
+mod labels;
+mod liveness;
+mod names;
+mod operands;
+mod stack;
+mod syscalls;
 
 impl Document {
-    pub async fn analyze_document(&self) -> Vec<Diagnostic> {
+    pub async fn analyze_document(
+        &self,
+        definitions: &LanguageDefinitions,
+        settings: &Settings,
+    ) -> Vec<Diagnostic> {
         let mut diags = Vec::new();
         for err in &self.semantic_model.syntax_errors {
             let diagnostic = match err {
@@ -38,6 +60,35 @@ impl Document {
             }
         }
 
+        names::check_names(self, definitions, &mut diags);
+        labels::check_labels(self, settings, &mut diags);
+        operands::check_operands(self, definitions, &mut diags);
+        expressions::check_expressions(self, settings, &mut diags);
+        immediates::check_immediates(self, definitions, settings, &mut diags);
+
+        let layout = Layout::compute(self, definitions, settings);
+        alignment::check_alignment(self, settings, &layout, &mut diags);
+        assembler_temporary::check_assembler_temporary(
+            self,
+            definitions,
+            settings,
+            &layout,
+            &mut diags,
+        );
+        hazards::check_hazards(self, definitions, settings, &mut diags);
+
+        let cfg = Cfg::build(self, settings);
+        control_flow::check_control_flow(self, &cfg, &mut diags);
+        liveness::check_liveness(self, definitions, &cfg, &mut diags);
+        stack::check_stack(self, settings, &cfg, &mut diags);
+        syscalls::check_syscalls(self, definitions, settings, &cfg, &mut diags);
+        calling_convention::check_calling_convention(self, definitions, settings, &cfg, &mut diags);
+        includes::check_includes(self, settings, &mut diags);
+
+        diags.retain(|diagnostic| match &diagnostic.code {
+            Some(NumberOrString::String(code)) => !settings.disabled_diagnostics.contains(code),
+            _ => true,
+        });
         diags
     }
 }
@@ -180,6 +231,16 @@ fn create_diagnostic(
     }
 }
 
+/// Prefix of diagnostic messages, e.g. `error: ` for errors.
+fn severity_prefix(severity: DiagnosticSeverity) -> &'static str {
+    match severity {
+        DiagnosticSeverity::ERROR => "error",
+        DiagnosticSeverity::WARNING => "warning",
+        DiagnosticSeverity::INFORMATION => "info",
+        _ => "hint",
+    }
+}
+
 fn create_single_related_information(
     doc: &Document,
     range: &tree_sitter::Range,
@@ -193,3 +254,30 @@ fn create_single_related_information(
         message: message.to_string(),
     }])
 }
+
+/// Analyzes `file:///test.asm` with the text and keeps the diagnostics with one of the codes.
+#[cfg(test)]
+async fn test_diagnostics(text: &str, settings: &Settings, codes: &[&str]) -> Vec<Diagnostic> {
+    let uri = "file:///test.asm".parse().unwrap();
+    test_diagnostics_at(uri, text, settings, codes).await
+}
+
+#[cfg(test)]
+async fn test_diagnostics_at(
+    uri: Uri,
+    text: &str,
+    settings: &Settings,
+    codes: &[&str],
+) -> Vec<Diagnostic> {
+    let mut definitions = LanguageDefinitions::new();
+    definitions.parse(settings);
+
+    let mut doc = Document::new(uri, 1, text.to_string());
+    doc.analyze(&definitions, settings)
+        .await
+        .into_iter()
+        .filter(|diagnostic| {
+            matches!(&diagnostic.code, Some(NumberOrString::String(code)) if codes.contains(&code.as_str()))
+        })
+        .collect()
+}
@@ -0,0 +1,304 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{visit_operands, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::fuzzy;
+use crate::lang::{LanguageDefinitions, Unavailability};
+
+use super::create_diagnostic;
+
+/// Reports instruction mnemonics, directives and registers that are not known for the current
+/// settings.
+pub fn check_names(doc: &Document, definitions: &LanguageDefinitions, diags: &mut Vec<Diagnostic>) {
+    let text = &doc.text;
+
+    for (index, statement) in doc.ast.items.iter().enumerate() {
+        let operands = match statement {
+            SyntaxNode::Instruction(node) => {
+                check_instruction(doc, definitions, node.mnemonic.range, diags);
+                &node.operands
+            }
+            SyntaxNode::Directive(node) => {
+                check_directive(doc, definitions, node.mnemonic.range, diags);
+                &node.operands
+            }
+            SyntaxNode::MacroInvocation(node) => &node.operands,
+            _ => continue,
+        };
+
+        let in_macro = doc.semantic_model.macro_body[index];
+        visit_operands(operands, &mut |value| match value {
+            ValueNode::Register { range } => check_register(doc, definitions, *range, diags),
+            // Register-like names that are not registers are parsed as macro variables
+            ValueNode::MacroVariable { range }
+                if !in_macro && get_text_in_ts_range(text, *range).starts_with('$') =>
+            {
+                check_register(doc, definitions, *range, diags)
+            }
+            _ => {}
+        });
+    }
+}
+
+fn check_instruction(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    range: tree_sitter::Range,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let text = &doc.text;
+    let mnemonic = get_text_in_ts_range(text, range);
+    let lowercase = mnemonic.to_ascii_lowercase();
+
+    if definitions.instructions.contains_key(&lowercase)
+        || doc.semantic_model.macros.contains_key(mnemonic)
+        || is_macro_variable(mnemonic)
+    {
+        return;
+    }
+
+    if let Some(reason) = definitions.unavailable_instructions.get(&lowercase) {
+        diags.push(unavailable_diagnostic(
+            doc,
+            range,
+            "instruction",
+            mnemonic,
+            reason,
+        ));
+        return;
+    }
+
+    let candidates = definitions
+        .instructions
+        .keys()
+        .map(|name| name.as_str())
+        .chain(doc.semantic_model.macros.keys().map(|name| name.as_str()));
+    let message = with_suggestion(
+        format!("error: unknown instruction `{}`", mnemonic),
+        fuzzy::suggest(&lowercase, candidates),
+    );
+
+    diags.push(create_diagnostic(
+        doc,
+        &range,
+        "E008",
+        &message,
+        DiagnosticSeverity::ERROR,
+        None,
+    ));
+}
+
+fn check_directive(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    range: tree_sitter::Range,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let mnemonic = get_text_in_ts_range(&doc.text, range);
+    let Some(name) = mnemonic.strip_prefix('.') else {
+        return;
+    };
+    let name = name.to_ascii_lowercase();
+
+    if definitions.directives.contains_key(&name) {
+        return;
+    }
+
+    if let Some(reason) = definitions.unavailable_directives.get(&name) {
+        diags.push(unavailable_diagnostic(
+            doc,
+            range,
+            "directive",
+            mnemonic,
+            reason,
+        ));
+        return;
+    }
+
+    let suggestion = fuzzy::suggest(&name, definitions.directives.keys().map(|n| n.as_str()))
+        .map(|suggestion| format!(".{}", suggestion));
+    let message = with_suggestion(
+        format!("warning: unknown directive `{}`", mnemonic),
+        suggestion.as_deref(),
+    );
+
+    // Assemblers know more directives than the definitions, so this is only a warning
+    diags.push(create_diagnostic(
+        doc,
+        &range,
+        "E008",
+        &message,
+        DiagnosticSeverity::WARNING,
+        None,
+    ));
+}
+
+fn check_register(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    range: tree_sitter::Range,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let name = get_text_in_ts_range(&doc.text, range);
+    if definitions.is_register(name) || is_macro_variable(name) {
+        return;
+    }
+
+    let message = with_suggestion(
+        format!("error: unknown register `{}`", name),
+        fuzzy::suggest(name, definitions.register_names()),
+    );
+
+    diags.push(create_diagnostic(
+        doc,
+        &range,
+        "E008",
+        &message,
+        DiagnosticSeverity::ERROR,
+        None,
+    ));
+}
+
+fn unavailable_diagnostic(
+    doc: &Document,
+    range: tree_sitter::Range,
+    kind: &str,
+    name: &str,
+    reason: &Unavailability,
+) -> Diagnostic {
+    let setting = match reason {
+        Unavailability::Dialect(_) => "dialect",
+        Unavailability::Introduced(_) | Unavailability::Removed(_) => "ISA version",
+        Unavailability::Extension(_) => "extensions",
+    };
+
+    create_diagnostic(
+        doc,
+        &range,
+        "E009",
+        &format!(
+            "error: {} `{}` is not available in the configured {} ({})",
+            kind, name, setting, reason
+        ),
+        DiagnosticSeverity::ERROR,
+        None,
+    )
+}
+
+fn with_suggestion(message: String, suggestion: Option<&str>) -> String {
+    match suggestion {
+        Some(suggestion) => format!("{}; did you mean `{}`?", message, suggestion),
+        None => message,
+    }
+}
+
+/// Names inside macro bodies may be built from macro parameters, e.g. `\op` or `%reg`.
+fn is_macro_variable(name: &str) -> bool {
+    name.contains(['\\', '%'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::lang::Dialect;
+    use crate::settings::Settings;
+    use crate::version::{self, Extension};
+
+    async fn diagnostics(text: &str, settings: &Settings) -> Vec<Diagnostic> {
+        test_diagnostics(text, settings, &["E008", "E009"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_names_with_suggestions() {
+        let diags = diagnostics(
+            ".text\nmain:\n  addd $t0, $t1, $t2\n  add $t10, $t1, $t2\n  .wrod 4\n",
+            &Settings::default(),
+        )
+        .await;
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "error: unknown instruction `addd`; did you mean `add`?",
+                "error: unknown register `$t10`; did you mean `$t1`?",
+                "warning: unknown directive `.wrod`; did you mean `.word`?",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_unavailable_names() {
+        let mut settings = Settings::default();
+        settings.version = &version::MIPS1;
+        let diags = diagnostics("  seb $t0, $t1\n", &settings).await;
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].code, Some(NumberOrString::String("E009".into())));
+        assert!(diags[0].message.contains("configured ISA version"));
+
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        let diags = diagnostics(".ktext\n", &settings).await;
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.contains("configured dialect"));
+    }
+
+    #[tokio::test]
+    async fn accepts_instructions_of_mips32_and_extensions() {
+        let text = "  mul $t0, $t1, $t2
+  madd $t0, $t1
+  clz $t0, $t1
+  sync
+";
+        let mut settings = Settings::default();
+        assert!(diagnostics(text, &settings).await.is_empty());
+
+        settings.version = &version::MIPS32R2;
+        assert!(diagnostics(text, &settings).await.is_empty());
+
+        for dialect in [Dialect::Gas, Dialect::Mars, Dialect::Spim] {
+            settings.dialect = dialect;
+            let diags = diagnostics(
+                "  mul $t0, $t1, $t2
+",
+                &settings,
+            )
+            .await;
+            assert!(diags.is_empty(), "{}: {:?}", dialect, diags);
+        }
+
+        settings.version = &version::MIPS1;
+        settings.dialect = Dialect::Gas;
+        let diags = diagnostics(
+            "  clz $t0, $t1
+",
+            &settings,
+        )
+        .await;
+        assert_eq!(diags[0].code, Some(NumberOrString::String("E009".into())));
+    }
+
+    #[tokio::test]
+    async fn requires_enabled_extensions() {
+        let text = "  lbe $t0, 0($t1)\n  crc32b $t0, $t1\n";
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        settings.version = &version::MIPS32R6;
+        let diags = diagnostics(text, &settings).await;
+        assert_eq!(diags.len(), 2);
+        assert!(diags[0]
+            .message
+            .contains("configured extensions (requires the EVA extension)"));
+
+        settings.extensions = vec![Extension::Eva, Extension::Crc];
+        assert!(diagnostics(text, &settings).await.is_empty());
+
+        settings.version = &version::MIPS32R2;
+        let diags = diagnostics(text, &settings).await;
+        assert_eq!(diags.len(), 2);
+        assert!(diags[0]
+            .message
+            .contains("configured ISA version (requires MIPS32 Release 3)"));
+    }
+}
@@ -0,0 +1,238 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{Instruction, InstructionVariant, LanguageDefinitions};
+use crate::operands::{self, OperandKind, ValueClass};
+
+use super::create_diagnostic;
+
+/// Maximum number of valid forms listed in a diagnostic.
+const MAX_LISTED_FORMS: usize = 3;
+
+/// Reports instructions whose operands are not accepted by any variant.
+pub fn check_operands(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+
+    for instruction in &model.instructions {
+        let Some(SyntaxNode::Instruction(node)) = doc.ast.items.get(instruction.statement_index)
+        else {
+            continue;
+        };
+
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+        if model.macros.contains_key(mnemonic) {
+            continue;
+        }
+        // Unknown mnemonics are reported separately
+        let Some(definition) = definitions.instructions.get(&mnemonic.to_ascii_lowercase()) else {
+            continue;
+        };
+        if definition.variants.is_empty() {
+            continue;
+        }
+
+        let values: Vec<&ValueNode> = instruction
+            .real_operand_indices
+            .iter()
+            .filter_map(|&index| match node.operands.get(index) {
+                Some(OperandListItem::Operand(value)) => Some(value),
+                _ => None,
+            })
+            .collect();
+        let classes: Vec<ValueClass> = values
+            .iter()
+            .map(|value| ValueClass::of(text, value))
+            .collect();
+
+        if definition
+            .variants
+            .iter()
+            .any(|variant| operands::variant_accepts(variant, &classes))
+        {
+            continue;
+        }
+
+        let (range, message) =
+            describe_mismatch(definition, &values, &classes).unwrap_or_else(|| {
+                (
+                    node.mnemonic.range,
+                    format!("error: invalid operands for `{}`", mnemonic),
+                )
+            });
+
+        let forms = closest_variants(definition, &classes)
+            .iter()
+            .map(|variant| format!("`{}`", format_variant(mnemonic, variant)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        diags.push(create_diagnostic(
+            doc,
+            &range,
+            "E007",
+            &format!("{}\nvalid forms: {}", message, forms),
+            DiagnosticSeverity::ERROR,
+            None,
+        ));
+    }
+}
+
+/// Explains why the closest variant does not accept the operands and returns the range
+/// of the offending operands.
+fn describe_mismatch(
+    definition: &Instruction,
+    values: &[&ValueNode],
+    classes: &[ValueClass],
+) -> Option<(tree_sitter::Range, String)> {
+    let same_count = definition
+        .variants
+        .iter()
+        .filter(|variant| variant.operands.len() == classes.len())
+        .min_by_key(|variant| mismatches(variant, classes));
+
+    if let Some(variant) = same_count {
+        let (index, spec) =
+            variant.operands.iter().enumerate().find(|(index, spec)| {
+                !operands::accepts(OperandKind::parse(spec), classes[*index])
+            })?;
+        return Some((
+            values[index].range(),
+            format!(
+                "error: expected {}, found {}",
+                OperandKind::parse(spec).description(),
+                classes[index].description()
+            ),
+        ));
+    }
+
+    let max = definition
+        .variants
+        .iter()
+        .map(|variant| variant.operands.len())
+        .max()?;
+    let mut counts: Vec<usize> = definition
+        .variants
+        .iter()
+        .map(|variant| variant.operands.len())
+        .collect();
+    counts.sort_unstable();
+    counts.dedup();
+    let expected = join_alternatives(&counts);
+
+    if classes.len() > max {
+        let first = values.get(max)?.range();
+        let last = values.last()?.range();
+        let range = tree_sitter::Range {
+            start_byte: first.start_byte,
+            end_byte: last.end_byte,
+            start_point: first.start_point,
+            end_point: last.end_point,
+        };
+        return Some((
+            range,
+            format!(
+                "error: too many operands, expected {}, found {}",
+                expected,
+                classes.len()
+            ),
+        ));
+    }
+
+    let range = values.last().map(|value| value.range())?;
+    Some((
+        range,
+        format!(
+            "error: too few operands, expected {}, found {}",
+            expected,
+            classes.len()
+        ),
+    ))
+}
+
+/// Returns the variants that are closest to the written operands, best first.
+fn closest_variants<'a>(
+    definition: &'a Instruction,
+    classes: &[ValueClass],
+) -> Vec<&'a InstructionVariant> {
+    let mut variants: Vec<_> = definition.variants.iter().collect();
+    variants.sort_by_key(|variant| {
+        variant.operands.len().abs_diff(classes.len()) * 2 + mismatches(variant, classes)
+    });
+    variants.truncate(MAX_LISTED_FORMS);
+    variants
+}
+
+fn mismatches(variant: &InstructionVariant, classes: &[ValueClass]) -> usize {
+    variant
+        .operands
+        .iter()
+        .zip(classes)
+        .filter(|(spec, class)| !operands::accepts(OperandKind::parse(spec), **class))
+        .count()
+}
+
+fn format_variant(mnemonic: &str, variant: &InstructionVariant) -> String {
+    if variant.operands.is_empty() {
+        mnemonic.to_string()
+    } else {
+        format!("{} {}", mnemonic, variant.operands.join(", "))
+    }
+}
+
+fn join_alternatives(counts: &[usize]) -> String {
+    match counts {
+        [] => String::new(),
+        [count] => count.to_string(),
+        [init @ .., last] => format!(
+            "{} or {}",
+            init.iter()
+                .map(|count| count.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            last
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::settings::Settings;
+
+    async fn diagnostics(text: &str) -> Vec<Diagnostic> {
+        test_diagnostics(text, &Settings::default(), &["E007"]).await
+    }
+
+    #[tokio::test]
+    async fn reports_wrong_operand_kinds() {
+        let diags = diagnostics("main:\n  add $t0, $f1, $t2\n  lw $t0, 4($sp)\n").await;
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.starts_with(
+            "error: expected general purpose register, found floating point register"
+        ));
+        assert!(diags[0].message.contains("`add rd, rs, rt`"));
+        assert_eq!(diags[0].range.start, Position::new(1, 11));
+
+        let diags = diagnostics("  jr main\n").await;
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.contains("found label or symbol"));
+    }
+
+    #[tokio::test]
+    async fn reports_operand_count() {
+        let diags = diagnostics("  sll $t0, $t1, 2, 3\n  syscall\n").await;
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0]
+            .message
+            .starts_with("error: too many operands, expected 3, found 4"));
+        assert_eq!(diags[0].range.start, Position::new(0, 19));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::SyntaxNode;
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::semantic::cfg::{Cfg, Exit};
+use crate::semantic::eval::Evaluator;
+use crate::semantic::frame::{Frame, StackPointer};
+use crate::semantic::liveness::LocationSet;
+use crate::settings::Settings;
+
+use super::calling_convention::return_range;
+use super::{create_diagnostic, create_single_related_information};
+
+/// Reports routines that leave with `$sp` different from its value on entry, and loads from
+/// slots of the frame that no path of the routine stores.
+pub fn check_stack(doc: &Document, settings: &Settings, cfg: &Cfg, diags: &mut Vec<Diagnostic>) {
+    let model = &doc.semantic_model;
+    let evaluator = Evaluator::new(doc, settings);
+    let instruction_range =
+        |instruction: usize| doc.ast.items[model.instructions[instruction].statement_index].range();
+
+    for (index, routine) in cfg.routines.iter().enumerate() {
+        let Some(name) = &routine.name else {
+            continue;
+        };
+        let frame = Frame::compute(doc, settings, cfg, index, LocationSet::default());
+        let stored: HashSet<i64> = frame
+            .exits
+            .values()
+            .flat_map(|state| state.stored.keys().copied())
+            .collect();
+
+        for &block in &routine.blocks {
+            let (Some(start), Some(end)) = (frame.entries.get(&block), frame.exits.get(&block))
+            else {
+                continue;
+            };
+            let instructions = &cfg.blocks[block].instructions;
+
+            let mut state = start.clone();
+            for &instruction in instructions {
+                let offset = state.sp_offset();
+                let Some(access) = state.step(doc, &evaluator, instruction, LocationSet::default())
+                else {
+                    continue;
+                };
+                let Some(offset) = offset else {
+                    continue;
+                };
+                if !access.load || access.slot >= 0 || stored.contains(&access.slot) {
+                    continue;
+                }
+                let Some(SyntaxNode::Instruction(node)) = doc
+                    .ast
+                    .items
+                    .get(model.instructions[instruction].statement_index)
+                else {
+                    continue;
+                };
+                diags.push(create_diagnostic(
+                    doc,
+                    &node.range,
+                    "E026",
+                    &format!(
+                        "warning: `{}` loads from `{}($sp)`, which is never stored in `{}`",
+                        get_text_in_ts_range(&doc.text, node.mnemonic.range),
+                        access.slot - offset,
+                        name
+                    ),
+                    DiagnosticSeverity::WARNING,
+                    None,
+                ));
+            }
+
+            let tail_jump = cfg.blocks[block].targets.iter().any(|&target| {
+                let routine = cfg.blocks[target].routine;
+                routine != index && cfg.routines[routine].entry == target
+            }) && cfg.blocks[block].fall_through.is_none();
+            let range = match cfg.blocks[block].exit {
+                Some(Exit::Return) => return_range(doc, instructions),
+                None if tail_jump => jump_range(doc, instructions),
+                _ => None,
+            };
+            let Some(range) = range else {
+                continue;
+            };
+            let leaves = if tail_jump { "jumps out" } else { "returns" };
+            match end.sp {
+                StackPointer::Offset { offset: 0, .. } => {}
+                StackPointer::Offset { offset, adjusted } => diags.push(create_diagnostic(
+                    doc,
+                    &range,
+                    "E025",
+                    &format!(
+                        "warning: `{}` {} with `$sp` {} bytes {} its value on entry",
+                        name,
+                        leaves,
+                        offset.abs(),
+                        if offset < 0 { "below" } else { "above" }
+                    ),
+                    DiagnosticSeverity::WARNING,
+                    adjusted.and_then(|instruction| {
+                        create_single_related_information(
+                            doc,
+                            &instruction_range(instruction),
+                            "`$sp` adjusted here",
+                        )
+                    }),
+                )),
+                StackPointer::Unknown(instruction) => diags.push(create_diagnostic(
+                    doc,
+                    &range,
+                    "E025",
+                    &format!(
+                        "warning: `{}` may {} without restoring `$sp`",
+                        name,
+                        if tail_jump { "jump out" } else { "return" }
+                    ),
+                    DiagnosticSeverity::WARNING,
+                    create_single_related_information(
+                        doc,
+                        &instruction_range(instruction),
+                        "`$sp` modified here",
+                    ),
+                )),
+            }
+        }
+    }
+}
+
+/// Range of the jump or branch that leaves a block.
+fn jump_range(doc: &Document, instructions: &[usize]) -> Option<tree_sitter::Range> {
+    instructions.iter().rev().find_map(|&instruction| {
+        let statement = doc.semantic_model.instructions[instruction].statement_index;
+        match doc.ast.items.get(statement) {
+            Some(SyntaxNode::Instruction(node)) => {
+                let mnemonic = get_text_in_ts_range(&doc.text, node.mnemonic.range);
+                let mnemonic = mnemonic.to_ascii_lowercase();
+                ((mnemonic.starts_with('j') || mnemonic.starts_with('b')) && mnemonic != "break")
+                    .then_some(node.range)
+            }
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+
+    async fn diagnostics(text: &str) -> Vec<(u32, String)> {
+        test_diagnostics(text, &Settings::default(), &["E025", "E026"])
+            .await
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reports_unbalanced_stack() {
+        let text = ".text\nmain:\n  jal f\n  jal g\n  li $v0, 10\n  syscall\nf:\n  addiu $sp, $sp, -8\n  sw $ra, 4($sp)\n  beqz $a0, 1f\n  lw $ra, 4($sp)\n  addiu $sp, $sp, 8\n  jr $ra\n1:\n  lw $s0, 0($sp)\n  j g\ng:\n  jr $ra\n";
+        assert_eq!(
+            diagnostics(text).await,
+            vec![
+                (
+                    14,
+                    "warning: `lw` loads from `0($sp)`, which is never stored in `f`".to_string()
+                ),
+                (
+                    15,
+                    "warning: `f` jumps out with `$sp` 8 bytes below its value on entry"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn accepts_balanced_stack() {
+        let text = ".text\nf:\n  subu $sp, $sp, 16\n  sw $ra, 12($sp)\n  sw $s0, 8($sp)\n  lw $s0, 8($sp)\n  lw $ra, 12($sp)\n  addu $sp, $sp, 16\n  jr $ra\n";
+        assert!(diagnostics(text).await.is_empty());
+    }
+}
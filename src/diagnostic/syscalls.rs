@@ -0,0 +1,89 @@
+use tower_lsp_server::ls_types::*;
+
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::semantic::cfg::Cfg;
+use crate::semantic::effects;
+use crate::semantic::syscall::syscall_sites;
+use crate::settings::Settings;
+
+use super::create_diagnostic;
+
+/// Reports MARS/SPIM system calls whose argument registers are not set in the same block.
+pub fn check_syscalls(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    cfg: &Cfg,
+    diags: &mut Vec<Diagnostic>,
+) {
+    if definitions.syscalls.is_empty() {
+        return;
+    }
+
+    for site in syscall_sites(doc, settings, cfg) {
+        let Some(syscall) = site
+            .service
+            .and_then(|service| definitions.syscalls.get(&service))
+        else {
+            continue;
+        };
+        let range = doc.ast.items
+            [doc.semantic_model.instructions[site.instruction].statement_index]
+            .range();
+        for argument in &syscall.arguments {
+            let Some(location) = effects::location(&argument.register) else {
+                continue;
+            };
+            if site.written.contains(location) {
+                continue;
+            }
+            diags.push(create_diagnostic(
+                doc,
+                &range,
+                "E027",
+                &format!(
+                    "warning: `{}` reads `{}` ({}), which is not set in this block",
+                    syscall.name, argument.register, argument.description
+                ),
+                DiagnosticSeverity::WARNING,
+                None,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::test_diagnostics;
+    use crate::lang::Dialect;
+
+    async fn diagnostics(text: &str, dialect: Dialect) -> Vec<(u32, String)> {
+        let mut settings = Settings::default();
+        settings.dialect = dialect;
+        test_diagnostics(text, &settings, &["E027"])
+            .await
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect()
+    }
+
+    const PROGRAM: &str = ".data\nbuffer: .space 16\n.text\nmain:\n  la $a0, buffer\n  li $v0, 8\n  syscall\n  li $v0, 2\n  syscall\n  li $v0, 10\n  syscall\n";
+
+    #[tokio::test]
+    async fn reports_arguments_not_set() {
+        assert_eq!(
+            diagnostics(PROGRAM, Dialect::Mars).await,
+            vec![
+                (6, "warning: `read_string` reads `$a1` (maximum number of characters to read, including the null terminator), which is not set in this block".to_string()),
+                (8, "warning: `print_float` reads `$f12` (float to print), which is not set in this block".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_other_dialects() {
+        assert!(diagnostics(PROGRAM, Dialect::Gas).await.is_empty());
+    }
+}
@@ -3,11 +3,13 @@ pub mod utils;
 
 use tower_lsp_server::{
     jsonrpc,
-    ls_types::{Diagnostic, Uri},
+    ls_types::{Diagnostic, SemanticTokens, Uri},
 };
 use tree_sitter::*;
 
-use crate::{ast::Ast, document, semantic::SemanticModel};
+use crate::{
+    ast::Ast, document, lang::LanguageDefinitions, semantic::SemanticModel, settings::Settings,
+};
 
 pub struct Document {
     /// resource identifier of document
@@ -26,6 +28,8 @@ pub struct Document {
     pub ast: Ast,
     /// semantic document information
     pub semantic_model: SemanticModel,
+    /// semantic tokens last sent to the client, used for delta requests
+    pub semantic_tokens: Option<SemanticTokens>,
 }
 
 impl Document {
@@ -52,6 +56,7 @@ impl Document {
             uri,
             ast,
             semantic_model,
+            semantic_tokens: None,
         }
     }
 
@@ -110,14 +115,23 @@ impl Document {
         self.tree = self.parser.parse(&self.text, None).unwrap();
     }
 
-    pub async fn analyze(&mut self) -> Vec<Diagnostic> {
+    pub async fn analyze(
+        &mut self,
+        definitions: &LanguageDefinitions,
+        settings: &Settings,
+    ) -> Vec<Diagnostic> {
+        self.update_model();
+
+        // Analyze document and publish diagnostics
+        self.analyze_document(definitions, settings).await
+    }
+
+    /// Rebuilds the abstract syntax tree and semantic model from the current syntax tree.
+    pub fn update_model(&mut self) {
         self.ast = Ast::from_ts_tree(&self.text, &self.tree);
 
         self.semantic_model = SemanticModel::new();
         self.semantic_model.parse(&self.text, &self.ast);
-
-        // Analyze document and publish diagnostics
-        self.analyze_document().await
     }
 
     pub fn position_to_point(&self, position: &tower_lsp_server::ls_types::Position) -> Point {
@@ -229,6 +243,14 @@ impl Document {
     }
 }
 
+/// Parses `file:///test.asm` with the text and builds its semantic model.
+#[cfg(test)]
+pub fn test_document(text: &str) -> Document {
+    let mut doc = Document::new("file:///test.asm".parse().unwrap(), 1, text.to_string());
+    doc.update_model();
+    doc
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,238 @@
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{LabelKind, OperandListItem, SyntaxNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::references::is_macro_end;
+use crate::semantic::{self, Section};
+use crate::server::Backend;
+
+impl Backend {
+    pub async fn handle_document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        log!("textDocument/documentSymbol");
+
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+
+        Ok(Some(DocumentSymbolResponse::Nested(document_symbols(&doc))))
+    }
+}
+
+/// Builds the document outline: sections at the top level, containing labels and macro
+/// definitions. Data directives are nested under the label they belong to.
+pub fn document_symbols(doc: &Document) -> Vec<DocumentSymbol> {
+    let text = &doc.text;
+    let items = &doc.ast.items;
+
+    let mut sections: Vec<DocumentSymbol> = Vec::new();
+    let mut current_section = Section::Text;
+    let mut label: Option<DocumentSymbol> = None;
+
+    let mut index = 0;
+    while index < items.len() {
+        let statement = &items[index];
+        let range = doc.ts_range_to_ls(&statement.range());
+
+        if let SyntaxNode::Directive(node) = statement {
+            let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+            if let Some(section) = semantic::parse_section(mnemonic) {
+                close_label(&mut sections, &mut label);
+                current_section = section;
+                sections.push(create_symbol(
+                    mnemonic,
+                    None,
+                    SymbolKind::NAMESPACE,
+                    range,
+                    doc.ts_range_to_ls(&node.mnemonic.range),
+                ));
+                index += 1;
+                continue;
+            }
+        }
+
+        // Code before the first section directive belongs to the implicit .text section
+        if sections.is_empty() {
+            sections.push(create_symbol(
+                ".text",
+                None,
+                SymbolKind::NAMESPACE,
+                range,
+                range,
+            ));
+        }
+
+        match statement {
+            SyntaxNode::Label(node) if !matches!(node.kind, LabelKind::Numeric) => {
+                close_label(&mut sections, &mut label);
+                let kind = match current_section {
+                    Section::Text | Section::KText => SymbolKind::FUNCTION,
+                    _ => SymbolKind::VARIABLE,
+                };
+                label = Some(create_symbol(
+                    get_text_in_ts_range(text, node.name.range),
+                    None,
+                    kind,
+                    range,
+                    doc.ts_range_to_ls(&node.name.range),
+                ));
+            }
+            SyntaxNode::MacroDefinition(node) => {
+                close_label(&mut sections, &mut label);
+
+                // Skip the macro body; labels inside of it are local to the macro
+                let end = items[index..]
+                    .iter()
+                    .position(|statement| is_macro_end(text, statement))
+                    .map_or(items.len() - 1, |offset| index + offset);
+                let end_range = doc.ts_range_to_ls(&items[end].range());
+
+                let name = node
+                    .name
+                    .as_ref()
+                    .map_or("", |name| get_text_in_ts_range(text, name.range));
+                let selection_range = node
+                    .name
+                    .as_ref()
+                    .map_or(range, |name| doc.ts_range_to_ls(&name.range));
+                let parameters = node
+                    .parameters
+                    .iter()
+                    .map(|parameter| get_text_in_ts_range(text, parameter.name.range))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                let section = sections.last_mut().expect("section exists");
+                section.range.end = end_range.end;
+                push_child(
+                    section,
+                    create_symbol(
+                        name,
+                        Some(parameters),
+                        SymbolKind::METHOD,
+                        Range::new(range.start, end_range.end),
+                        selection_range,
+                    ),
+                );
+
+                index = end + 1;
+                continue;
+            }
+            SyntaxNode::Directive(node) => {
+                let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+                if semantic::is_data_directive(mnemonic) {
+                    let data = create_symbol(
+                        mnemonic,
+                        Some(operands_text(text, &node.operands)),
+                        SymbolKind::FIELD,
+                        range,
+                        doc.ts_range_to_ls(&node.mnemonic.range),
+                    );
+                    match &mut label {
+                        Some(label) => push_child(label, data),
+                        None => push_child(sections.last_mut().expect("section exists"), data),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        // Extend enclosing symbols up to the current statement
+        if let Some(label) = &mut label {
+            label.range.end = range.end;
+        }
+        if let Some(section) = sections.last_mut() {
+            section.range.end = range.end;
+        }
+
+        index += 1;
+    }
+
+    close_label(&mut sections, &mut label);
+    sections
+}
+
+fn close_label(sections: &mut [DocumentSymbol], label: &mut Option<DocumentSymbol>) {
+    if let (Some(label), Some(section)) = (label.take(), sections.last_mut()) {
+        push_child(section, label);
+    }
+}
+
+fn push_child(parent: &mut DocumentSymbol, child: DocumentSymbol) {
+    parent.children.get_or_insert_with(Vec::new).push(child);
+}
+
+fn operands_text(text: &str, operands: &[OperandListItem]) -> String {
+    let (Some(first), Some(last)) = (operands.first(), operands.last()) else {
+        return String::new();
+    };
+    let range_of = |operand: &OperandListItem| match operand {
+        OperandListItem::Operand(value) => value.range(),
+        OperandListItem::Comma(range) | OperandListItem::MissingOperand(range) => *range,
+    };
+    text[range_of(first).start_byte..range_of(last).end_byte].to_string()
+}
+
+#[allow(deprecated)]
+fn create_symbol(
+    name: &str,
+    detail: Option<String>,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name: name.to_string(),
+        detail: detail.filter(|detail| !detail.is_empty()),
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::test_document;
+
+    #[test]
+    fn outline_hierarchy() {
+        let doc = test_document(
+            ".data\nmsg: .asciiz \"hi\"\nnums: .word 1, 2\n.text\nmain:\n  li $v0, 10\n  syscall\n.macro exit\n  li $v0, 10\n.end_macro\nhelper:\n  jr $ra\n",
+        );
+
+        let symbols = document_symbols(&doc);
+        let names = |symbols: &Option<Vec<DocumentSymbol>>| {
+            symbols
+                .iter()
+                .flatten()
+                .map(|symbol| symbol.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(&Some(symbols.clone())), vec![".data", ".text"]);
+        assert_eq!(names(&symbols[0].children), vec!["msg", "nums"]);
+        assert_eq!(names(&symbols[1].children), vec!["main", "exit", "helper"]);
+
+        let nums = &symbols[0].children.as_ref().unwrap()[1];
+        assert_eq!(nums.kind, SymbolKind::VARIABLE);
+        assert_eq!(names(&nums.children), vec![".word"]);
+        assert_eq!(
+            nums.children.as_ref().unwrap()[0].detail.as_deref(),
+            Some("1, 2")
+        );
+        assert_eq!(
+            symbols[1].children.as_ref().unwrap()[0].kind,
+            SymbolKind::FUNCTION
+        );
+    }
+}
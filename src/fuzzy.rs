@@ -0,0 +1,143 @@
+//! Fuzzy matching of identifiers.
+
+/// Scores how well `candidate` matches `query` as a case-insensitive subsequence.
+///
+/// Returns `None` if the query characters do not all appear in order. Higher scores are
+/// better matches: exact substrings, consecutive characters, matches at word starts (after
+/// `_` or `.`) and matches at the very beginning of the candidate are rewarded, long
+/// candidates are penalized.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let candidate_chars: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for query_char in query.chars() {
+        let lowercase = query_char.to_ascii_lowercase();
+
+        let offset = candidate_chars[position..]
+            .iter()
+            .position(|c| c.to_ascii_lowercase() == lowercase)?;
+        let index = position + offset;
+
+        score += 1;
+        if index == 0 {
+            score += 8;
+        } else if matches!(candidate_chars[index - 1], '_' | '.' | '$') {
+            score += 5;
+        }
+        if previous_match.is_some_and(|previous| previous + 1 == index) {
+            score += 4;
+        }
+        if candidate_chars[index] == query_char {
+            score += 1;
+        }
+
+        previous_match = Some(index);
+        position = index + 1;
+    }
+
+    // Characters are matched greedily, so reward contiguous occurrences separately
+    if candidate
+        .to_ascii_lowercase()
+        .contains(&query.to_ascii_lowercase())
+    {
+        score += 4 * query.len() as i64;
+    }
+
+    Some(score * 16 - candidate_chars.len() as i64)
+}
+
+/// Returns the number of single character insertions, deletions, substitutions and
+/// transpositions of adjacent characters needed to turn `a` into `b`, ignoring ASCII case.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+    // distances[i][j] is the distance between the first i characters of a and j of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j - 1] + cost)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// Picks the candidate closest to a misspelled name for a "did you mean" suggestion.
+///
+/// Candidates further away than a third of the name's length are not considered. Ties are
+/// broken in favor of the longest common prefix.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let common_prefix = |candidate: &str| {
+        name.chars()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+            .count()
+    };
+
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let distance = edit_distance(name, candidate);
+            ((distance, usize::MAX - common_prefix(candidate)), candidate)
+        })
+        .filter(|((distance, _), _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequences() {
+        assert!(fuzzy_score("prt", "print_int").is_some());
+        assert!(fuzzy_score("PI", "print_int").is_some());
+        assert!(fuzzy_score("tnirp", "print_int").is_none());
+    }
+
+    #[test]
+    fn prefers_prefixes_and_word_starts() {
+        let prefix = fuzzy_score("loop", "loop_end").unwrap();
+        let inner = fuzzy_score("loop", "inner_loop_end").unwrap();
+        let scattered = fuzzy_score("loop", "long_operand_p").unwrap();
+        assert!(prefix > inner);
+        assert!(inner > scattered);
+    }
+
+    #[test]
+    fn prefers_exact_case() {
+        assert!(fuzzy_score("Main", "Main").unwrap() > fuzzy_score("Main", "main").unwrap());
+        assert!(fuzzy_score("main", "main").unwrap() > fuzzy_score("main", "Main").unwrap());
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        assert_eq!(edit_distance("addd", "add"), 1);
+        assert_eq!(edit_distance("ADDU", "addiu"), 1);
+        assert_eq!(edit_distance("wrod", "word"), 1);
+        assert_eq!(suggest("addd", ["add", "addi", "and"]), Some("add"));
+        assert_eq!(suggest("$t10", ["$10", "$t0", "$t1"]), Some("$t1"));
+        assert_eq!(suggest("foo", ["add", "addi"]), None);
+    }
+}
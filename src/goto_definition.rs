@@ -1,19 +1,11 @@
-use serde::de::value;
 use tower_lsp_server::jsonrpc;
-use tower_lsp_server::ls_types::request::{GotoDeclarationParams, GotoDeclarationResponse};
 use tower_lsp_server::ls_types::*;
-use tower_lsp_server::{Client, LanguageServer, LspService, Server};
-use tree_sitter::{InputEdit, Point, Query, QueryCursor};
 
-use streaming_iterator::StreamingIterator;
-
-use crate::document;
-use crate::lang::LanguageDefinitions;
-use crate::lang::{Directive, Instruction, Registers};
+use crate::references::{find_definition, symbol_at};
 use crate::server::Backend;
 
 impl Backend {
-    // Goto definition: searches for word below cursor among all labels
+    // Goto definition: looks up the label, constant or macro below cursor in the semantic model
     pub async fn handle_goto_definition(
         &self,
         params: GotoDefinitionParams,
@@ -24,65 +16,25 @@ impl Backend {
             text_document,
         } = params.text_document_position_params;
 
-        // Retrieve current content and tree
+        // Retrieve current document
         let doc_arc = self
             .documents
             .get(&text_document.uri)
             .ok_or(jsonrpc::Error::invalid_request())?;
-
         let doc = doc_arc.read().await;
-        let text = &doc.text;
-        let tree = &doc.tree;
-
-        // Determine node below cursor and fetch the label name
-        let point = doc.position_to_point(&position);
-        let cursor_label_name = tree
-            .root_node()
-            .descendant_for_point_range(
-                point,
-                Point {
-                    row: point.row,
-                    column: point.column + 1,
-                },
-            )
-            .ok_or(jsonrpc::Error::invalid_request())?
-            .utf8_text(text.as_bytes())
-            .unwrap_or_default();
-
-        // Generate query to find labels
-        let query = Query::new(
-            &tree_sitter_mips::LANGUAGE.into(),
-            r#"
-            (label) @label
-            (macro_label) @label
-            (numeric_label) @label
-            "#,
-        )
-        .expect("Error compiling query");
-
-        // Execute the query
-        let mut query_cursor = QueryCursor::new();
-        let mut matches = query_cursor.matches(&query, tree.root_node(), text.as_bytes());
-
-        // Iterate over the matches
-        while let Some(m) = matches.next() {
-            if let Some(capture) = m.captures.first() {
-                let node = capture.node;
-                let label_text = &text[node.start_byte()..node.end_byte() - 1];
-
-                if label_text == cursor_label_name {
-                    return Ok(Some(GotoDefinitionResponse::Scalar(Location {
-                        uri: text_document.uri,
-                        range: Range {
-                            start: doc.point_to_position(&node.start_position()),
-                            end: doc.point_to_position(&node.end_position()),
-                        },
-                    })));
-                }
-            }
-        }
 
-        // Return None; no definition found
-        Ok(None)
+        // Determine symbol below cursor
+        let byte = doc.position_to_byte(&position);
+        let Some((target, _)) = symbol_at(&doc, byte) else {
+            return Ok(None);
+        };
+
+        // Return None if no definition was found
+        Ok(find_definition(&doc, &target).map(|range| {
+            GotoDefinitionResponse::Scalar(Location {
+                uri: text_document.uri,
+                range: doc.ts_range_to_ls(&range),
+            })
+        }))
     }
 }
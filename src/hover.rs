@@ -4,11 +4,23 @@ use tower_lsp_server::ls_types::*;
 use tower_lsp_server::{Client, LanguageServer, LspService, Server};
 use tree_sitter::{InputEdit, Point, Query, QueryCursor};
 
-use crate::document;
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::{self, utils::get_text_in_ts_range, Document};
 use crate::lang::{
     Directive, Directives, Instruction, Instructions, LanguageDefinitions, Registers,
 };
+use crate::references::{symbol_at, SymbolTarget};
+use crate::semantic::cfg::Cfg;
+use crate::semantic::constant_definition_name;
+use crate::semantic::encode::Encoder;
+use crate::semantic::eval::{format_value, Evaluator};
+use crate::semantic::expand::Expander;
+use crate::semantic::frame::Frame;
+use crate::semantic::layout::Layout;
+use crate::semantic::liveness::LocationSet;
+use crate::semantic::syscall::syscall_sites;
 use crate::server::Backend;
+use crate::settings::Settings;
 
 impl Backend {
     pub async fn handle_hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
@@ -47,15 +59,32 @@ impl Backend {
         let kind = cursor_node.kind();
         let cursor_node_text = cursor_node.utf8_text(text.as_bytes()).unwrap_or_default();
 
-        let definitions = self.definitions.read().await;
+        let (settings, definitions) = self.document_settings(&doc.uri).await;
 
         let hover = match kind {
-            "mnemonic" => hover_instruction(&definitions.instructions, cursor_node_text),
+            "mnemonic" => {
+                let byte = doc.position_to_byte(&position);
+                hover_syscall(&doc, &definitions, &settings, byte).or_else(|| {
+                    let mut hover = hover_instruction(&definitions.instructions, cursor_node_text)?;
+                    let machine_code = expansion(&doc, &definitions, &settings, byte)
+                        .or_else(|| encoding(&doc, &definitions, &settings, byte));
+                    if let (Some(machine_code), HoverContents::Markup(content)) =
+                        (machine_code, &mut hover.contents)
+                    {
+                        content.value = format!("{}\n\n{}", machine_code, content.value);
+                    }
+                    Some(hover)
+                })
+            }
             "macro_mnemonic" | "numeric_mnemonic" | "string_mnemonic" | "control_mnemonic" => {
                 hover_directive(&definitions.directives, cursor_node_text)
             }
             "register" => hover_register(&definitions.registers, cursor_node_text),
-            _ => None,
+            _ => {
+                let byte = doc.position_to_byte(&position);
+                hover_value(&doc, &settings, byte)
+                    .or_else(|| hover_label(&doc, &definitions, &settings, byte))
+            }
         };
 
         Ok(hover)
@@ -111,3 +140,364 @@ fn hover_register(registers: &Registers, text: &str) -> Option<Hover> {
         })
     }
 }
+
+/// Shows the value of the constant or constant expression below the cursor.
+fn hover_value(doc: &Document, settings: &Settings, byte: usize) -> Option<Hover> {
+    let text = &doc.text;
+    let contains = |range: &tree_sitter::Range| range.start_byte <= byte && byte <= range.end_byte;
+    let (index, statement) = doc
+        .ast
+        .items
+        .iter()
+        .enumerate()
+        .find(|(_, statement)| contains(&statement.range()))?;
+    let evaluator = Evaluator::new(doc, settings);
+
+    let (operands, defined_name) = match statement {
+        SyntaxNode::Instruction(node) => (&node.operands, None),
+        SyntaxNode::Directive(node) => (
+            &node.operands,
+            constant_definition_name(
+                get_text_in_ts_range(text, node.mnemonic.range),
+                &node.operands,
+            ),
+        ),
+        SyntaxNode::Assignment(node) => (&node.operands, Some(node.name.range)),
+        _ => return None,
+    };
+
+    // The name of a constant definition
+    if let Some(name) = defined_name.filter(|range| contains(range)) {
+        let name = get_text_in_ts_range(text, name);
+        let value = evaluator.constant(name, index + 1)?.ok()?;
+        return Some(value_hover(name, value, evaluator.bits()));
+    }
+
+    let operand = operands.iter().find_map(|item| match item {
+        OperandListItem::Operand(value) if contains(&value.range()) => Some(value),
+        _ => None,
+    })?;
+
+    // A constant referenced in the operand
+    let mut symbol = None;
+    operand.visit(&mut |value| {
+        if let ValueNode::Symbol { range } = value {
+            if contains(range) {
+                symbol = Some(*range);
+            }
+        }
+    });
+    if let Some(range) = symbol {
+        let name = get_text_in_ts_range(text, range);
+        if let Some(value) = evaluator.constant(name, index) {
+            return Some(value_hover(name, value.ok()?, evaluator.bits()));
+        }
+    }
+
+    // A whole expression; plain literals are shown as written
+    if !matches!(
+        operand,
+        ValueNode::BinaryExpression { .. }
+            | ValueNode::UnaryExpression { .. }
+            | ValueNode::ParenthesizedExpression { .. }
+    ) {
+        return None;
+    }
+    let value = evaluator.evaluate(operand, index).ok()?;
+    Some(value_hover(
+        get_text_in_ts_range(text, operand.range()),
+        value,
+        evaluator.bits(),
+    ))
+}
+
+/// Shows the address of the label below the cursor and the stack frame of the routine it starts.
+fn hover_label(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    byte: usize,
+) -> Option<Hover> {
+    let (target, _) = symbol_at(doc, byte)?;
+    let SymbolTarget::Label(name) = target else {
+        return None;
+    };
+    let label = doc.semantic_model.labels.get(&name)?;
+    let layout = Layout::compute(doc, definitions, settings);
+    let address = layout.label(&name)?;
+
+    let mut value = if layout.absolute {
+        format!(
+            "`{}`: address `{:#010x}` in `{}`",
+            name,
+            address,
+            label.section.name()
+        )
+    } else {
+        format!(
+            "`{}`: offset `{:#x}` in `{}`",
+            name,
+            address,
+            label.section.name()
+        )
+    };
+    if let Some(frame) = routine_frame(doc, settings, &name) {
+        value.push_str(&frame);
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Index of the instruction whose statement contains the byte.
+fn instruction_at(doc: &Document, byte: usize) -> Option<usize> {
+    doc.semantic_model
+        .instructions
+        .iter()
+        .position(|instruction| {
+            let range = doc.ast.items[instruction.statement_index].range();
+            range.start_byte <= byte && byte < range.end_byte
+        })
+}
+
+/// Describes the machine instructions of the pseudo-instruction below the cursor.
+fn expansion(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    byte: usize,
+) -> Option<String> {
+    let instruction = instruction_at(doc, byte)?;
+    let layout = Layout::compute(doc, definitions, settings);
+    let expansion = Expander::new(doc, definitions, settings, &layout).expand(instruction)?;
+    Some(format!(
+        "**Expands to:**\n```asm\n{}\n```",
+        expansion.instructions.join("\n")
+    ))
+}
+
+/// Describes the machine code of the instruction below the cursor with its bit fields.
+fn encoding(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    byte: usize,
+) -> Option<String> {
+    let instruction = instruction_at(doc, byte)?;
+    let layout = Layout::compute(doc, definitions, settings);
+    let encoding = Encoder::new(doc, definitions, settings, &layout).encode(instruction)?;
+    let names: Vec<&str> = encoding
+        .fields
+        .iter()
+        .map(|field| field.name.as_str())
+        .collect();
+    let bits: Vec<String> = encoding
+        .fields
+        .iter()
+        .map(|field| format!("`{}`", field.bits))
+        .collect();
+    Some(format!(
+        "**Encoding:** `0x{:08x}`\n\n| {} |\n|{}\n| {} |",
+        encoding.word,
+        names.join(" | "),
+        "---|".repeat(names.len()),
+        bits.join(" | ")
+    ))
+}
+
+/// Shows the MARS/SPIM service requested by the `syscall` below the cursor.
+fn hover_syscall(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    byte: usize,
+) -> Option<Hover> {
+    if definitions.syscalls.is_empty() {
+        return None;
+    }
+    let instruction = instruction_at(doc, byte)?;
+    let cfg = Cfg::build(doc, settings);
+    let service = syscall_sites(doc, settings, &cfg)
+        .into_iter()
+        .find(|site| site.instruction == instruction)?
+        .service?;
+    let syscall = definitions.syscalls.get(&service)?;
+
+    let mut value = format!(
+        "`{}` (syscall {})\n\n{}",
+        syscall.name, service, syscall.description
+    );
+    for (title, registers) in [
+        ("Arguments", &syscall.arguments),
+        ("Results", &syscall.results),
+    ] {
+        if registers.is_empty() {
+            continue;
+        }
+        value.push_str(&format!("\n\n**{}:**", title));
+        for register in registers {
+            value.push_str(&format!(
+                "\n- `{}`: {}",
+                register.register, register.description
+            ));
+        }
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Describes the frame size and saved registers of the routine starting at a label.
+fn routine_frame(doc: &Document, settings: &Settings, name: &str) -> Option<String> {
+    let cfg = Cfg::build(doc, settings);
+    let routine = cfg
+        .routines
+        .iter()
+        .position(|routine| routine.name.as_deref() == Some(name))?;
+    let frame = Frame::compute(doc, settings, &cfg, routine, LocationSet::default());
+    if frame.size == 0 && frame.slots.is_empty() {
+        return None;
+    }
+
+    let mut text = format!("\n\nFrame: {} bytes", frame.size);
+    for slot in &frame.slots {
+        text.push_str(&format!(
+            "\n- `{}` at `{}($sp)`",
+            slot.location, slot.offset
+        ));
+    }
+    Some(text)
+}
+
+fn value_hover(expression: &str, value: i64, bits: u32) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("`{}` = `{}`", expression, format_value(value, bits)),
+        }),
+        range: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::test_document;
+    use crate::lang::Dialect;
+    use std::str::FromStr;
+
+    fn analyzed_document(text: &str, settings: &Settings) -> (Document, LanguageDefinitions) {
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(settings);
+        (test_document(text), definitions)
+    }
+
+    fn hover_text(text: &str, needle: &str) -> Option<String> {
+        let doc = test_document(text);
+        let hover = hover_value(&doc, &Settings::default(), text.find(needle)?)?;
+        match hover.contents {
+            HoverContents::Markup(content) => Some(content.value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn shows_values_of_constants_and_expressions() {
+        let text = ".eqv N, 11\n.data\n.word (4*N+3)&~3, 5\n";
+        assert_eq!(hover_text(text, "N,").as_deref(), Some("`N` = `0xb (11)`"));
+        assert_eq!(hover_text(text, "N+").as_deref(), Some("`N` = `0xb (11)`"));
+        assert_eq!(
+            hover_text(text, "&~").as_deref(),
+            Some("`(4*N+3)&~3` = `0x2c (44)`")
+        );
+        assert_eq!(hover_text(text, "5"), None);
+    }
+
+    #[test]
+    fn shows_label_addresses() {
+        let text = ".data\nmsg: .asciiz \"hi\"\nvalue: .word 1\n.text\nmain: lw $t0, value\n";
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let (doc, definitions) = analyzed_document(text, &settings);
+
+        let hover =
+            hover_label(&doc, &definitions, &settings, text.rfind("value").unwrap()).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert_eq!(content.value, "`value`: address `0x10010004` in `.data`");
+    }
+    #[test]
+    fn shows_stack_frames_of_routines() {
+        let text = ".text\nmain:\n  jal f\n  li $v0, 10\n  syscall\nf:\n  addiu $sp, $sp, -8\n  sw $ra, 4($sp)\n  sw $s0, 0($sp)\n  lw $s0, 0($sp)\n  lw $ra, 4($sp)\n  addiu $sp, $sp, 8\n  jr $ra\n";
+        let settings = Settings::default();
+        let (doc, definitions) = analyzed_document(text, &settings);
+
+        let hover = hover_label(&doc, &definitions, &settings, text.find("f:").unwrap()).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert_eq!(
+            content.value,
+            "`f`: offset `0xc` in `.text`\n\nFrame: 8 bytes\n- `$s0` at `0($sp)`\n- `$ra` at `4($sp)`"
+        );
+
+        let hover = hover_label(&doc, &definitions, &settings, text.find("main").unwrap()).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert_eq!(content.value, "`main`: offset `0x0` in `.text`");
+    }
+    #[test]
+    fn shows_syscall_services() {
+        let text = ".text\nmain:\n  li $v0, 8\n  syscall\n";
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Spim;
+        let (doc, definitions) = analyzed_document(text, &settings);
+
+        let hover =
+            hover_syscall(&doc, &definitions, &settings, text.find("syscall").unwrap()).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert_eq!(
+            content.value,
+            "`read_string` (syscall 8)\n\nRead a line into a buffer and terminate it with null\n\n**Arguments:**\n- `$a0`: address of the buffer\n- `$a1`: maximum number of characters to read, including the null terminator"
+        );
+    }
+    #[test]
+    fn shows_pseudo_instruction_expansions() {
+        let text = ".text\nmain:\n  li $t0, 0x12345678\n  addu $t1, $t0, $t0\n";
+        let settings = Settings::default();
+        let (doc, definitions) = analyzed_document(text, &settings);
+
+        assert_eq!(
+            expansion(&doc, &definitions, &settings, text.find("li").unwrap()).as_deref(),
+            Some("**Expands to:**\n```asm\nlui $t0, 0x1234\nori $t0, $t0, 0x5678\n```")
+        );
+        assert_eq!(
+            expansion(&doc, &definitions, &settings, text.find("addu").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn shows_instruction_encodings() {
+        let text = ".text\nmain:\n  addu $v0, $a0, $a1\n";
+        let settings = Settings::default();
+        let (doc, definitions) = analyzed_document(text, &settings);
+
+        assert_eq!(
+            encoding(&doc, &definitions, &settings, text.find("addu").unwrap()).as_deref(),
+            Some("**Encoding:** `0x00851021`\n\n| opcode | rs | rt | rd | shamt | funct |\n|---|---|---|---|---|---|\n| `000000` | `00100` | `00101` | `00010` | `00000` | `100001` |")
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::SyntaxNode;
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::semantic::cfg::Cfg;
+use crate::semantic::encode::Encoder;
+use crate::semantic::expand::Expander;
+use crate::semantic::layout::Layout;
+use crate::semantic::liveness::Liveness;
+use crate::server::Backend;
+use crate::settings::Settings;
+
+impl Backend {
+    pub async fn handle_inlay_hint(
+        &self,
+        params: InlayHintParams,
+    ) -> jsonrpc::Result<Option<Vec<InlayHint>>> {
+        log!("textDocument/inlayHint");
+
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+        let (settings, definitions) = self.document_settings(&doc.uri).await;
+
+        let hints = inlay_hints(&doc, &definitions, &settings)
+            .into_iter()
+            .filter(|hint| params.range.start <= hint.position && hint.position <= params.range.end)
+            .collect();
+        Ok(Some(hints))
+    }
+}
+
+pub fn inlay_hints(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+) -> Vec<InlayHint> {
+    let mut hints = live_register_hints(doc, definitions, settings);
+    if settings.expansion_hints {
+        hints.extend(expansion_hints(doc, definitions, settings));
+    }
+    if settings.encoding_hints {
+        hints.extend(encoding_hints(doc, definitions, settings));
+    }
+    hints
+}
+
+/// Shows the registers live at labels that start a block, limited to the registers the routine
+/// uses.
+fn live_register_hints(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+) -> Vec<InlayHint> {
+    let cfg = Cfg::build(doc, settings);
+    let liveness = Liveness::compute(doc, definitions, &cfg);
+    let model = &doc.semantic_model;
+
+    let mut hints = Vec::new();
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        let (Some(name), Some(&first)) = (block.labels.last(), block.instructions.first()) else {
+            continue;
+        };
+        let live = liveness.live_in[index].intersection(liveness.used[block.routine]);
+        if live.is_empty() {
+            continue;
+        }
+
+        // The labels of the block are in front of its first instruction
+        let statement = model.instructions[first].statement_index;
+        let label = doc.ast.items[..statement]
+            .iter()
+            .rev()
+            .find_map(|item| match item {
+                SyntaxNode::Label(node)
+                    if get_text_in_ts_range(&doc.text, node.name.range) == name =>
+                {
+                    Some(node)
+                }
+                _ => None,
+            });
+        let Some(label) = label else {
+            continue;
+        };
+
+        let registers: Vec<String> = live.iter().map(|location| location.to_string()).collect();
+        hints.push(InlayHint {
+            position: doc.ts_range_to_ls(&label.range).end,
+            label: InlayHintLabel::String(format!("live: {}", registers.join(", "))),
+            kind: None,
+            text_edits: None,
+            tooltip: Some(InlayHintTooltip::String(
+                "Registers read before they are written on some path from here".to_string(),
+            )),
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        });
+    }
+    hints
+}
+
+/// Shows the machine instructions of pseudo-instructions after them.
+fn expansion_hints(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+) -> Vec<InlayHint> {
+    let layout = Layout::compute(doc, definitions, settings);
+    let expander = Expander::new(doc, definitions, settings, &layout);
+
+    let mut hints = Vec::new();
+    for (index, instruction) in doc.semantic_model.instructions.iter().enumerate() {
+        let Some(expansion) = expander.expand(index) else {
+            continue;
+        };
+        let range = doc.ast.items[instruction.statement_index].range();
+        hints.push(InlayHint {
+            position: doc.ts_range_to_ls(&range).end,
+            label: InlayHintLabel::String(format!("→ {}", expansion.instructions.join("; "))),
+            kind: None,
+            text_edits: None,
+            tooltip: Some(InlayHintTooltip::String(
+                "Machine instructions of the pseudo-instruction".to_string(),
+            )),
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        });
+    }
+    hints
+}
+
+/// Shows the machine code of machine instructions after them.
+fn encoding_hints(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+) -> Vec<InlayHint> {
+    let layout = Layout::compute(doc, definitions, settings);
+    let encoder = Encoder::new(doc, definitions, settings, &layout);
+
+    let mut hints = Vec::new();
+    for (index, instruction) in doc.semantic_model.instructions.iter().enumerate() {
+        let Some(encoding) = encoder.encode(index) else {
+            continue;
+        };
+        let range = doc.ast.items[instruction.statement_index].range();
+        hints.push(InlayHint {
+            position: doc.ts_range_to_ls(&range).end,
+            label: InlayHintLabel::String(format!("0x{:08x}", encoding.word)),
+            kind: None,
+            text_edits: None,
+            tooltip: Some(InlayHintTooltip::String(
+                "Machine code of the instruction".to_string(),
+            )),
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        });
+    }
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn hints(doc: &Document, settings: &Settings) -> Vec<(u32, u32, String)> {
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(settings);
+        inlay_hints(doc, &definitions, settings)
+            .into_iter()
+            .map(|hint| {
+                let InlayHintLabel::String(label) = hint.label else {
+                    panic!("expected string label");
+                };
+                (hint.position.line, hint.position.character, label)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shows_live_registers_at_labels() {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            ".text\nmain:\n  li $a0, 5\n  jal sum\n  li $v0, 10\n  syscall\nsum:\n  move $v0, $zero\nloop:\n  addu $v0, $v0, $a0\n  addi $a0, $a0, -1\n  bgtz $a0, loop\n  jr $ra\n".to_string(),
+        );
+        doc.update_model();
+
+        assert_eq!(
+            hints(&doc, &Settings::default()),
+            vec![
+                (6, 4, "live: $a0, $ra".to_string()),
+                (8, 5, "live: $v0, $a0, $ra".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shows_expansions_if_enabled() {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            ".text\n  li $t0, 0x10000\n  nop\n".to_string(),
+        );
+        doc.update_model();
+
+        let mut settings = Settings::default();
+        assert!(hints(&doc, &settings).is_empty());
+        settings.expansion_hints = true;
+        assert_eq!(
+            hints(&doc, &settings),
+            vec![(1, 17, "→ lui $t0, 1".to_string())]
+        );
+    }
+
+    #[test]
+    fn shows_encodings_if_enabled() {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            ".text\n  li $t0, 0x10000\n  nop\n".to_string(),
+        );
+        doc.update_model();
+
+        let mut settings = Settings::default();
+        assert!(hints(&doc, &settings).is_empty());
+        settings.encoding_hints = true;
+        assert_eq!(
+            hints(&doc, &settings),
+            vec![(2, 5, "0x00000000".to_string())]
+        );
+    }
+}
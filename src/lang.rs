@@ -4,9 +4,9 @@ use std::collections::HashMap;
 use std::fmt;
 
 use crate::settings::{Settings, SettingsError};
-use crate::version::{Version, VersionLabel};
+use crate::version::{Extension, Version, VersionLabel};
 
-#[derive(Debug, Eq, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, Hash, Serialize, Deserialize, PartialEq)]
 pub enum Dialect {
     Gas,
     Mars,
@@ -50,6 +50,39 @@ impl Dialect {
     }
 }
 
+/// Calling convention the code is checked against.
+#[derive(Debug, Clone, Copy, Eq, Serialize, Deserialize, PartialEq)]
+pub enum Abi {
+    /// No calling convention is enforced, as in MARS and SPIM exercises
+    None,
+    O32,
+    N32,
+    N64,
+}
+
+impl fmt::Display for Abi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Abi::None => write!(f, "none"),
+            Abi::O32 => write!(f, "o32"),
+            Abi::N32 => write!(f, "n32"),
+            Abi::N64 => write!(f, "n64"),
+        }
+    }
+}
+
+impl Abi {
+    pub fn parse(s: &str) -> Result<Self, SettingsError> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Abi::None),
+            "o32" => Ok(Abi::O32),
+            "n32" => Ok(Abi::N32),
+            "n64" => Ok(Abi::N64),
+            _ => Err(SettingsError::UnknownAbi(s.into())),
+        }
+    }
+}
+
 // impl ISA {
 //     pub fn parse(s: &str) -> Result<Self, SettingsError> {
 //         match s {
@@ -77,12 +110,21 @@ pub struct RawInstructionVariant {
     pub deprecated: Option<String>,
     pub mips32: bool,
     pub pseudo: bool,
+    pub encoding: Option<String>,
+    pub encoding_r6: Option<String>,
 }
 
 impl TryFrom<RawInstructionVariant> for InstructionVariant {
     type Error = SettingsError;
 
     fn try_from(raw: RawInstructionVariant) -> Result<Self, Self::Error> {
+        let (extension, introduced) = match raw.introduced.as_deref() {
+            Some(label) => match Extension::parse_introduced(label) {
+                Some((extension, release)) => (Some(extension), release),
+                None => (None, VersionLabel::parse(label)?),
+            },
+            None => (None, VersionLabel::Mips1),
+        };
         Ok(Self {
             description: raw.description,
             operands: raw.operands,
@@ -91,12 +133,8 @@ impl TryFrom<RawInstructionVariant> for InstructionVariant {
                 .into_iter()
                 .map(|d| Dialect::parse(&d))
                 .collect::<Result<_, _>>()?,
-            introduced: raw
-                .introduced
-                .as_deref()
-                .map(VersionLabel::parse)
-                .transpose()?
-                .unwrap_or(VersionLabel::Mips1),
+            introduced,
+            extension,
             deprecated: raw
                 .deprecated
                 .as_deref()
@@ -104,6 +142,8 @@ impl TryFrom<RawInstructionVariant> for InstructionVariant {
                 .transpose()?,
             mips32: raw.mips32,
             pseudo: raw.pseudo,
+            encoding: raw.encoding,
+            encoding_r6: raw.encoding_r6,
         })
     }
 }
@@ -166,14 +206,133 @@ pub struct Registers {
     pub float: HashMap<String, String>,
 }
 
+/// Conventional names of the general purpose registers, indexed by register number.
+pub const GPR_NAMES: [&str; 32] = [
+    "$zero", "$at", "$v0", "$v1", "$a0", "$a1", "$a2", "$a3", "$t0", "$t1", "$t2", "$t3", "$t4",
+    "$t5", "$t6", "$t7", "$s0", "$s1", "$s2", "$s3", "$s4", "$s5", "$s6", "$s7", "$t8", "$t9",
+    "$k0", "$k1", "$gp", "$sp", "$fp", "$ra",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+    /// General purpose register `$0` to `$31`
+    Gpr(u8),
+    /// Floating point register `$f0` to `$f31`
+    Fpr(u8),
+}
+
+impl Register {
+    /// Parses numeric (`$8`), conventional (`$t0`) and floating point (`$f2`) register names.
+    pub fn parse(name: &str) -> Option<Register> {
+        if let Some(index) = GPR_NAMES.iter().position(|n| *n == name) {
+            return Some(Register::Gpr(index as u8));
+        }
+
+        let digits = name.strip_prefix('$')?;
+        let (digits, float) = match digits.strip_prefix('f') {
+            Some(digits) => (digits, true),
+            None => (digits, false),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let number = digits.parse::<u8>().ok().filter(|n| *n < 32)?;
+        if float {
+            Some(Register::Fpr(number))
+        } else {
+            Some(Register::Gpr(number))
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::Gpr(n) => write!(f, "{}", GPR_NAMES[*n as usize]),
+            Register::Fpr(n) => write!(f, "$f{}", n),
+        }
+    }
+}
+
+/*
+ *! Syscalls
+ */
+/// Register passed to or returned by a system call service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyscallRegister {
+    pub register: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawSyscall {
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<SyscallRegister>,
+    pub results: Vec<SyscallRegister>,
+    pub dialects: Vec<String>,
+}
+
+/// Service of the MARS/SPIM `syscall` instruction, selected by the number in `$v0`.
+#[derive(Debug)]
+pub struct Syscall {
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<SyscallRegister>,
+    pub results: Vec<SyscallRegister>,
+}
+
+pub type RawSyscalls = HashMap<i64, RawSyscall>;
+pub type Syscalls = HashMap<i64, Syscall>;
+
 /*
  *! Language Definitions
  */
+/// Reason why a known instruction or directive is not available with the current settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unavailability {
+    /// Only supported by the listed dialects
+    Dialect(Vec<Dialect>),
+    /// Introduced in a later ISA version
+    Introduced(VersionLabel),
+    /// Removed in the configured ISA version or one of its ancestors
+    Removed(VersionLabel),
+    /// Part of an application-specific extension that is not enabled
+    Extension(Extension),
+}
+
+impl fmt::Display for Unavailability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unavailability::Dialect(dialects) => {
+                let names = dialects
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "only supported by {}", names)
+            }
+            Unavailability::Introduced(label) => write!(f, "requires {}", label),
+            Unavailability::Removed(label) => write!(f, "removed in {}", label),
+            Unavailability::Extension(extension) => {
+                write!(f, "requires the {} extension", extension)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct LanguageDefinitions {
     pub instructions: Instructions,
     pub directives: Directives,
     pub registers: Registers,
+    /// System call services of MARS or SPIM, empty for other dialects
+    pub syscalls: Syscalls,
+    /// Instructions that exist, but not in the configured dialect or version
+    pub unavailable_instructions: HashMap<String, Unavailability>,
+    /// Directives that exist, but not in the configured dialect
+    pub unavailable_directives: HashMap<String, Unavailability>,
 }
 
 impl LanguageDefinitions {
@@ -186,21 +345,51 @@ impl LanguageDefinitions {
             instructions,
             directives,
             registers,
+            syscalls: HashMap::new(),
+            unavailable_instructions: HashMap::new(),
+            unavailable_directives: HashMap::new(),
         }
     }
 
     pub fn parse(&mut self, settings: &Settings) {
         let raw_instructions = load_instructions();
-        self.instructions = process_instructions(raw_instructions, settings)
-            .expect("Failed to process instruction definitions");
+        self.unavailable_instructions.clear();
+        self.instructions = process_instructions(
+            raw_instructions,
+            settings,
+            &mut self.unavailable_instructions,
+        )
+        .expect("Failed to process instruction definitions");
 
         let raw_directives = load_directives();
-        self.directives = process_directives(raw_directives, settings)
-            .expect("Failed to process directive definitions");
+        self.unavailable_directives.clear();
+        self.directives =
+            process_directives(raw_directives, settings, &mut self.unavailable_directives)
+                .expect("Failed to process directive definitions");
+
+        self.syscalls = process_syscalls(load_syscalls(), settings)
+            .expect("Failed to process syscall definitions");
+    }
+
+    /// Returns true if the name is a known register, e.g. `$t0`, `$8` or `$f2`.
+    pub fn is_register(&self, name: &str) -> bool {
+        self.registers.common.contains_key(name)
+            || self.registers.numeric.contains_key(name)
+            || self.registers.float.contains_key(name)
+    }
+
+    /// All known register names.
+    pub fn register_names(&self) -> impl Iterator<Item = &str> {
+        self.registers
+            .common
+            .keys()
+            .chain(self.registers.numeric.keys())
+            .chain(self.registers.float.keys())
+            .map(|name| name.as_str())
     }
 }
 
-fn load_instructions() -> RawInstructions {
+pub(crate) fn load_instructions() -> RawInstructions {
     let json = include_str!("../resources/instructions.json");
     serde_json::from_str(json).expect("JSON parsing failed")
 }
@@ -215,50 +404,64 @@ fn load_registers() -> Registers {
     serde_json::from_str(json).expect("JSON parsing failed")
 }
 
+fn load_syscalls() -> RawSyscalls {
+    let json = include_str!("../resources/syscalls.json");
+    serde_json::from_str(json).expect("JSON parsing failed")
+}
+
 fn process_instructions(
     raw: RawInstructions,
     settings: &Settings,
+    unavailable: &mut HashMap<String, Unavailability>,
 ) -> Result<Instructions, SettingsError> {
-    let instructions = raw
-        .into_iter()
-        .filter_map(|(mnemonic, raw_instruction)| {
-            let mut variants = Vec::new();
-
-            for raw_variants in raw_instruction.variants {
-                let v = match InstructionVariant::try_from(raw_variants) {
-                    Ok(v) => v,
-                    Err(_) => return None,
-                };
-
-                if !settings.allows_dialects(&v.dialects) {
-                    continue;
-                }
-
-                if settings.allows_any_version(&v) || v.is_valid_for(settings.version) {
-                    variants.push(v);
+    let mut instructions = HashMap::new();
+    for (mnemonic, raw_instruction) in raw {
+        let mut variants = Vec::new();
+        let mut dialects = Vec::new();
+        let mut version_reason = None;
+
+        for raw_variants in raw_instruction.variants {
+            // A variant that does not parse is a mistake in the definitions, not a missing
+            // instruction
+            let v = InstructionVariant::try_from(raw_variants)?;
+
+            if !settings.allows_dialects(&v.dialects) {
+                for dialect in v.dialects {
+                    if !dialects.contains(&dialect) {
+                        dialects.push(dialect);
+                    }
                 }
+                continue;
             }
 
-            if variants.is_empty() {
-                return None;
+            if settings.allows_any_version(&v) || v.is_valid_for(settings) {
+                variants.push(v);
+            } else if version_reason.is_none() {
+                version_reason = Some(v.unavailability_for(settings));
             }
+        }
 
-            let info = build_instruction_hover_info(
-                &mnemonic,
-                &raw_instruction.description,
-                &variants,
-                settings,
-            );
+        if variants.is_empty() {
+            let reason = version_reason.unwrap_or(Unavailability::Dialect(dialects));
+            unavailable.insert(mnemonic, reason);
+            continue;
+        }
 
-            Some((
-                mnemonic,
-                Instruction {
-                    variants,
-                    description: info,
-                },
-            ))
-        })
-        .collect();
+        let info = build_instruction_hover_info(
+            &mnemonic,
+            &raw_instruction.description,
+            &variants,
+            settings,
+        );
+
+        instructions.insert(
+            mnemonic,
+            Instruction {
+                variants,
+                description: info,
+            },
+        );
+    }
 
     Ok(instructions)
 }
@@ -266,34 +469,58 @@ fn process_instructions(
 fn process_directives(
     raw: RawDirectives,
     settings: &Settings,
+    unavailable: &mut HashMap<String, Unavailability>,
 ) -> Result<Directives, SettingsError> {
-    let directives = raw
-        .into_iter()
-        .filter_map(|(mnemonic, raw_directive)| {
-            let d = match Directive::try_from(raw_directive) {
-                Ok(d) => d,
-                Err(_) => {
-                    return None;
-                }
-            };
+    let mut directives = HashMap::new();
+    for (mnemonic, raw_directive) in raw {
+        let d = Directive::try_from(raw_directive)?;
 
-            if !settings.allows_dialects(&d.dialects) {
-                return None;
-            }
+        if !settings.allows_dialects(&d.dialects) {
+            unavailable.insert(mnemonic, Unavailability::Dialect(d.dialects));
+            continue;
+        }
 
-            Some((
-                mnemonic,
-                Directive {
-                    description: d.description,
-                    dialects: d.dialects,
-                },
-            ))
-        })
-        .collect();
+        directives.insert(
+            mnemonic,
+            Directive {
+                description: d.description,
+                dialects: d.dialects,
+            },
+        );
+    }
 
     Ok(directives)
 }
 
+/// Keeps the services of the configured dialect. Only MARS and SPIM define them.
+fn process_syscalls(raw: RawSyscalls, settings: &Settings) -> Result<Syscalls, SettingsError> {
+    if !matches!(settings.dialect, Dialect::Mars | Dialect::Spim) {
+        return Ok(HashMap::new());
+    }
+
+    let mut syscalls = HashMap::new();
+    for (number, raw_syscall) in raw {
+        let dialects = raw_syscall
+            .dialects
+            .iter()
+            .map(|d| Dialect::parse(d))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !dialects.contains(&settings.dialect) {
+            continue;
+        }
+        syscalls.insert(
+            number,
+            Syscall {
+                name: raw_syscall.name,
+                description: raw_syscall.description,
+                arguments: raw_syscall.arguments,
+                results: raw_syscall.results,
+            },
+        );
+    }
+    Ok(syscalls)
+}
+
 /// Returns a human-readable description for an instruction with all variants.
 ///
 ///  TODO:
@@ -336,6 +563,9 @@ fn build_instruction_hover_info(
         if let Some(d) = v.deprecated {
             part.push_str(format!("Deprecated: {:?} | ", d).as_str());
         }
+        if let Some(e) = v.extension {
+            part.push_str(format!("Extension: {} | ", e).as_str());
+        }
 
         let dialects_str = {
             let mut dialects: Vec<String> = v.dialects.iter().map(|d| d.to_string()).collect();
@@ -368,17 +598,31 @@ pub struct InstructionVariant {
     pub operands: Vec<String>,
     pub dialects: Vec<Dialect>,
     pub introduced: VersionLabel,
+    /// extension the variant belongs to, which must be enabled in addition to the release
+    pub extension: Option<Extension>,
     pub deprecated: Option<VersionLabel>,
     pub mips32: bool,
     pub pseudo: bool,
+    /// bit fields of the machine instruction, e.g. `opcode=000000 rs:5 rt:5 ...`
+    pub encoding: Option<String>,
+    /// bit fields in Release 6, if it encodes the instruction differently
+    pub encoding_r6: Option<String>,
 }
 
 impl InstructionVariant {
-    fn is_valid_for(&self, target: &Version) -> bool {
+    fn is_valid_for(&self, settings: &Settings) -> bool {
+        let target = settings.version;
         if !target.has_ancestor(self.introduced) {
             return false;
         }
 
+        if self
+            .extension
+            .is_some_and(|extension| !settings.extensions.contains(&extension))
+        {
+            return false;
+        }
+
         if let Some(d) = self.deprecated {
             if target.has_ancestor(d) {
                 return false;
@@ -387,4 +631,15 @@ impl InstructionVariant {
 
         true
     }
+
+    fn unavailability_for(&self, settings: &Settings) -> Unavailability {
+        let target = settings.version;
+        match (self.deprecated, self.extension) {
+            (Some(d), _) if target.has_ancestor(d) => Unavailability::Removed(d),
+            (_, Some(extension)) if target.has_ancestor(self.introduced) => {
+                Unavailability::Extension(extension)
+            }
+            _ => Unavailability::Introduced(self.introduced),
+        }
+    }
 }
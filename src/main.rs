@@ -10,17 +10,38 @@ mod settings;
 mod version;
 
 mod ast;
+mod check;
+mod cli;
+mod config;
+mod dap;
 mod document;
 mod lang;
+mod operands;
 mod semantic;
+mod simulator;
 
 mod completion;
+mod control_flow_graph;
 mod diagnostic;
+mod document_symbol;
+mod fuzzy;
 mod goto_definition;
 mod hover;
+mod inlay_hint;
+mod memory_layout;
+mod references;
+mod rename;
+mod run;
+mod semantic_tokens;
+mod signature_help;
+mod workspace;
 
 #[tokio::main]
 async fn main() {
+    if let Some(code) = cli::main().await {
+        std::process::exit(code);
+    }
+
     log!("Starting MIPS language server");
     log_init!();
     crate::server::serve().await
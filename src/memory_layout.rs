@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::semantic::layout::Layout;
+use crate::server::Backend;
+use crate::settings::Settings;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryLayoutParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Response of the `mipsls/memoryLayout` request.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryLayout {
+    /// whether addresses are absolute (MARS, SPIM) or offsets within their section
+    pub absolute: bool,
+    pub sections: Vec<SectionUsage>,
+    pub labels: Vec<LabelAddress>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SectionUsage {
+    pub name: String,
+    pub start: i64,
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct LabelAddress {
+    pub name: String,
+    pub section: String,
+    pub address: i64,
+}
+
+impl Backend {
+    pub async fn handle_memory_layout(
+        &self,
+        params: MemoryLayoutParams,
+    ) -> jsonrpc::Result<MemoryLayout> {
+        log!("mipsls/memoryLayout");
+
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+        let (settings, definitions) = self.document_settings(&doc.uri).await;
+
+        Ok(memory_layout(&doc, &definitions, &settings))
+    }
+}
+
+/// Lists the size of every section and the addresses of all labels in source order.
+pub fn memory_layout(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+) -> MemoryLayout {
+    let layout = Layout::compute(doc, definitions, settings);
+
+    let mut labels: Vec<_> = doc
+        .semantic_model
+        .labels
+        .iter()
+        .filter_map(|(name, label)| {
+            Some((
+                label.statement_index,
+                LabelAddress {
+                    name: name.to_string(),
+                    section: label.section.name().to_string(),
+                    address: layout.label(name)?,
+                },
+            ))
+        })
+        .collect();
+    labels.sort_by_key(|(index, _)| *index);
+
+    MemoryLayout {
+        absolute: layout.absolute,
+        sections: layout
+            .sections
+            .iter()
+            .map(|section| SectionUsage {
+                name: section.section.name().to_string(),
+                start: section.start,
+                size: section.size,
+            })
+            .collect(),
+        labels: labels.into_iter().map(|(_, label)| label).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::Dialect;
+    use std::str::FromStr;
+
+    #[test]
+    fn lists_sections_and_labels() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Spim;
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            ".data\narray: .space 40\n.text\nmain:\n  la $a0, array\n  jr $ra\n".to_string(),
+        );
+        doc.update_model();
+
+        let layout = memory_layout(&doc, &definitions, &settings);
+        assert!(layout.absolute);
+        assert_eq!(
+            layout.sections,
+            vec![
+                SectionUsage {
+                    name: ".text".to_string(),
+                    start: 0x0040_0000,
+                    size: 12
+                },
+                SectionUsage {
+                    name: ".data".to_string(),
+                    start: 0x1000_0000,
+                    size: 40
+                },
+            ]
+        );
+        let names: Vec<_> = layout
+            .labels
+            .iter()
+            .map(|label| (label.name.as_str(), label.address))
+            .collect();
+        assert_eq!(names, vec![("array", 0x1000_0000), ("main", 0x0040_0000)]);
+    }
+}
@@ -0,0 +1,285 @@
+//! Classification of the operand specifiers used in `resources/instructions.json`
+//! and matching of parsed operand values against them.
+
+use crate::ast::{OperandListItem, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::lang::{InstructionVariant, Register};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    /// General purpose register: `rd`, `rs`, `rt`
+    Gpr,
+    /// Floating point register: `fd`, `fs`, `ft`, `fr`
+    Fpr,
+    /// Coprocessor or performance counter register: `cop_reg`, `ct`, `perf_reg`
+    CoprocessorRegister,
+    /// Integer constant with the given width: `imm16_s`, `sa5`, `code`, ...
+    Immediate { bits: u8, signed: bool },
+    /// Memory address: `offset(base)`, `(base)`, `index(base)`
+    Memory,
+    /// Absolute code or data address: `label`, `target`
+    Address,
+    /// Branch target, written as label or as constant offset: `offset`
+    BranchOffset,
+}
+
+impl OperandKind {
+    pub fn parse(spec: &str) -> OperandKind {
+        match spec {
+            "rd" | "rs" | "rt" => OperandKind::Gpr,
+            "fd" | "fs" | "ft" | "fr" => OperandKind::Fpr,
+            "cop_reg" | "ct" | "perf_reg" => OperandKind::CoprocessorRegister,
+            "offset(base)" | "(base)" | "index(base)" => OperandKind::Memory,
+            "label" | "target" => OperandKind::Address,
+            "offset" => OperandKind::BranchOffset,
+            "sa" | "sa5" => OperandKind::Immediate {
+                bits: 5,
+                signed: false,
+            },
+            "sa12" => OperandKind::Immediate {
+                bits: 12,
+                signed: false,
+            },
+            "cc" | "sel" => OperandKind::Immediate {
+                bits: 3,
+                signed: false,
+            },
+            "code" => OperandKind::Immediate {
+                bits: 20,
+                signed: false,
+            },
+            _ => parse_sized_immediate(spec).unwrap_or(OperandKind::Immediate {
+                bits: 32,
+                signed: true,
+            }),
+        }
+    }
+
+    /// Returns the inclusive range of values an immediate operand can encode.
+    ///
+    /// 32-bit immediates accept both signed and unsigned values, since the assembler only
+    /// loads the bit pattern.
+    pub fn value_range(&self) -> Option<(i64, i64)> {
+        let OperandKind::Immediate { bits, signed } = *self else {
+            return None;
+        };
+        let range = match (bits, signed) {
+            (32.., _) => (i32::MIN as i64, u32::MAX as i64),
+            (bits, true) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+            (bits, false) => (0, (1 << bits) - 1),
+        };
+        Some(range)
+    }
+
+    /// Human-readable description used in diagnostics.
+    pub fn description(&self) -> String {
+        match self {
+            OperandKind::Gpr => "general purpose register".to_string(),
+            OperandKind::Fpr => "floating point register".to_string(),
+            OperandKind::CoprocessorRegister => "coprocessor register".to_string(),
+            OperandKind::Immediate { bits, signed } => format!(
+                "{}-bit {} immediate",
+                bits,
+                if *signed { "signed" } else { "unsigned" }
+            ),
+            OperandKind::Memory => "memory address `offset(base)`".to_string(),
+            OperandKind::Address => "label or address".to_string(),
+            OperandKind::BranchOffset => "branch target".to_string(),
+        }
+    }
+}
+
+/// Parses specifiers like `imm16_s`, `hint5`, `pos6` or `code16`.
+fn parse_sized_immediate(spec: &str) -> Option<OperandKind> {
+    let (spec, signed) = match spec.strip_suffix("_s") {
+        Some(spec) => (spec, true),
+        None => (spec.strip_suffix("_u").unwrap_or(spec), false),
+    };
+    let digits_start = spec.find(|c: char| c.is_ascii_digit())?;
+    let bits = spec[digits_start..].parse().ok()?;
+    Some(OperandKind::Immediate { bits, signed })
+}
+
+/// Coarse category of a written operand value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueClass {
+    Gpr,
+    Fpr,
+    /// register with a name that is not known
+    Register,
+    Integer,
+    Float,
+    /// expression that refers to a label or constant symbol
+    Symbol,
+    Memory,
+    String,
+    /// value that cannot be classified before macro expansion
+    Unknown,
+}
+
+impl ValueClass {
+    pub fn of(text: &str, value: &ValueNode) -> ValueClass {
+        match value {
+            ValueNode::Register { range } => {
+                match Register::parse(get_text_in_ts_range(text, *range)) {
+                    Some(Register::Gpr(_)) => ValueClass::Gpr,
+                    Some(Register::Fpr(_)) => ValueClass::Fpr,
+                    None => ValueClass::Register,
+                }
+            }
+            ValueNode::Decimal { .. }
+            | ValueNode::Hexadecimal { .. }
+            | ValueNode::Octal { .. }
+            | ValueNode::Binary { .. }
+            | ValueNode::Char { .. } => ValueClass::Integer,
+            ValueNode::Float { .. } => ValueClass::Float,
+            ValueNode::Symbol { .. } | ValueNode::NumericLabelReference { .. } => {
+                ValueClass::Symbol
+            }
+            ValueNode::String { .. } => ValueClass::String,
+            ValueNode::BinaryExpression { left, right, .. } => {
+                merge_expression_classes(ValueClass::of(text, left), ValueClass::of(text, right))
+            }
+            ValueNode::UnaryExpression { body, .. } => ValueClass::of(text, body),
+            ValueNode::ParenthesizedExpression { head, body, .. } => {
+                let registers_in_body = body.iter().any(|item| {
+                    matches!(item, OperandListItem::Operand(value)
+                        if matches!(ValueClass::of(text, value), ValueClass::Gpr | ValueClass::Register))
+                });
+                if registers_in_body {
+                    return ValueClass::Memory;
+                }
+                let inner = body.iter().find_map(|item| match item {
+                    OperandListItem::Operand(value) => Some(ValueClass::of(text, value)),
+                    _ => None,
+                });
+                match (head, inner) {
+                    (Some(_), _) => ValueClass::Unknown,
+                    (None, Some(inner)) => inner,
+                    (None, None) => ValueClass::Unknown,
+                }
+            }
+            ValueNode::MacroVariable { .. } => ValueClass::Unknown,
+            ValueNode::OptionFlag { .. }
+            | ValueNode::ElfTypeTag { .. }
+            | ValueNode::MalformedValue { .. } => ValueClass::Unknown,
+        }
+    }
+
+    /// Human-readable description used in diagnostics.
+    pub fn description(&self) -> &'static str {
+        match self {
+            ValueClass::Gpr => "general purpose register",
+            ValueClass::Fpr => "floating point register",
+            ValueClass::Register => "register",
+            ValueClass::Integer => "integer",
+            ValueClass::Float => "floating point number",
+            ValueClass::Symbol => "label or symbol",
+            ValueClass::Memory => "memory address",
+            ValueClass::String => "string",
+            ValueClass::Unknown => "value",
+        }
+    }
+}
+
+fn merge_expression_classes(left: ValueClass, right: ValueClass) -> ValueClass {
+    match (left, right) {
+        (ValueClass::Unknown, _) | (_, ValueClass::Unknown) => ValueClass::Unknown,
+        (ValueClass::Symbol, _) | (_, ValueClass::Symbol) => ValueClass::Symbol,
+        (ValueClass::Integer, ValueClass::Integer) => ValueClass::Integer,
+        _ => ValueClass::Unknown,
+    }
+}
+
+/// Returns true if a value of the given class may be written for an operand of `kind`.
+///
+/// Symbols are accepted wherever a constant is expected, since they may name a constant
+/// defined with `.eqv` or `.set`. Unclassifiable values, e.g. macro variables, always match.
+pub fn accepts(kind: OperandKind, class: ValueClass) -> bool {
+    match class {
+        ValueClass::Unknown => true,
+        ValueClass::Gpr => matches!(kind, OperandKind::Gpr | OperandKind::CoprocessorRegister),
+        ValueClass::Fpr => matches!(kind, OperandKind::Fpr | OperandKind::CoprocessorRegister),
+        ValueClass::Register => matches!(
+            kind,
+            OperandKind::Gpr | OperandKind::Fpr | OperandKind::CoprocessorRegister
+        ),
+        ValueClass::Integer => matches!(
+            kind,
+            OperandKind::Immediate { .. }
+                | OperandKind::Address
+                | OperandKind::BranchOffset
+                | OperandKind::Memory
+        ),
+        ValueClass::Symbol => matches!(
+            kind,
+            OperandKind::Immediate { .. }
+                | OperandKind::Address
+                | OperandKind::BranchOffset
+                | OperandKind::Memory
+        ),
+        ValueClass::Memory => matches!(kind, OperandKind::Memory),
+        ValueClass::Float | ValueClass::String => false,
+    }
+}
+
+/// Returns true if the variant accepts the classes of the given operands as a prefix of its
+/// operand list, i.e. the operands might still be completed to match this variant.
+pub fn variant_accepts_prefix(variant: &InstructionVariant, classes: &[ValueClass]) -> bool {
+    classes.len() <= variant.operands.len()
+        && classes
+            .iter()
+            .zip(&variant.operands)
+            .all(|(class, spec)| accepts(OperandKind::parse(spec), *class))
+}
+
+/// Returns true if the variant accepts exactly the given operands.
+pub fn variant_accepts(variant: &InstructionVariant, classes: &[ValueClass]) -> bool {
+    classes.len() == variant.operands.len() && variant_accepts_prefix(variant, classes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_operand_specifiers() {
+        assert_eq!(OperandKind::parse("rt"), OperandKind::Gpr);
+        assert_eq!(OperandKind::parse("ft"), OperandKind::Fpr);
+        assert_eq!(
+            OperandKind::parse("imm16_s"),
+            OperandKind::Immediate {
+                bits: 16,
+                signed: true
+            }
+        );
+        assert_eq!(
+            OperandKind::parse("hint5"),
+            OperandKind::Immediate {
+                bits: 5,
+                signed: false
+            }
+        );
+        assert_eq!(OperandKind::parse("offset(base)"), OperandKind::Memory);
+        assert_eq!(OperandKind::parse("offset"), OperandKind::BranchOffset);
+    }
+
+    #[test]
+    fn immediate_ranges() {
+        let range = |spec| OperandKind::parse(spec).value_range();
+        assert_eq!(range("imm16_s"), Some((-32768, 32767)));
+        assert_eq!(range("imm16_u"), Some((0, 65535)));
+        assert_eq!(range("sa5"), Some((0, 31)));
+        assert_eq!(range("sa6"), Some((0, 63)));
+        assert_eq!(range("rt"), None);
+    }
+
+    #[test]
+    fn accepts_matching_classes() {
+        assert!(accepts(OperandKind::Gpr, ValueClass::Gpr));
+        assert!(!accepts(OperandKind::Gpr, ValueClass::Fpr));
+        assert!(!accepts(OperandKind::Gpr, ValueClass::Symbol));
+        assert!(accepts(OperandKind::Memory, ValueClass::Symbol));
+        assert!(accepts(OperandKind::Fpr, ValueClass::Unknown));
+    }
+}
@@ -0,0 +1,452 @@
+use smol_str::{SmolStr, ToSmolStr};
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{visit_operands, LabelKind, OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::semantic;
+use crate::server::Backend;
+
+/// A named entity that can be defined once and referenced from elsewhere in the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolTarget {
+    Label(SmolStr),
+    /// Numeric label like `1:` defined at `statement_index`, referenced as `1b` or `1f`.
+    NumericLabel {
+        statement_index: usize,
+    },
+    /// Symbol defined with `.eqv`, `.set`, `.equ`, `.equiv` or `=`.
+    Constant(SmolStr),
+    Macro(SmolStr),
+    /// Parameter of the macro defined at `macro_index`, stored without its `%` or `\` sigil.
+    MacroParameter {
+        macro_index: usize,
+        name: SmolStr,
+    },
+}
+
+impl Backend {
+    pub async fn handle_references(
+        &self,
+        params: ReferenceParams,
+    ) -> jsonrpc::Result<Option<Vec<Location>>> {
+        log!("textDocument/references");
+
+        let TextDocumentPositionParams {
+            position,
+            text_document,
+        } = params.text_document_position;
+
+        let doc_arc = self
+            .documents
+            .get(&text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+
+        let byte = doc.position_to_byte(&position);
+        let Some((target, _)) = symbol_at(&doc, byte) else {
+            return Ok(None);
+        };
+
+        let locations = find_references(&doc, &target, params.context.include_declaration)
+            .iter()
+            .map(|range| Location {
+                uri: text_document.uri.clone(),
+                range: doc.ts_range_to_ls(range),
+            })
+            .collect();
+
+        Ok(Some(locations))
+    }
+}
+
+/// Returns the symbol below the given byte offset together with the range of its name.
+pub fn symbol_at(doc: &Document, byte: usize) -> Option<(SymbolTarget, tree_sitter::Range)> {
+    let text = &doc.text;
+    let contains = |range: &tree_sitter::Range| range.start_byte <= byte && byte <= range.end_byte;
+
+    let (statement_index, statement) = doc
+        .ast
+        .items
+        .iter()
+        .enumerate()
+        .find(|(_, statement)| contains(&statement.range()))?;
+
+    let operand_symbol_at = |operands: &[OperandListItem]| {
+        let mut found = None;
+        visit_operands(operands, &mut |value| match value {
+            ValueNode::Symbol { range } if contains(range) => {
+                let name = get_text_in_ts_range(text, *range).to_smolstr();
+                found = Some((symbol_target(doc, name), *range));
+            }
+            ValueNode::NumericLabelReference { range } if contains(range) => {
+                let reference = get_text_in_ts_range(text, *range);
+                found = doc
+                    .semantic_model
+                    .resolve_numeric_label(statement_index, reference)
+                    .map(|statement_index| {
+                        (SymbolTarget::NumericLabel { statement_index }, *range)
+                    });
+            }
+            ValueNode::MacroVariable { range } if contains(range) => {
+                if let Some(macro_index) = enclosing_macro(doc, statement_index) {
+                    let name = strip_sigil(get_text_in_ts_range(text, *range)).to_smolstr();
+                    found = Some((SymbolTarget::MacroParameter { macro_index, name }, *range));
+                }
+            }
+            _ => {}
+        });
+        found
+    };
+
+    match statement {
+        SyntaxNode::Label(node) => {
+            if !contains(&node.name.range) {
+                return None;
+            }
+            if matches!(node.kind, LabelKind::Numeric) {
+                return Some((
+                    SymbolTarget::NumericLabel { statement_index },
+                    node.name.range,
+                ));
+            }
+            let name = get_text_in_ts_range(text, node.name.range).to_smolstr();
+            Some((SymbolTarget::Label(name), node.name.range))
+        }
+        SyntaxNode::MacroDefinition(node) => {
+            if let Some(parameter) = node
+                .parameters
+                .iter()
+                .find(|parameter| contains(&parameter.name.range))
+            {
+                let name = strip_sigil(get_text_in_ts_range(text, parameter.name.range));
+                return Some((
+                    SymbolTarget::MacroParameter {
+                        macro_index: statement_index,
+                        name: name.to_smolstr(),
+                    },
+                    parameter.name.range,
+                ));
+            }
+
+            let name_range = node.name.as_ref()?.range;
+            if !contains(&name_range) {
+                return None;
+            }
+            let name = get_text_in_ts_range(text, name_range).to_smolstr();
+            Some((SymbolTarget::Macro(name), name_range))
+        }
+        SyntaxNode::Instruction(node) => mnemonic_at(doc, &node.mnemonic.range, byte)
+            .or_else(|| operand_symbol_at(&node.operands)),
+        SyntaxNode::MacroInvocation(node) => mnemonic_at(doc, &node.mnemonic.range, byte)
+            .or_else(|| operand_symbol_at(&node.operands)),
+        SyntaxNode::Directive(node) => operand_symbol_at(&node.operands),
+        SyntaxNode::Assignment(node) if contains(&node.name.range) => {
+            let name = get_text_in_ts_range(text, node.name.range).to_smolstr();
+            Some((SymbolTarget::Constant(name), node.name.range))
+        }
+        SyntaxNode::Assignment(node) => operand_symbol_at(&node.operands),
+        SyntaxNode::Error(_) => None,
+    }
+}
+
+/// Labels take precedence over constants of the same name, as assemblers reject both.
+fn symbol_target(doc: &Document, name: SmolStr) -> SymbolTarget {
+    let model = &doc.semantic_model;
+    if !model.labels.contains_key(&name) && model.constants.contains_key(&name) {
+        SymbolTarget::Constant(name)
+    } else {
+        SymbolTarget::Label(name)
+    }
+}
+
+/// Returns the range of the definition of a symbol, if it is defined in the document.
+pub fn find_definition(doc: &Document, target: &SymbolTarget) -> Option<tree_sitter::Range> {
+    match target {
+        SymbolTarget::Label(name) => {
+            let label = doc.semantic_model.labels.get(name)?;
+            match doc.ast.items.get(label.statement_index)? {
+                SyntaxNode::Label(node) => Some(node.name.range),
+                _ => None,
+            }
+        }
+        SymbolTarget::NumericLabel { statement_index } => {
+            match doc.ast.items.get(*statement_index)? {
+                SyntaxNode::Label(node) => Some(node.name.range),
+                _ => None,
+            }
+        }
+        // `.set` may redefine a symbol; the first definition is its declaration
+        SymbolTarget::Constant(name) => doc
+            .semantic_model
+            .constants
+            .get(name)?
+            .first()
+            .map(|constant| constant.name_range),
+        SymbolTarget::Macro(name) => {
+            let macro_def = doc.semantic_model.macros.get(name)?;
+            match doc.ast.items.get(macro_def.statement_index)? {
+                SyntaxNode::MacroDefinition(node) => node.name.as_ref().map(|name| name.range),
+                _ => None,
+            }
+        }
+        SymbolTarget::MacroParameter { macro_index, name } => {
+            match doc.ast.items.get(*macro_index)? {
+                SyntaxNode::MacroDefinition(node) => node
+                    .parameters
+                    .iter()
+                    .find(|parameter| {
+                        strip_sigil(get_text_in_ts_range(&doc.text, parameter.name.range)) == name
+                    })
+                    .map(|parameter| parameter.name.range),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Returns the name ranges of all uses of a symbol, optionally including its definition.
+pub fn find_references(
+    doc: &Document,
+    target: &SymbolTarget,
+    include_declaration: bool,
+) -> Vec<tree_sitter::Range> {
+    let mut ranges = Vec::new();
+
+    if include_declaration {
+        if let Some(range) = find_definition(doc, target) {
+            ranges.push(range);
+        }
+    }
+
+    match target {
+        SymbolTarget::Label(name) => {
+            if let Some(references) = doc.semantic_model.symbol_references.get(name) {
+                ranges.extend(references.iter().copied());
+            }
+        }
+        SymbolTarget::NumericLabel { statement_index } => {
+            for (index, statement) in doc.ast.items.iter().enumerate() {
+                let operands = match statement {
+                    SyntaxNode::Instruction(node) => &node.operands,
+                    SyntaxNode::MacroInvocation(node) => &node.operands,
+                    SyntaxNode::Directive(node) => &node.operands,
+                    SyntaxNode::Assignment(node) => &node.operands,
+                    _ => continue,
+                };
+                visit_operands(operands, &mut |value| {
+                    if let ValueNode::NumericLabelReference { range } = value {
+                        let reference = get_text_in_ts_range(&doc.text, *range);
+                        let resolved = doc.semantic_model.resolve_numeric_label(index, reference);
+                        if resolved == Some(*statement_index) {
+                            ranges.push(*range);
+                        }
+                    }
+                });
+            }
+        }
+        SymbolTarget::Constant(name) => {
+            let definitions: Vec<tree_sitter::Range> = doc
+                .semantic_model
+                .constants
+                .get(name)
+                .map(|constants| constants.iter().map(|c| c.name_range).collect())
+                .unwrap_or_default();
+            // The first definition is already added as the declaration
+            if include_declaration {
+                ranges.extend(definitions.iter().skip(1).copied());
+            }
+            if let Some(references) = doc.semantic_model.symbol_references.get(name) {
+                ranges.extend(
+                    references
+                        .iter()
+                        .filter(|range| !definitions.contains(range))
+                        .copied(),
+                );
+            }
+        }
+        SymbolTarget::Macro(name) => {
+            for instruction in &doc.semantic_model.instructions {
+                let mnemonic_range = match doc.ast.items.get(instruction.statement_index) {
+                    Some(SyntaxNode::Instruction(node)) => node.mnemonic.range,
+                    Some(SyntaxNode::MacroInvocation(node)) => node.mnemonic.range,
+                    _ => continue,
+                };
+                if get_text_in_ts_range(&doc.text, mnemonic_range) == name.as_str() {
+                    ranges.push(mnemonic_range);
+                }
+            }
+        }
+        SymbolTarget::MacroParameter { macro_index, name } => {
+            let body = doc.ast.items[*macro_index + 1..]
+                .iter()
+                .take_while(|statement| !is_macro_end(&doc.text, statement));
+
+            for statement in body {
+                let operands = match statement {
+                    SyntaxNode::Instruction(node) => &node.operands,
+                    SyntaxNode::MacroInvocation(node) => &node.operands,
+                    SyntaxNode::Directive(node) => &node.operands,
+                    _ => continue,
+                };
+                visit_operands(operands, &mut |value| {
+                    if let ValueNode::MacroVariable { range } = value {
+                        if strip_sigil(get_text_in_ts_range(&doc.text, *range)) == name {
+                            ranges.push(*range);
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    ranges.sort_by_key(|range| range.start_byte);
+    ranges
+}
+
+fn mnemonic_at(
+    doc: &Document,
+    range: &tree_sitter::Range,
+    byte: usize,
+) -> Option<(SymbolTarget, tree_sitter::Range)> {
+    if byte < range.start_byte || byte > range.end_byte {
+        return None;
+    }
+    let name = get_text_in_ts_range(&doc.text, *range).to_smolstr();
+    if !doc.semantic_model.macros.contains_key(&name) {
+        return None;
+    }
+    Some((SymbolTarget::Macro(name), *range))
+}
+
+/// Returns the index of the macro definition whose body contains the given statement.
+pub fn enclosing_macro(doc: &Document, statement_index: usize) -> Option<usize> {
+    for index in (0..statement_index).rev() {
+        match &doc.ast.items[index] {
+            SyntaxNode::MacroDefinition(_) => return Some(index),
+            statement if is_macro_end(&doc.text, statement) => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+pub fn is_macro_end(text: &str, statement: &SyntaxNode) -> bool {
+    match statement {
+        SyntaxNode::Directive(node) => {
+            semantic::is_macro_end(get_text_in_ts_range(text, node.mnemonic.range))
+        }
+        _ => false,
+    }
+}
+
+/// Strips the `%` (MARS) or `\` (GAS) prefix from a macro parameter or variable name.
+pub fn strip_sigil(name: &str) -> &str {
+    name.strip_prefix(['%', '\\']).unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::test_document;
+
+    fn texts<'a>(doc: &'a Document, ranges: &[tree_sitter::Range]) -> Vec<&'a str> {
+        ranges
+            .iter()
+            .map(|range| get_text_in_ts_range(&doc.text, *range))
+            .collect()
+    }
+
+    #[test]
+    fn label_references() {
+        let doc = test_document(
+            ".globl main\nmain:\n  la $t0, data+4\n  beq $t0, $zero, main\n  j main\ndata: .word main\n",
+        );
+
+        let (target, _) = symbol_at(&doc, doc.text.find("main:").unwrap()).unwrap();
+        assert_eq!(target, SymbolTarget::Label("main".into()));
+
+        let with_declaration = find_references(&doc, &target, true);
+        assert_eq!(with_declaration.len(), 5);
+        assert!(texts(&doc, &with_declaration).iter().all(|t| *t == "main"));
+
+        let without_declaration = find_references(&doc, &target, false);
+        assert_eq!(without_declaration.len(), 4);
+    }
+
+    #[test]
+    fn macro_references() {
+        let doc = test_document(
+            ".macro push(%reg)\n  sw %reg, 0($sp)\n.end_macro\n  push($t0)\n  push($t1)\n",
+        );
+
+        let (target, _) = symbol_at(&doc, doc.text.rfind("push").unwrap()).unwrap();
+        assert_eq!(target, SymbolTarget::Macro("push".into()));
+
+        let references = find_references(&doc, &target, true);
+        assert_eq!(references.len(), 3);
+        assert!(texts(&doc, &references).iter().all(|t| *t == "push"));
+    }
+
+    #[test]
+    fn macro_parameter_references() {
+        let doc = test_document(
+            ".macro add3 a, b\n  add \\a, \\a, \\b\n  add \\a, \\a, \\b\n.endm\n  add $t0, $t0, $t1\n",
+        );
+
+        let (target, _) = symbol_at(&doc, doc.text.find("a, b").unwrap()).unwrap();
+        assert_eq!(
+            target,
+            SymbolTarget::MacroParameter {
+                macro_index: 0,
+                name: "a".into()
+            }
+        );
+
+        let references = find_references(&doc, &target, true);
+        assert_eq!(
+            texts(&doc, &references),
+            vec!["a", "\\a", "\\a", "\\a", "\\a"]
+        );
+    }
+
+    #[test]
+    fn numeric_label_definitions() {
+        let text = "1:\n  beqz $t0, 1f\n  j 1b\n1:\n  b 1b\n";
+        let doc = test_document(text);
+
+        let (target, _) = symbol_at(&doc, text.find("1f").unwrap()).unwrap();
+        let definition = find_definition(&doc, &target).unwrap();
+        assert_eq!(definition.start_byte, text.rfind("1:").unwrap());
+        let references = find_references(&doc, &target, false);
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[1].start_byte, text.rfind("1b").unwrap());
+
+        let (target, _) = symbol_at(&doc, text.find("1b").unwrap()).unwrap();
+        assert_eq!(find_definition(&doc, &target).unwrap().start_byte, 0);
+        assert_eq!(find_references(&doc, &target, true).len(), 2);
+    }
+
+    #[test]
+    fn constant_definitions() {
+        let text = ".eqv SIZE, 8\nCOUNT = SIZE / 4\n  li $t0, SIZE\n  li $t1, COUNT\n";
+        let doc = test_document(text);
+
+        let (target, _) = symbol_at(&doc, text.rfind("SIZE").unwrap()).unwrap();
+        assert_eq!(target, SymbolTarget::Constant("SIZE".into()));
+        let definition = find_definition(&doc, &target).unwrap();
+        assert_eq!(definition.start_byte, text.find("SIZE").unwrap());
+        assert_eq!(find_references(&doc, &target, true).len(), 3);
+        assert_eq!(find_references(&doc, &target, false).len(), 2);
+
+        let (target, _) = symbol_at(&doc, text.rfind("COUNT").unwrap()).unwrap();
+        assert_eq!(target, SymbolTarget::Constant("COUNT".into()));
+        let definition = find_definition(&doc, &target).unwrap();
+        assert_eq!(definition.start_byte, text.find("COUNT").unwrap());
+        assert_eq!(
+            texts(&doc, &find_references(&doc, &target, true)),
+            vec!["COUNT", "COUNT"]
+        );
+    }
+}
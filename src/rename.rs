@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::SyntaxNode;
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::references::{find_references, strip_sigil, symbol_at, SymbolTarget};
+use crate::server::Backend;
+
+impl Backend {
+    pub async fn handle_prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<PrepareRenameResponse>> {
+        log!("textDocument/prepareRename");
+
+        let TextDocumentPositionParams {
+            position,
+            text_document,
+        } = params;
+
+        let doc_arc = self
+            .documents
+            .get(&text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+
+        let byte = doc.position_to_byte(&position);
+        let Some((target, range)) = symbol_at(&doc, byte) else {
+            return Ok(None);
+        };
+        // Numeric labels are referred to by their number and direction, not by a name
+        if matches!(target, SymbolTarget::NumericLabel { .. }) {
+            return Ok(None);
+        }
+
+        let range = strip_sigil_from_range(&doc.text, range);
+        Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+            range: doc.ts_range_to_ls(&range),
+            placeholder: get_text_in_ts_range(&doc.text, range).to_string(),
+        }))
+    }
+
+    pub async fn handle_rename(
+        &self,
+        params: RenameParams,
+    ) -> jsonrpc::Result<Option<WorkspaceEdit>> {
+        log!("textDocument/rename");
+
+        let TextDocumentPositionParams {
+            position,
+            text_document,
+        } = params.text_document_position;
+
+        let doc_arc = self
+            .documents
+            .get(&text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+
+        let byte = doc.position_to_byte(&position);
+        let Some((target, _)) = symbol_at(&doc, byte) else {
+            return Ok(None);
+        };
+
+        let (_, definitions) = self.document_settings(&doc.uri).await;
+        let new_name = strip_sigil(&params.new_name);
+        validate_new_name(&doc, &definitions, &target, new_name)
+            .map_err(jsonrpc::Error::invalid_params)?;
+
+        let edits = find_references(&doc, &target, true)
+            .iter()
+            .map(|range| TextEdit {
+                range: doc.ts_range_to_ls(&strip_sigil_from_range(&doc.text, *range)),
+                new_text: new_name.to_string(),
+            })
+            .collect();
+
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(text_document.uri, edits)])),
+            ..Default::default()
+        }))
+    }
+}
+
+/// Checks that `new_name` is a valid identifier that does not shadow another definition.
+fn validate_new_name(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    target: &SymbolTarget,
+    new_name: &str,
+) -> Result<(), String> {
+    if !is_valid_identifier(new_name) {
+        return Err(format!("`{}` is not a valid name", new_name));
+    }
+
+    match target {
+        SymbolTarget::NumericLabel { .. } => {
+            return Err("numeric labels cannot be renamed".to_string());
+        }
+        SymbolTarget::Label(name) | SymbolTarget::Constant(name) | SymbolTarget::Macro(name) => {
+            if name == new_name {
+                return Ok(());
+            }
+
+            let model = &doc.semantic_model;
+            if model.labels.contains_key(new_name)
+                || model.constants.contains_key(new_name)
+                || model.symbol_references.contains_key(new_name)
+            {
+                return Err(format!("a symbol named `{}` already exists", new_name));
+            }
+            if model.macros.contains_key(new_name) {
+                return Err(format!("a macro named `{}` already exists", new_name));
+            }
+            if definitions
+                .instructions
+                .contains_key(&new_name.to_lowercase())
+            {
+                return Err(format!("`{}` is an instruction mnemonic", new_name));
+            }
+        }
+        SymbolTarget::MacroParameter { macro_index, name } => {
+            if name == new_name {
+                return Ok(());
+            }
+
+            let Some(SyntaxNode::MacroDefinition(node)) = doc.ast.items.get(*macro_index) else {
+                return Err("macro definition not found".to_string());
+            };
+            let collides = node.parameters.iter().any(|parameter| {
+                strip_sigil(get_text_in_ts_range(&doc.text, parameter.name.range)) == new_name
+            });
+            if collides {
+                return Err(format!("a parameter named `{}` already exists", new_name));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    (first.is_ascii_alphabetic() || first == '_' || first == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+}
+
+/// Shrinks a range so that a leading macro variable sigil is excluded from edits.
+fn strip_sigil_from_range(text: &str, mut range: tree_sitter::Range) -> tree_sitter::Range {
+    let name = get_text_in_ts_range(text, range);
+    let sigil_len = name.len() - strip_sigil(name).len();
+    range.start_byte += sigil_len;
+    range.start_point.column += sigil_len;
+    range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::test_document;
+    use crate::settings::Settings;
+
+    fn definitions() -> LanguageDefinitions {
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&Settings::default());
+        definitions
+    }
+
+    #[test]
+    fn rejects_colliding_names() {
+        let doc = test_document("loop:\n  j end\nend:\n  j loop\n");
+        let definitions = definitions();
+        let target = SymbolTarget::Label("loop".into());
+
+        assert!(validate_new_name(&doc, &definitions, &target, "again").is_ok());
+        assert!(validate_new_name(&doc, &definitions, &target, "end").is_err());
+        assert!(validate_new_name(&doc, &definitions, &target, "addiu").is_err());
+        assert!(validate_new_name(&doc, &definitions, &target, "1abc").is_err());
+    }
+
+    #[test]
+    fn rejects_colliding_macro_parameters() {
+        let doc = test_document(".macro swap a, b\n  move \\a, \\b\n.endm\n");
+        let target = SymbolTarget::MacroParameter {
+            macro_index: 0,
+            name: "a".into(),
+        };
+
+        assert!(validate_new_name(&doc, &definitions(), &target, "x").is_ok());
+        assert!(validate_new_name(&doc, &definitions(), &target, "b").is_err());
+    }
+}
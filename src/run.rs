@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::document::Document;
+use crate::lang::{LanguageDefinitions, GPR_NAMES};
+use crate::server::Backend;
+use crate::settings::Settings;
+use crate::simulator::{BufferConsole, Machine, Simulator, Status};
+
+/// Instructions executed before a run is stopped, to end programs that never exit.
+pub const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+/// Upper limit for the steps a client may request, so a run cannot occupy a thread indefinitely.
+pub const MAX_STEPS: u64 = 100_000_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunParams {
+    pub text_document: TextDocumentIdentifier,
+    /// standard input of the program
+    #[serde(default)]
+    pub input: String,
+    pub max_steps: Option<u64>,
+}
+
+/// Response of the `mipsls/run` request.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RunResult {
+    /// standard output of the program
+    pub output: String,
+    /// exit code if the program ended normally
+    pub exit_code: Option<i32>,
+    pub error: Option<RunError>,
+    /// number of instructions executed
+    pub steps: u64,
+    /// general purpose registers followed by `hi`, `lo` and `pc`
+    pub registers: Vec<RegisterValue>,
+    pub float_registers: Vec<RegisterValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RunError {
+    pub message: String,
+    /// instruction that failed
+    pub range: Option<Range>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RegisterValue {
+    pub name: String,
+    pub value: u32,
+}
+
+impl Backend {
+    pub async fn handle_run(&self, params: RunParams) -> jsonrpc::Result<RunResult> {
+        log!("mipsls/run");
+
+        // The entry of the map is not held across awaits, which would block opening and closing
+        // documents
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .map(|entry| entry.value().clone())
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let (uri, version, text) = {
+            let doc = doc_arc.read().await;
+            (doc.uri.clone(), doc.version, doc.text.clone())
+        };
+        let (settings, definitions) = self.document_settings(&uri).await;
+        let max_steps = params.max_steps.unwrap_or(DEFAULT_MAX_STEPS).min(MAX_STEPS);
+
+        // The simulation runs on a copy of the document, so edits are not blocked meanwhile
+        tokio::task::spawn_blocking(move || {
+            let mut doc = Document::new(uri, version, text);
+            doc.update_model();
+            run(&doc, &definitions, &settings, &params.input, max_steps)
+        })
+        .await
+        .map_err(|_| jsonrpc::Error::internal_error())
+    }
+}
+
+/// Runs a program in the simulator and reports its output and final registers.
+pub fn run(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    settings: &Settings,
+    input: &str,
+    max_steps: u64,
+) -> RunResult {
+    let mut simulator = Simulator::new(doc, definitions, settings);
+    let mut console = BufferConsole::new(input);
+    simulator.run(&mut console, max_steps);
+
+    let (exit_code, error) = match &simulator.status {
+        Status::Exited(code) => (Some(*code), None),
+        Status::Failed(error) => (
+            None,
+            Some(RunError {
+                message: error.message.clone(),
+                range: error
+                    .statement_index
+                    .map(|index| doc.ts_range_to_ls(&doc.ast.items[index].range())),
+            }),
+        ),
+        Status::Running => (None, None),
+    };
+    RunResult {
+        output: console.output,
+        exit_code,
+        error,
+        steps: simulator.steps,
+        registers: registers(&simulator.machine),
+        float_registers: float_registers(&simulator.machine),
+    }
+}
+
+/// General purpose registers by their conventional names, then `hi`, `lo` and `pc`.
+pub fn registers(machine: &Machine) -> Vec<RegisterValue> {
+    let special = [("hi", machine.hi), ("lo", machine.lo), ("pc", machine.pc)];
+    GPR_NAMES
+        .iter()
+        .zip(machine.registers)
+        .map(|(name, value)| (name.trim_start_matches('$'), value))
+        .chain(special)
+        .map(|(name, value)| RegisterValue {
+            name: name.to_string(),
+            value,
+        })
+        .collect()
+}
+
+pub fn float_registers(machine: &Machine) -> Vec<RegisterValue> {
+    machine
+        .fpr
+        .iter()
+        .enumerate()
+        .map(|(number, value)| RegisterValue {
+            name: format!("f{}", number),
+            value: *value,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::Dialect;
+    use std::str::FromStr;
+
+    #[test]
+    fn runs_documents() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+        let text = ".text\nmain:\n  li $a0, 5\n  li $v0, 1\n  syscall\n  lw $t0, 1($zero)\n";
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+
+        let result = run(&doc, &definitions, &settings, "", DEFAULT_MAX_STEPS);
+        assert_eq!(result.output, "5");
+        assert_eq!(result.exit_code, None);
+        let error = result.error.unwrap();
+        assert_eq!(
+            error.message,
+            "address 0x00000001 is not aligned to a 4-byte boundary"
+        );
+        assert_eq!(error.range.unwrap().start, Position::new(5, 2));
+        assert_eq!(result.steps, 4);
+        assert_eq!(result.registers[4].name, "a0");
+        assert_eq!(result.registers[4].value, 5);
+        assert_eq!(result.registers[34].name, "pc");
+        assert_eq!(result.registers[34].value, 0x0040000c);
+    }
+}
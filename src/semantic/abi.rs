@@ -0,0 +1,69 @@
+//! Registers a routine must preserve under a calling convention.
+
+use crate::lang::{Abi, LanguageDefinitions, Register};
+
+use super::effects::Location;
+use super::frame::{FP, RA, SP};
+use super::liveness::LocationSet;
+
+const GP: Location = Location::Gpr(28);
+
+/// Returns the registers a routine has to restore before it returns: `$sp`, `$fp`, `$ra` and the
+/// registers described as preserved across procedure calls.
+pub fn preserved_registers(abi: Abi, definitions: &LanguageDefinitions) -> LocationSet {
+    if abi == Abi::None {
+        return LocationSet::default();
+    }
+
+    let mut preserved = LocationSet::of(&[SP, FP, RA]);
+    let registers = &definitions.registers;
+    for (name, description) in registers.common.iter().chain(&registers.float) {
+        if !description.ends_with("Preserved across procedure calls") {
+            continue;
+        }
+        match Register::parse(name) {
+            Some(Register::Gpr(number)) => preserved.insert(Location::Gpr(number)),
+            Some(Register::Fpr(number)) if abi != Abi::N64 => {
+                preserved.insert(Location::Fpr(number))
+            }
+            _ => {}
+        }
+    }
+    if matches!(abi, Abi::N32 | Abi::N64) {
+        preserved.insert(GP);
+    }
+    if abi == Abi::N64 {
+        for number in 24..32 {
+            preserved.insert(Location::Fpr(number));
+        }
+    }
+    preserved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    #[test]
+    fn preserves_registers_of_the_abi() {
+        let mut settings = Settings::default();
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+
+        let preserved = preserved_registers(Abi::O32, &definitions);
+        assert!(preserved.contains(Location::Gpr(16)));
+        assert!(preserved.contains(Location::Gpr(23)));
+        assert!(preserved.contains(Location::Fpr(20)));
+        assert!(!preserved.contains(Location::Gpr(8)));
+        assert!(!preserved.contains(GP));
+
+        let preserved = preserved_registers(Abi::N64, &definitions);
+        assert!(preserved.contains(GP));
+        assert!(preserved.contains(Location::Fpr(25)));
+        assert!(!preserved.contains(Location::Fpr(20)));
+
+        settings.abi = Abi::None;
+        assert!(preserved_registers(settings.abi, &definitions).is_empty());
+    }
+}
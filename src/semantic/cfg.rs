@@ -0,0 +1,575 @@
+//! Control-flow graph of the code sections, split into basic blocks and routines.
+
+use std::collections::{HashMap, HashSet};
+
+use smol_str::{SmolStr, ToSmolStr};
+
+use crate::ast::*;
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::settings::Settings;
+
+use super::effects::{instruction_effects, is_call, is_control_transfer, Location};
+use super::eval::Evaluator;
+use super::{parse_section, Section};
+
+/// Labels where execution starts.
+pub const ENTRY_POINTS: &[&str] = &["main", "__start"];
+
+/// `$v0` values of `syscall` that terminate the program: MARS/SPIM `exit` and `exit2`, and
+/// Linux `exit` and `exit_group` for o32, n64 and n32.
+const EXIT_SYSCALLS: &[i64] = &[10, 17, 4001, 4246, 5058, 5205, 6058, 6205];
+
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+    pub routines: Vec<Routine>,
+}
+
+#[derive(Debug, Default)]
+pub struct Block {
+    /// indices into `SemanticModel::instructions`, in source order
+    pub instructions: Vec<usize>,
+    /// instructions in the delay slot of the branch or jump in front of them
+    pub delay_slots: Vec<usize>,
+    /// labels defined at the start of the block
+    pub labels: Vec<SmolStr>,
+    /// blocks reached by branches and jumps
+    pub targets: Vec<usize>,
+    /// block reached by executing past the end of this block
+    pub fall_through: Option<usize>,
+    /// names of the routines called from this block
+    pub calls: Vec<SmolStr>,
+    pub exit: Option<Exit>,
+    /// index into `Cfg::routines`
+    pub routine: usize,
+    /// whether the block is the first one of its section, which is reached without a label
+    pub section_start: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// `jr $ra` or `eret`
+    Return,
+    /// `syscall` that terminates the program
+    Terminate,
+    /// jump to an address in a register other than `$ra`
+    Indirect,
+}
+
+impl Exit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Exit::Return => "return",
+            Exit::Terminate => "terminate",
+            Exit::Indirect => "indirect",
+        }
+    }
+}
+
+pub struct Routine {
+    /// label the routine starts at, or `None` for code in front of the first routine
+    pub name: Option<SmolStr>,
+    pub entry: usize,
+    pub blocks: Vec<usize>,
+}
+
+impl Block {
+    pub fn successors(&self) -> impl Iterator<Item = usize> + '_ {
+        self.targets.iter().copied().chain(self.fall_through)
+    }
+
+    /// Instructions in the order they execute: an instruction in a delay slot executes before the
+    /// branch or jump in front of it.
+    pub fn execution_order(&self) -> Vec<usize> {
+        let mut order = self.instructions.clone();
+        for position in 1..order.len() {
+            if self.delay_slots.contains(&order[position]) {
+                order.swap(position - 1, position);
+            }
+        }
+        order
+    }
+
+    /// Range from the first to the last instruction of the block.
+    pub fn range(&self, doc: &Document) -> Option<tree_sitter::Range> {
+        let model = &doc.semantic_model;
+        let range = |instruction: &usize| {
+            doc.ast.items[model.instructions[*instruction].statement_index].range()
+        };
+        let (first, last) = (
+            range(self.instructions.first()?),
+            range(self.instructions.last()?),
+        );
+        Some(tree_sitter::Range {
+            start_byte: first.start_byte,
+            start_point: first.start_point,
+            end_byte: last.end_byte,
+            end_point: last.end_point,
+        })
+    }
+}
+
+/// Instruction in a code section together with the labels in front of it.
+struct Node<'a> {
+    instruction: usize,
+    statement: usize,
+    /// `None` for macro invocations
+    node: Option<&'a InstructionNode>,
+    mnemonic: String,
+    labels: Vec<&'a LabelNode>,
+    delay_slot: bool,
+}
+
+/// How an instruction affects the control flow.
+enum Transfer<'a> {
+    Jump {
+        target: Option<&'a ValueNode>,
+        conditional: bool,
+    },
+    Call(Option<&'a ValueNode>),
+    Exit(Exit),
+}
+
+impl Cfg {
+    pub fn build(doc: &Document, settings: &Settings) -> Cfg {
+        let text = &doc.text;
+        let evaluator = Evaluator::new(doc, settings);
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut label_blocks: HashMap<SmolStr, usize> = HashMap::new();
+        // numeric labels may be defined several times, so they are resolved by position
+        let mut numeric_labels: Vec<(usize, SmolStr, usize)> = Vec::new();
+        let mut jumps: Vec<(usize, usize, &ValueNode)> = Vec::new();
+
+        for nodes in sections(doc) {
+            let first = blocks.len();
+            // whether the current block has ended, and whether it continues into the next one
+            let mut ended = true;
+            let mut falls = false;
+            let mut delayed: Option<Transfer> = None;
+
+            for (position, node) in nodes.iter().enumerate() {
+                if ended || !node.labels.is_empty() {
+                    if falls && blocks.len() > first {
+                        let last = blocks.len() - 1;
+                        blocks[last].fall_through = Some(blocks.len());
+                    }
+                    blocks.push(Block {
+                        section_start: blocks.len() == first,
+                        ..Block::default()
+                    });
+                }
+                let current = blocks.len() - 1;
+                for label in &node.labels {
+                    let name = get_text_in_ts_range(text, label.name.range).to_smolstr();
+                    if matches!(label.kind, LabelKind::Numeric) {
+                        numeric_labels.push((node.statement, name.clone(), current));
+                    } else {
+                        label_blocks.entry(name.clone()).or_insert(current);
+                    }
+                    blocks[current].labels.push(name);
+                }
+                blocks[current].instructions.push(node.instruction);
+                if node.delay_slot {
+                    blocks[current].delay_slots.push(node.instruction);
+                }
+
+                let transfer = match delayed.take() {
+                    Some(transfer) if node.delay_slot => Some(transfer),
+                    _ => transfer(doc, &evaluator, &nodes[..=position]),
+                };
+                // The instruction in the delay slot executes before the branch is taken
+                if transfer.is_some() && nodes.get(position + 1).is_some_and(|n| n.delay_slot) {
+                    delayed = transfer;
+                    ended = false;
+                    falls = true;
+                    continue;
+                }
+
+                let block = &mut blocks[current];
+                (ended, falls) = match transfer {
+                    None => (false, true),
+                    Some(Transfer::Call(target)) => {
+                        if let Some(ValueNode::Symbol { range }) = target {
+                            block
+                                .calls
+                                .push(get_text_in_ts_range(text, *range).to_smolstr());
+                        }
+                        (false, true)
+                    }
+                    Some(Transfer::Exit(exit)) => {
+                        block.exit = Some(exit);
+                        (true, false)
+                    }
+                    Some(Transfer::Jump {
+                        target,
+                        conditional,
+                    }) => {
+                        if let Some(target) = target {
+                            jumps.push((current, node.statement, target));
+                        }
+                        (true, conditional)
+                    }
+                };
+            }
+        }
+
+        for (block, statement, target) in jumps {
+            let name = get_text_in_ts_range(text, target.range());
+            let target = match target {
+                ValueNode::NumericLabelReference { .. } => {
+                    resolve_numeric_label(&numeric_labels, statement, name)
+                }
+                _ => label_blocks.get(name).copied(),
+            };
+            if let Some(target) = target {
+                if !blocks[block].targets.contains(&target) {
+                    blocks[block].targets.push(target);
+                }
+            }
+        }
+
+        let routines = assign_routines(doc, &mut blocks, &label_blocks);
+        Cfg { blocks, routines }
+    }
+
+    /// Returns for each block whether no path reaches it: it has no predecessors and no label
+    /// another file could jump to.
+    pub fn unreachable(&self) -> Vec<bool> {
+        self.predecessors()
+            .iter()
+            .zip(&self.blocks)
+            .map(|(predecessors, block)| {
+                predecessors.is_empty() && block.labels.is_empty() && !block.section_start
+            })
+            .collect()
+    }
+
+    /// Returns the blocks that branch, jump or fall into each block.
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in block.successors() {
+                if !predecessors[successor].contains(&index) {
+                    predecessors[successor].push(index);
+                }
+            }
+        }
+        predecessors
+    }
+}
+
+/// Collects the instructions of each code section in source order.
+fn sections(doc: &Document) -> Vec<Vec<Node<'_>>> {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+    let mut sections: Vec<(Section, Vec<Node>)> = Vec::new();
+    let mut labels: Vec<&LabelNode> = Vec::new();
+    let mut instructions = model.instructions.iter().enumerate().peekable();
+
+    for (index, statement) in doc.ast.items.iter().enumerate() {
+        if model.macro_body[index] {
+            continue;
+        }
+        let node = match statement {
+            SyntaxNode::Label(node) => {
+                labels.push(node);
+                continue;
+            }
+            SyntaxNode::Directive(node) => {
+                if parse_section(get_text_in_ts_range(text, node.mnemonic.range)).is_some() {
+                    labels.clear();
+                }
+                continue;
+            }
+            SyntaxNode::Instruction(node)
+                if !model
+                    .macros
+                    .contains_key(get_text_in_ts_range(text, node.mnemonic.range)) =>
+            {
+                Some(node)
+            }
+            SyntaxNode::Instruction(_) | SyntaxNode::MacroInvocation(_) => None,
+            _ => continue,
+        };
+        while instructions
+            .peek()
+            .is_some_and(|(_, instruction)| instruction.statement_index < index)
+        {
+            instructions.next();
+        }
+        let Some((position, instruction)) =
+            instructions.next_if(|(_, instruction)| instruction.statement_index == index)
+        else {
+            continue;
+        };
+        if !matches!(instruction.section, Section::Text | Section::KText) {
+            labels.clear();
+            continue;
+        }
+
+        let mnemonic = node
+            .map(|node| get_text_in_ts_range(text, node.mnemonic.range).to_ascii_lowercase())
+            .unwrap_or_default();
+        let nodes = match sections
+            .iter()
+            .position(|(section, _)| *section == instruction.section)
+        {
+            Some(section) => &mut sections[section].1,
+            None => {
+                sections.push((instruction.section, Vec::new()));
+                &mut sections.last_mut().expect("just pushed").1
+            }
+        };
+        // Without reordering, the instruction after a branch is in its delay slot
+        let delay_slot = nodes.last().is_some_and(|previous| {
+            is_control_transfer(&previous.mnemonic)
+                && !previous.delay_slot
+                && !model.instructions[previous.instruction].options.reorder
+        });
+        nodes.push(Node {
+            instruction: position,
+            statement: index,
+            node,
+            mnemonic,
+            labels: std::mem::take(&mut labels),
+            delay_slot,
+        });
+    }
+
+    sections.into_iter().map(|(_, nodes)| nodes).collect()
+}
+
+fn operand_values(node: &InstructionNode) -> Vec<&ValueNode> {
+    node.operands
+        .iter()
+        .filter_map(|item| match item {
+            OperandListItem::Operand(value) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_register(text: &str, value: &ValueNode, names: &[&str]) -> bool {
+    matches!(value, ValueNode::Register { range } if names.contains(&get_text_in_ts_range(text, *range)))
+}
+
+/// Classifies the last of `nodes`; the ones before it are used to find the `syscall` number.
+fn transfer<'a>(doc: &Document, evaluator: &Evaluator, nodes: &[Node<'a>]) -> Option<Transfer<'a>> {
+    let text = &doc.text;
+    let (node, before) = nodes.split_last()?;
+    let instruction = node.node?;
+    let mnemonic = node.mnemonic.as_str();
+
+    match mnemonic {
+        "syscall" => {
+            return exit_syscall(doc, evaluator, before).then_some(Transfer::Exit(Exit::Terminate))
+        }
+        "eret" | "deret" => return Some(Transfer::Exit(Exit::Return)),
+        _ if !is_control_transfer(mnemonic) => return None,
+        _ => {}
+    }
+
+    let operands = operand_values(instruction);
+    let target = operands.last().copied().filter(|value| {
+        matches!(
+            value,
+            ValueNode::Symbol { .. } | ValueNode::NumericLabelReference { .. }
+        )
+    });
+    if is_call(mnemonic) {
+        return Some(Transfer::Call(target));
+    }
+    if matches!(mnemonic, "jr" | "jr.hb" | "jic") {
+        let returns = operands
+            .first()
+            .is_some_and(|value| is_register(text, value, &["$ra", "$31"]));
+        return Some(Transfer::Exit(if returns {
+            Exit::Return
+        } else {
+            Exit::Indirect
+        }));
+    }
+
+    // `beq $t0, $t0, label` always branches
+    let same_registers = matches!(mnemonic, "beq" | "beql")
+        && matches!(operands.as_slice(), [ValueNode::Register { range: left }, ValueNode::Register { range: right }, _]
+            if get_text_in_ts_range(text, *left) == get_text_in_ts_range(text, *right));
+    Some(Transfer::Jump {
+        target,
+        conditional: !(matches!(mnemonic, "j" | "b" | "bc") || same_registers),
+    })
+}
+
+/// Returns true if the `$v0` value set before a `syscall` in the same block terminates the
+/// program.
+fn exit_syscall(doc: &Document, evaluator: &Evaluator, before: &[Node]) -> bool {
+    let text = &doc.text;
+    for node in before.iter().rev() {
+        let Some(instruction) = node.node else {
+            return false;
+        };
+        if is_control_transfer(&node.mnemonic) {
+            return false;
+        }
+        let operands = operand_values(instruction);
+        let effects = instruction_effects(text, &node.mnemonic, &operands);
+        if !effects.writes.contains(&Location::Gpr(2)) {
+            if !node.labels.is_empty() {
+                return false;
+            }
+            continue;
+        }
+        let value = match (node.mnemonic.as_str(), operands.as_slice()) {
+            ("li", [_, value]) => value,
+            ("addi" | "addiu" | "ori", [_, base, value])
+                if is_register(text, base, &["$zero", "$0"]) =>
+            {
+                value
+            }
+            _ => return false,
+        };
+        return evaluator
+            .evaluate(value, node.statement)
+            .is_ok_and(|number| EXIT_SYSCALLS.contains(&number));
+    }
+    false
+}
+
+/// Resolves `1f` to the next and `1b` to the previous definition of `1`.
+fn resolve_numeric_label(
+    labels: &[(usize, SmolStr, usize)],
+    statement: usize,
+    reference: &str,
+) -> Option<usize> {
+    let (number, forward) = match reference.as_bytes().last()? {
+        b'f' | b'F' => (&reference[..reference.len() - 1], true),
+        b'b' | b'B' => (&reference[..reference.len() - 1], false),
+        _ => return None,
+    };
+    let mut candidates = labels.iter().filter(|(_, name, _)| name == number);
+    let found = if forward {
+        candidates.find(|(index, _, _)| *index > statement)
+    } else {
+        candidates.rfind(|(index, _, _)| *index < statement)
+    };
+    found.map(|(_, _, block)| *block)
+}
+
+/// Routines start at the entry points, global symbols, `.ent` names and call targets. Every
+/// block belongs to the routine started last before it.
+fn assign_routines(
+    doc: &Document,
+    blocks: &mut [Block],
+    label_blocks: &HashMap<SmolStr, usize>,
+) -> Vec<Routine> {
+    let mut entries: HashSet<SmolStr> = ENTRY_POINTS.iter().map(|name| name.to_smolstr()).collect();
+    for (index, statement) in doc.ast.items.iter().enumerate() {
+        let SyntaxNode::Directive(node) = statement else {
+            continue;
+        };
+        let mnemonic = get_text_in_ts_range(&doc.text, node.mnemonic.range).to_ascii_lowercase();
+        if !doc.semantic_model.macro_body[index]
+            && matches!(mnemonic.as_str(), ".globl" | ".global" | ".ent")
+        {
+            for item in &node.operands {
+                if let OperandListItem::Operand(ValueNode::Symbol { range }) = item {
+                    entries.insert(get_text_in_ts_range(&doc.text, *range).to_smolstr());
+                }
+            }
+        }
+    }
+    for block in blocks.iter() {
+        entries.extend(block.calls.iter().cloned());
+    }
+
+    let mut routines: Vec<Routine> = Vec::new();
+    for (index, block) in blocks.iter_mut().enumerate() {
+        let entry = block
+            .labels
+            .iter()
+            .find(|label| entries.contains(*label) && label_blocks.get(*label) == Some(&index));
+        if entry.is_some() || block.section_start {
+            routines.push(Routine {
+                name: entry.cloned(),
+                entry: index,
+                blocks: Vec::new(),
+            });
+        }
+        let routine = routines.len() - 1;
+        block.routine = routine;
+        routines[routine].blocks.push(index);
+    }
+    routines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    fn cfg(text: &str) -> Cfg {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+        Cfg::build(&doc, &Settings::default())
+    }
+
+    type Shape = (usize, Vec<usize>, Option<usize>, Option<Exit>);
+
+    fn shape(cfg: &Cfg) -> Vec<Shape> {
+        cfg.blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.instructions.len(),
+                    block.targets.clone(),
+                    block.fall_through,
+                    block.exit,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_blocks_at_labels_and_branches() {
+        let cfg = cfg(".text\nmain:\n  li $t0, 3\nloop:\n  addi $t0, $t0, -1\n  bnez $t0, loop\n  jal f\n  li $v0, 10\n  syscall\nf:\n  jr $ra\n");
+        assert_eq!(
+            shape(&cfg),
+            vec![
+                (1, vec![], Some(1), None),
+                (2, vec![1], Some(2), None),
+                (3, vec![], None, Some(Exit::Terminate)),
+                (1, vec![], None, Some(Exit::Return)),
+            ]
+        );
+        assert_eq!(cfg.blocks[2].calls, vec![SmolStr::new("f")]);
+        let routines: Vec<_> = cfg
+            .routines
+            .iter()
+            .map(|routine| (routine.name.as_deref(), routine.blocks.clone()))
+            .collect();
+        assert_eq!(
+            routines,
+            vec![(Some("main"), vec![0, 1, 2]), (Some("f"), vec![3])]
+        );
+    }
+
+    #[test]
+    fn keeps_delay_slots_with_their_branch() {
+        let cfg = cfg(".set noreorder\nmain:\n  b done\n  addiu $v0, $v0, 1\n  nop\ndone:\n  beqz $v0, 1f\n  nop\n  nop\n1:\n  jr $ra\n  nop\n");
+        assert_eq!(
+            shape(&cfg),
+            vec![
+                (2, vec![2], None, None),
+                (1, vec![], Some(2), None),
+                (2, vec![4], Some(3), None),
+                (1, vec![], Some(4), None),
+                (2, vec![], None, Some(Exit::Return)),
+            ]
+        );
+    }
+}
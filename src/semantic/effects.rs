@@ -0,0 +1,239 @@
+//! Registers read and written by instructions.
+
+use std::fmt;
+
+use crate::ast::ValueNode;
+use crate::document::utils::get_text_in_ts_range;
+use crate::lang::Register;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    Gpr(u8),
+    Fpr(u8),
+    Hi,
+    Lo,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Gpr(n) => write!(f, "{}", Register::Gpr(*n)),
+            Location::Fpr(n) => write!(f, "{}", Register::Fpr(*n)),
+            Location::Hi => write!(f, "HI"),
+            Location::Lo => write!(f, "LO"),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Effects {
+    pub reads: Vec<Location>,
+    pub writes: Vec<Location>,
+}
+
+impl Effects {
+    fn read(&mut self, location: Location) {
+        if location != Location::Gpr(0) && !self.reads.contains(&location) {
+            self.reads.push(location);
+        }
+    }
+
+    fn write(&mut self, location: Location) {
+        if location != Location::Gpr(0) && !self.writes.contains(&location) {
+            self.writes.push(location);
+        }
+    }
+}
+
+/// Instructions whose first operand is read instead of written.
+const FIRST_OPERAND_READ: &[&str] = &[
+    "sb", "sh", "sw", "sd", "swl", "swr", "sdl", "sdr", "swc1", "sdc1", "s.s", "s.d", "swxc1",
+    "sdxc1", "suxc1", "usw", "ush", "jr", "jr.hb", "mthi", "mtlo", "mult", "multu", "dmult",
+    "dmultu", "madd", "maddu", "msub", "msubu", "teq", "teqi", "tne", "tnei", "tge", "tgei",
+    "tgeiu", "tgeu", "tlt", "tlti", "tltiu", "tltu", "cache", "pref",
+];
+
+/// Instructions that also read the register they write.
+const FIRST_OPERAND_UPDATED: &[&str] = &[
+    "lwl", "lwr", "ldl", "ldr", "movz", "movn", "movf", "movt", "ins", "dins", "sc", "scd",
+];
+
+/// Moves to coprocessors read the first and write the second operand.
+const MOVES_TO_COPROCESSOR: &[&str] = &["mtc0", "mtc1", "dmtc1", "mthc1", "ctc1", "mtc2"];
+
+/// Instructions that write the return address register.
+const LINKING: &[&str] = &[
+    "jal", "bal", "bgezal", "bltzal", "bgezall", "bltzall", "jalr", "jalr.hb", "balc", "jialc",
+];
+
+/// Returns true for mnemonics that transfer control, i.e. branches and jumps.
+pub fn is_control_transfer(mnemonic: &str) -> bool {
+    match mnemonic.as_bytes().first() {
+        Some(b'j') => true,
+        Some(b'b') => !matches!(mnemonic, "break" | "bitswap" | "dbitswap"),
+        _ => false,
+    }
+}
+
+/// Returns true for branches and jumps that save the return address, i.e. calls.
+pub fn is_call(mnemonic: &str) -> bool {
+    LINKING.contains(&mnemonic)
+}
+
+/// Returns the registers an instruction reads and writes, including the implicit ones like `$ra`
+/// for `jal` or HI/LO for `mult`.
+pub fn instruction_effects(text: &str, mnemonic: &str, operands: &[&ValueNode]) -> Effects {
+    let mut effects = Effects::default();
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let mnemonic = mnemonic.as_str();
+
+    let first_written = !(FIRST_OPERAND_READ.contains(&mnemonic)
+        || MOVES_TO_COPROCESSOR.contains(&mnemonic)
+        || is_control_transfer(mnemonic) && mnemonic != "jalr" && mnemonic != "jalr.hb"
+        || mnemonic.starts_with("c.")
+        // Two operand forms only write HI and LO
+        || matches!(mnemonic, "div" | "divu" | "ddiv" | "ddivu") && operands.len() == 2
+        // `jalr rs` links to `$ra`
+        || matches!(mnemonic, "jalr" | "jalr.hb") && operands.len() == 1);
+
+    for (index, operand) in operands.iter().enumerate() {
+        let written = match index {
+            0 => first_written,
+            1 => MOVES_TO_COPROCESSOR.contains(&mnemonic),
+            _ => false,
+        };
+        match operand {
+            ValueNode::Register { range } => {
+                let Some(location) = location(get_text_in_ts_range(text, *range)) else {
+                    continue;
+                };
+                if written {
+                    effects.write(location);
+                    if FIRST_OPERAND_UPDATED.contains(&mnemonic) {
+                        effects.read(location);
+                    }
+                } else {
+                    effects.read(location);
+                }
+            }
+            // Registers in addresses and expressions are always read
+            value => value.visit(&mut |value| {
+                if let ValueNode::Register { range } = value {
+                    if let Some(location) = location(get_text_in_ts_range(text, *range)) {
+                        effects.read(location);
+                    }
+                }
+            }),
+        }
+    }
+
+    if LINKING.contains(&mnemonic) && !(mnemonic.starts_with("jalr") && operands.len() > 1) {
+        effects.write(Location::Gpr(31));
+    }
+    match mnemonic {
+        "mfhi" => effects.read(Location::Hi),
+        "mflo" => effects.read(Location::Lo),
+        "mthi" => effects.write(Location::Hi),
+        "mtlo" => effects.write(Location::Lo),
+        "mult" | "multu" | "dmult" | "dmultu" | "div" | "divu" | "ddiv" | "ddivu" => {
+            effects.write(Location::Hi);
+            effects.write(Location::Lo);
+        }
+        // Service number and arguments of MARS/SPIM and Linux system calls, and their results
+        "syscall" => {
+            for number in [2, 4, 5, 6, 7] {
+                effects.read(Location::Gpr(number));
+            }
+            effects.read(Location::Fpr(12));
+            effects.write(Location::Gpr(2));
+            effects.write(Location::Fpr(0));
+        }
+        "madd" | "maddu" | "msub" | "msubu" => {
+            effects.read(Location::Hi);
+            effects.read(Location::Lo);
+            effects.write(Location::Hi);
+            effects.write(Location::Lo);
+        }
+        _ => {}
+    }
+
+    effects
+}
+
+/// Parses a register name into the location it refers to.
+pub fn location(name: &str) -> Option<Location> {
+    match Register::parse(name)? {
+        Register::Gpr(number) => Some(Location::Gpr(number)),
+        Register::Fpr(number) => Some(Location::Fpr(number)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{OperandListItem, SyntaxNode};
+    use crate::document::Document;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    fn effects(line: &str) -> Effects {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            line.to_string(),
+        );
+        doc.update_model();
+        let Some(SyntaxNode::Instruction(node)) = doc.ast.items.first() else {
+            panic!("expected instruction");
+        };
+        let operands: Vec<&ValueNode> = node
+            .operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+        let mnemonic = get_text_in_ts_range(&doc.text, node.mnemonic.range);
+        instruction_effects(&doc.text, mnemonic, &operands)
+    }
+
+    #[test]
+    fn finds_read_and_written_registers() {
+        assert_eq!(
+            effects("add $t0, $t1, $t2"),
+            Effects {
+                reads: vec![Location::Gpr(9), Location::Gpr(10)],
+                writes: vec![Location::Gpr(8)],
+            }
+        );
+        assert_eq!(
+            effects("sw $ra, 4($sp)"),
+            Effects {
+                reads: vec![Location::Gpr(31), Location::Gpr(29)],
+                writes: vec![],
+            }
+        );
+        assert_eq!(
+            effects("jal func"),
+            Effects {
+                reads: vec![],
+                writes: vec![Location::Gpr(31)],
+            }
+        );
+        assert_eq!(
+            effects("mult $a0, $a1"),
+            Effects {
+                reads: vec![Location::Gpr(4), Location::Gpr(5)],
+                writes: vec![Location::Hi, Location::Lo],
+            }
+        );
+        assert_eq!(
+            effects("mtc1 $t0, $f2"),
+            Effects {
+                reads: vec![Location::Gpr(8)],
+                writes: vec![Location::Fpr(2)],
+            }
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Encoding of machine instructions into their 32-bit words.
+//!
+//! The bit fields of every instruction come from the `encoding` of its variant in the
+//! instruction resource, written from the most significant bit as `name=bits` for fixed fields
+//! and `name:width` for fields taken from the operand of that name. Release 6 moved some
+//! instructions to other opcodes; their variants carry an `encoding_r6` as well.
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{LanguageDefinitions, Register};
+use crate::operands::{OperandKind, ValueClass};
+use crate::settings::Settings;
+use crate::version::VersionLabel;
+
+use super::eval::Evaluator;
+use super::layout::{instruction_words, select_variant, Layout};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    /// bits of the field, most significant first
+    pub bits: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoding {
+    pub word: u32,
+    pub fields: Vec<Field>,
+}
+
+/// Bit field of an encoding pattern.
+enum Pattern<'p> {
+    Fixed { name: &'p str, bits: &'p str },
+    Operand { name: &'p str, width: u32 },
+}
+
+fn parse_pattern(encoding: &str) -> Option<Vec<Pattern<'_>>> {
+    encoding
+        .split_whitespace()
+        .map(|field| {
+            if let Some((name, bits)) = field.split_once('=') {
+                bits.bytes()
+                    .all(|b| b == b'0' || b == b'1')
+                    .then_some(Pattern::Fixed { name, bits })
+            } else {
+                let (name, width) = field.split_once(':')?;
+                let width = width
+                    .parse()
+                    .ok()
+                    .filter(|width| (1..=26).contains(width))?;
+                Some(Pattern::Operand { name, width })
+            }
+        })
+        .collect()
+}
+
+/// Name shown for a field taken from an operand.
+fn field_label(name: &str) -> &str {
+    match name {
+        "sa" | "sa5" => "shamt",
+        "target" => "instr_index",
+        _ if name.starts_with("imm") => "immediate",
+        _ if name.starts_with("hint") => "code",
+        _ => name,
+    }
+}
+
+pub struct Encoder<'a> {
+    doc: &'a Document,
+    definitions: &'a LanguageDefinitions,
+    layout: &'a Layout,
+    release6: bool,
+    evaluator: Evaluator<'a>,
+    /// resolves labels to their addresses if the layout is absolute
+    addresses: Evaluator<'a>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(
+        doc: &'a Document,
+        definitions: &'a LanguageDefinitions,
+        settings: &Settings,
+        layout: &'a Layout,
+    ) -> Encoder<'a> {
+        let addresses = Evaluator::new(doc, settings);
+        Encoder {
+            doc,
+            definitions,
+            layout,
+            release6: settings.version.has_ancestor(VersionLabel::Mips32r6),
+            evaluator: Evaluator::new(doc, settings),
+            addresses: if layout.absolute {
+                addresses.with_labels(&layout.labels)
+            } else {
+                addresses
+            },
+        }
+    }
+
+    /// Returns the machine code of an instruction, or `None` for pseudo-instructions, instructions
+    /// without a known encoding and operands that are not resolved, such as labels in other
+    /// sections or jump targets before linking.
+    pub fn encode(&self, instruction: usize) -> Option<Encoding> {
+        let text = &self.doc.text;
+        let model = &self.doc.semantic_model;
+        let statement = model.instructions[instruction].statement_index;
+        let Some(SyntaxNode::Instruction(node)) = self.doc.ast.items.get(statement) else {
+            return None;
+        };
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+        if model.macros.contains_key(mnemonic) {
+            return None;
+        }
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        let values: Vec<&ValueNode> = node
+            .operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+
+        let (variant, native) = select_variant(
+            self.definitions,
+            &self.evaluator,
+            text,
+            &mnemonic,
+            &values,
+            statement,
+        )?;
+        let words = instruction_words(
+            self.definitions,
+            &self.evaluator,
+            text,
+            &mnemonic,
+            &values,
+            statement,
+        );
+        if !native || words != 1 || variant.operands.len() != values.len() {
+            return None;
+        }
+        let encoding = match (self.release6, &variant.encoding_r6) {
+            (true, Some(encoding)) => encoding,
+            (true, None) => variant.encoding.as_ref()?,
+            (false, _) => variant.encoding.as_ref()?,
+        };
+
+        let mut operands: Vec<(&str, i64)> = Vec::new();
+        for (spec, value) in variant.operands.iter().zip(&values) {
+            match OperandKind::parse(spec) {
+                OperandKind::Gpr | OperandKind::Fpr | OperandKind::CoprocessorRegister => {
+                    operands.push((spec, register_number(text, value)?));
+                }
+                OperandKind::Memory => {
+                    let ValueNode::ParenthesizedExpression { head, body, .. } = value else {
+                        return None;
+                    };
+                    let [OperandListItem::Operand(base)] = body.as_slice() else {
+                        return None;
+                    };
+                    let offset = match head {
+                        Some(head) => self.addresses.evaluate(head, statement).ok()?,
+                        None => 0,
+                    };
+                    operands.push(("offset", offset));
+                    operands.push(("base", register_number(text, base)?));
+                }
+                OperandKind::BranchOffset => {
+                    operands.push((spec, self.branch_offset(value, statement)?));
+                }
+                OperandKind::Address => {
+                    operands.push((spec, self.jump_target(value, statement)?));
+                }
+                OperandKind::Immediate { .. } => {
+                    operands.push((spec, self.addresses.evaluate(value, statement).ok()?));
+                }
+            }
+        }
+
+        let mut word: u32 = 0;
+        let mut width_total = 0;
+        let mut fields = Vec::new();
+        let mut uses: Vec<&str> = Vec::new();
+        for pattern in parse_pattern(encoding)? {
+            let (name, bits) = match pattern {
+                Pattern::Fixed { name, bits } => (name, bits.to_string()),
+                Pattern::Operand { name, width } => {
+                    // Repeated fields take the next operand of that name, or repeat the last one,
+                    // as `clz` does with `rd`
+                    let occurrence = uses.iter().filter(|used| **used == name).count();
+                    uses.push(name);
+                    let candidates: Vec<i64> = operands
+                        .iter()
+                        .filter(|(operand, _)| *operand == name)
+                        .map(|(_, value)| *value)
+                        .collect();
+                    let value = *candidates.get(occurrence).or(candidates.last())?;
+                    if !fits(name, value, width) {
+                        return None;
+                    }
+                    let mask = (1i64 << width) - 1;
+                    (
+                        field_label(name),
+                        format!("{:0width$b}", value & mask, width = width as usize),
+                    )
+                }
+            };
+            for bit in bits.bytes() {
+                word = (word << 1) | (bit - b'0') as u32;
+            }
+            width_total += bits.len();
+            fields.push(Field {
+                name: name.to_string(),
+                bits,
+            });
+        }
+        (width_total == 32).then_some(Encoding { word, fields })
+    }
+
+    /// Offset in words from the delay slot to a label in the same section.
+    fn branch_offset(&self, value: &ValueNode, statement: usize) -> Option<i64> {
+        if ValueClass::of(&self.doc.text, value) != ValueClass::Symbol {
+            return None;
+        }
+        let name = get_text_in_ts_range(&self.doc.text, value.range());
+        let target = self.layout.label(name)?;
+        let placement = self.layout.statements.get(statement).copied().flatten()?;
+        let label = self.doc.semantic_model.labels.get(name)?;
+        if !self.layout.absolute && label.section != placement.section {
+            return None;
+        }
+        let distance = target - (placement.address + 4);
+        (distance % 4 == 0).then_some(distance / 4)
+    }
+
+    /// Word index of an absolute jump target within the 256 MB region of the delay slot.
+    fn jump_target(&self, value: &ValueNode, statement: usize) -> Option<i64> {
+        if !self.layout.absolute {
+            return None;
+        }
+        let target = self.addresses.evaluate(value, statement).ok()?;
+        let placement = self.layout.statements.get(statement).copied().flatten()?;
+        let region = |address: i64| address & !0x0fff_ffff;
+        (target % 4 == 0 && region(target) == region(placement.address + 4))
+            .then_some((target & 0x0fff_ffff) >> 2)
+    }
+}
+
+fn register_number(text: &str, value: &ValueNode) -> Option<i64> {
+    let ValueNode::Register { range } = value else {
+        return None;
+    };
+    match Register::parse(get_text_in_ts_range(text, *range))? {
+        Register::Gpr(number) | Register::Fpr(number) => Some(number as i64),
+    }
+}
+
+/// Whether a value fits a field, as signed number for offsets and signed immediates.
+fn fits(name: &str, value: i64, width: u32) -> bool {
+    if name == "offset" || name.ends_with("_s") {
+        (-(1 << (width - 1))..1 << (width - 1)).contains(&value)
+    } else {
+        (0..1 << width).contains(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::Dialect;
+    use crate::version;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    fn encode(text: &str, settings: &Settings) -> Vec<Option<u32>> {
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(settings);
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+        let layout = Layout::compute(&doc, &definitions, settings);
+        let encoder = Encoder::new(&doc, &definitions, settings, &layout);
+        (0..doc.semantic_model.instructions.len())
+            .map(|instruction| encoder.encode(instruction).map(|encoding| encoding.word))
+            .collect()
+    }
+
+    #[test]
+    fn encodes_instruction_formats() {
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Mars;
+        let text = ".text\nmain:\n  addu $v0, $a0, $a1\n  sll $t0, $t1, 4\n  addiu $sp, $sp, -8\n  lw $ra, 4($sp)\n  beq $t0, $zero, main\n  jal main\n  add.s $f0, $f2, $f4\n  c.lt.d $f2, $f4\n  mfc1 $t0, $f1\n  li $t0, 0x12345\n";
+        assert_eq!(
+            encode(text, &settings),
+            vec![
+                Some(0x00851021),
+                Some(0x00094100),
+                Some(0x27bdfff8),
+                Some(0x8fbf0004),
+                Some(0x1100fffb),
+                Some(0x0c100000),
+                Some(0x46041000),
+                Some(0x4624103c),
+                Some(0x44080800),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_release_6_encodings() {
+        let text = ".text\n  jr $ra\n  ll $t0, 4($sp)\n";
+        let mut settings = Settings::default();
+        assert_eq!(
+            encode(text, &settings),
+            vec![Some(0x03e00008), Some(0xc3a80004)]
+        );
+        settings.version = &version::MIPS32R6;
+        assert_eq!(
+            encode(text, &settings),
+            vec![Some(0x03e00009), Some(0x7fa80236)]
+        );
+    }
+
+    #[test]
+    fn prefers_variants_with_an_encoding() {
+        let text = ".text\n  addiu $2, $3, 4\n  div $zero, $t0, $t1\n";
+        let mut settings = Settings::default();
+        settings.dialect = Dialect::Gas;
+        settings.version = &version::MIPS32R6;
+        assert_eq!(
+            encode(text, &settings),
+            vec![Some(0x24620004), Some(0x0109009a)]
+        );
+    }
+
+    #[test]
+    fn encodings_of_the_resource_are_well_formed() {
+        for (mnemonic, instruction) in crate::lang::load_instructions() {
+            for variant in instruction.variants {
+                let names: Vec<&str> = variant
+                    .operands
+                    .iter()
+                    .flat_map(|spec| match OperandKind::parse(spec) {
+                        OperandKind::Memory => vec!["offset", "base"],
+                        _ => vec![spec.as_str()],
+                    })
+                    .collect();
+                for encoding in [&variant.encoding, &variant.encoding_r6]
+                    .into_iter()
+                    .flatten()
+                {
+                    let pattern = parse_pattern(encoding)
+                        .unwrap_or_else(|| panic!("{}: malformed `{}`", mnemonic, encoding));
+                    let width: u32 = pattern
+                        .iter()
+                        .map(|field| match field {
+                            Pattern::Fixed { bits, .. } => bits.len() as u32,
+                            Pattern::Operand { width, .. } => *width,
+                        })
+                        .sum();
+                    assert_eq!(width, 32, "{}: `{}`", mnemonic, encoding);
+                    for field in &pattern {
+                        if let Pattern::Operand { name, .. } = field {
+                            assert!(names.contains(name), "{}: `{}`", mnemonic, encoding);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Folding of constant expressions in operands, following the operator semantics of GAS and
+//! MARS.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use smol_str::SmolStr;
+use tree_sitter::Range;
+
+use crate::ast::*;
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::Dialect;
+use crate::settings::Settings;
+
+use super::{Constant, SemanticModel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalError {
+    /// Division or modulo by zero at the range of the operator
+    DivisionByZero(Range),
+    /// Result does not fit into the word size of the assembler
+    Overflow(Range),
+    /// Expression depends on registers, labels or other values unknown before assembly
+    NotConstant,
+}
+
+pub struct Evaluator<'a> {
+    text: &'a str,
+    ast: &'a Ast,
+    model: &'a SemanticModel,
+    /// addresses of labels, if they are known
+    labels: Option<&'a HashMap<SmolStr, i64>>,
+    /// word size the assembler evaluates expressions in
+    bits: u32,
+    /// statements of the constants currently being evaluated, to detect cyclic definitions
+    resolving: RefCell<Vec<usize>>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(doc: &'a Document, settings: &Settings) -> Evaluator<'a> {
+        Evaluator {
+            text: &doc.text,
+            ast: &doc.ast,
+            model: &doc.semantic_model,
+            labels: None,
+            bits: word_bits(settings),
+            resolving: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Resolves symbols that are not constants to the given label addresses.
+    pub fn with_labels(mut self, labels: &'a HashMap<SmolStr, i64>) -> Evaluator<'a> {
+        self.labels = Some(labels);
+        self
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Evaluates a value written in the statement with the given index.
+    pub fn evaluate(&self, value: &ValueNode, statement_index: usize) -> Result<i64, EvalError> {
+        let result = match value {
+            ValueNode::Decimal { value, .. }
+            | ValueNode::Hexadecimal { value, .. }
+            | ValueNode::Octal { value, .. }
+            | ValueNode::Binary { value, .. } => *value,
+            ValueNode::Char { value, .. } => *value as i64,
+            ValueNode::Symbol { range } => {
+                let name = get_text_in_ts_range(self.text, *range);
+                match self.constant(name, statement_index) {
+                    // Errors are reported at the definition of the constant
+                    Some(result) => result.map_err(|_| EvalError::NotConstant)?,
+                    None => *self
+                        .labels
+                        .and_then(|labels| labels.get(name))
+                        .ok_or(EvalError::NotConstant)?,
+                }
+            }
+            ValueNode::UnaryExpression { body, operator, .. } => {
+                let body = self.evaluate(body, statement_index)?;
+                match operator.kind {
+                    UnaryOperatorKind::Negation => body
+                        .checked_neg()
+                        .ok_or(EvalError::Overflow(operator.range))?,
+                    UnaryOperatorKind::BitwiseNegation => !body,
+                    UnaryOperatorKind::LogicalNegation => (body == 0) as i64,
+                }
+            }
+            ValueNode::BinaryExpression {
+                left,
+                right,
+                operator,
+                ..
+            } => {
+                let left = self.evaluate(left, statement_index)?;
+                let right = self.evaluate(right, statement_index)?;
+                self.apply(operator, left, right)?
+            }
+            ValueNode::ParenthesizedExpression {
+                head: None, body, ..
+            } => {
+                let mut operands = body.iter().filter_map(|item| match item {
+                    OperandListItem::Operand(value) => Some(value),
+                    _ => None,
+                });
+                match (operands.next(), operands.next()) {
+                    (Some(inner), None) => self.evaluate(inner, statement_index)?,
+                    _ => return Err(EvalError::NotConstant),
+                }
+            }
+            _ => return Err(EvalError::NotConstant),
+        };
+
+        self.check_width(result, value.range())
+    }
+
+    /// Evaluates the constant visible from the given statement, or returns `None` if no constant
+    /// with that name exists.
+    ///
+    /// Constants redefined with `.set` take the last value defined before the statement; forward
+    /// references use the first definition.
+    pub fn constant(&self, name: &str, statement_index: usize) -> Option<Result<i64, EvalError>> {
+        let definitions = self.model.constants.get(name)?;
+        let constant = definitions
+            .iter()
+            .rev()
+            .find(|constant| constant.statement_index < statement_index)
+            .or(definitions.first())?;
+
+        if self.resolving.borrow().contains(&constant.statement_index) {
+            return Some(Err(EvalError::NotConstant));
+        }
+        let value = constant_value(self.ast, constant)?;
+
+        self.resolving.borrow_mut().push(constant.statement_index);
+        let result = self.evaluate(value, constant.statement_index);
+        self.resolving.borrow_mut().pop();
+        Some(result)
+    }
+
+    fn apply(&self, operator: &OperatorNode, left: i64, right: i64) -> Result<i64, EvalError> {
+        let overflow = EvalError::Overflow(operator.range);
+        let result = match get_text_in_ts_range(self.text, operator.range) {
+            "+" => left.checked_add(right).ok_or(overflow)?,
+            "-" => left.checked_sub(right).ok_or(overflow)?,
+            "*" => left.checked_mul(right).ok_or(overflow)?,
+            "/" | "%" if right == 0 => return Err(EvalError::DivisionByZero(operator.range)),
+            "/" => left.checked_div(right).ok_or(overflow)?,
+            "%" => left.checked_rem(right).ok_or(overflow)?,
+            "<<" | ">>" if !(0..self.bits as i64).contains(&right) => return Err(overflow),
+            "<<" => left << right,
+            ">>" => left >> right,
+            "&" => left & right,
+            "|" => left | right,
+            "^" => left ^ right,
+            // GAS: bitwise or not
+            "!" => left | !right,
+            // GAS: comparisons yield -1 for true
+            "==" => -((left == right) as i64),
+            "!=" | "<>" => -((left != right) as i64),
+            "<" => -((left < right) as i64),
+            ">" => -((left > right) as i64),
+            "<=" => -((left <= right) as i64),
+            ">=" => -((left >= right) as i64),
+            "&&" => (left != 0 && right != 0) as i64,
+            "||" => (left != 0 || right != 0) as i64,
+            _ => return Err(EvalError::NotConstant),
+        };
+        Ok(result)
+    }
+
+    /// 32-bit assemblers accept both signed and unsigned words.
+    fn check_width(&self, value: i64, range: Range) -> Result<i64, EvalError> {
+        if self.bits == 32 && !(i32::MIN as i64..=u32::MAX as i64).contains(&value) {
+            return Err(EvalError::Overflow(range));
+        }
+        Ok(value)
+    }
+}
+
+/// Returns the value node a constant is defined with.
+pub fn constant_value<'a>(ast: &'a Ast, constant: &Constant) -> Option<&'a ValueNode> {
+    match ast.items.get(constant.statement_index)? {
+        SyntaxNode::Assignment(node) => node.value(),
+        SyntaxNode::Directive(node) => node
+            .operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .nth(1),
+        _ => None,
+    }
+}
+
+/// MARS and SPIM only simulate 32-bit MIPS.
+fn word_bits(settings: &Settings) -> u32 {
+    match settings.dialect {
+        Dialect::Mars | Dialect::Spim => 32,
+        _ => 64,
+    }
+}
+
+/// Formats a value as hexadecimal and decimal, e.g. `0x2c (44)`.
+pub fn format_value(value: i64, bits: u32) -> String {
+    let hex = if bits == 32 {
+        value as u32 as u64
+    } else {
+        value as u64
+    };
+    format!("{:#x} ({})", hex, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    fn document(text: &str) -> Document {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+        doc
+    }
+
+    /// Evaluates the first operand of the last statement.
+    fn evaluate(text: &str, settings: &Settings) -> Result<i64, EvalError> {
+        let doc = document(text);
+        let index = doc.ast.items.len() - 1;
+        let operands = match &doc.ast.items[index] {
+            SyntaxNode::Instruction(node) => &node.operands,
+            SyntaxNode::Directive(node) => &node.operands,
+            _ => panic!("expected instruction or directive"),
+        };
+        let Some(OperandListItem::Operand(value)) = operands.first() else {
+            panic!("expected operand");
+        };
+        Evaluator::new(&doc, settings).evaluate(value, index)
+    }
+
+    #[test]
+    fn folds_expressions_with_constants() {
+        let settings = Settings::default();
+        assert_eq!(
+            evaluate(".eqv N, 10\n.word (4*N+3)&~3\n", &settings),
+            Ok(40)
+        );
+        assert_eq!(evaluate("N = 11\n.word (4*N+3)&~3\n", &settings), Ok(44));
+        assert_eq!(evaluate(".word 7 % 4 - -2 << 1\n", &settings), Ok(10));
+        assert_eq!(evaluate(".word 1 < 2\n", &settings), Ok(-1));
+        assert_eq!(evaluate(".word !0 && 3\n", &settings), Ok(1));
+        assert_eq!(
+            evaluate(".set X, 1\n.set X, X+1\n.word X\n", &settings),
+            Ok(2)
+        );
+        assert_eq!(
+            evaluate(".eqv A, B\n.eqv B, A\n.word A\n", &settings),
+            Err(EvalError::NotConstant)
+        );
+        assert_eq!(
+            evaluate(".word label + 4\n", &settings),
+            Err(EvalError::NotConstant)
+        );
+    }
+
+    #[test]
+    fn reports_division_by_zero_and_overflow() {
+        let mut settings = Settings::default();
+        assert!(matches!(
+            evaluate(".word 4 / (2 - 2)\n", &settings),
+            Err(EvalError::DivisionByZero(_))
+        ));
+        assert!(matches!(
+            evaluate(".word 1 << 64\n", &settings),
+            Err(EvalError::Overflow(_))
+        ));
+
+        settings.dialect = Dialect::Mars;
+        assert_eq!(evaluate(".word 0xffffffff\n", &settings), Ok(0xffffffff));
+        assert!(matches!(
+            evaluate(".word 0x10000 * 0x10000\n", &settings),
+            Err(EvalError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn formats_values() {
+        assert_eq!(format_value(44, 32), "0x2c (44)");
+        assert_eq!(format_value(-4, 32), "0xfffffffc (-4)");
+        assert_eq!(format_value(-1, 64), "0xffffffffffffffff (-1)");
+    }
+}
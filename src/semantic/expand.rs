@@ -0,0 +1,468 @@
+//! Expansion of pseudo-instructions into the machine instructions the assembler emits for them.
+//!
+//! MARS and SPIM build large constants in `$at`, GAS in the destination register. Addresses of
+//! labels are only known for MARS and SPIM; otherwise they are shown as `%hi(label)` and
+//! `%lo(label)`.
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{Dialect, LanguageDefinitions};
+use crate::operands::ValueClass;
+use crate::settings::Settings;
+
+use super::effects::{self, Location};
+use super::eval::Evaluator;
+use super::layout::{instruction_words, select_variant, Layout};
+
+const AT: &str = "$at";
+
+/// Loads and stores that accept a label or a large offset as address.
+const MEMORY: &[&str] = &[
+    "lb", "lbu", "lh", "lhu", "lw", "lwl", "lwr", "ld", "ll", "sb", "sh", "sw", "swl", "swr", "sd",
+    "sc", "lwc1", "ldc1", "swc1", "sdc1", "l.s", "l.d", "s.s", "s.d",
+];
+
+/// Register-immediate instructions and their register-register counterparts.
+const IMMEDIATE_OPERATIONS: &[(&str, &str)] = &[
+    ("addi", "add"),
+    ("addiu", "addu"),
+    ("andi", "and"),
+    ("ori", "or"),
+    ("xori", "xor"),
+    ("slti", "slt"),
+    ("sltiu", "sltu"),
+    ("daddi", "dadd"),
+    ("daddiu", "daddu"),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct Expansion {
+    pub instructions: Vec<String>,
+    /// whether the expansion needs the assembler temporary `$at`
+    pub uses_at: bool,
+}
+
+/// Address operand, either known or relative to a label.
+enum Address {
+    Known(i64),
+    Symbolic(String),
+}
+
+impl Address {
+    /// Upper half for an `ori` of the lower half.
+    fn upper(&self) -> String {
+        match self {
+            Address::Known(value) => immediate((value >> 16) & 0xffff),
+            Address::Symbolic(text) => format!("%hi({})", text),
+        }
+    }
+
+    fn lower(&self) -> String {
+        match self {
+            Address::Known(value) => immediate(value & 0xffff),
+            Address::Symbolic(text) => format!("%lo({})", text),
+        }
+    }
+
+    /// Upper half for a sign extended lower half, as used by offsets and `addiu`.
+    fn upper_signed(&self) -> String {
+        match self {
+            Address::Known(value) => immediate(((value + 0x8000) >> 16) & 0xffff),
+            Address::Symbolic(_) => self.upper(),
+        }
+    }
+
+    fn lower_signed(&self) -> String {
+        match self {
+            Address::Known(value) => (((value & 0xffff) ^ 0x8000) - 0x8000).to_string(),
+            Address::Symbolic(_) => self.lower(),
+        }
+    }
+}
+
+pub struct Expander<'a> {
+    doc: &'a Document,
+    definitions: &'a LanguageDefinitions,
+    dialect: Dialect,
+    evaluator: Evaluator<'a>,
+    /// resolves labels to their addresses if the layout is absolute
+    addresses: Evaluator<'a>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(
+        doc: &'a Document,
+        definitions: &'a LanguageDefinitions,
+        settings: &Settings,
+        layout: &'a Layout,
+    ) -> Expander<'a> {
+        let addresses = Evaluator::new(doc, settings);
+        Expander {
+            doc,
+            definitions,
+            dialect: settings.dialect,
+            evaluator: Evaluator::new(doc, settings),
+            addresses: if layout.absolute {
+                addresses.with_labels(&layout.labels)
+            } else {
+                addresses
+            },
+        }
+    }
+
+    /// Returns the machine instructions of a pseudo-instruction, or `None` for machine
+    /// instructions and pseudo-instructions whose expansion is not known.
+    pub fn expand(&self, instruction: usize) -> Option<Expansion> {
+        let text = &self.doc.text;
+        let model = &self.doc.semantic_model;
+        let statement = model.instructions[instruction].statement_index;
+        let Some(SyntaxNode::Instruction(node)) = self.doc.ast.items.get(statement) else {
+            return None;
+        };
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+        if model.macros.contains_key(mnemonic) {
+            return None;
+        }
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        let values: Vec<&ValueNode> = node
+            .operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+
+        let (_, native) = select_variant(
+            self.definitions,
+            &self.evaluator,
+            text,
+            &mnemonic,
+            &values,
+            statement,
+        )?;
+        let words = instruction_words(
+            self.definitions,
+            &self.evaluator,
+            text,
+            &mnemonic,
+            &values,
+            statement,
+        );
+        if native && words == 1 {
+            return None;
+        }
+
+        let operands: Vec<&str> = values
+            .iter()
+            .map(|value| get_text_in_ts_range(text, value.range()))
+            .collect();
+        let numbers: Vec<Option<i64>> = values
+            .iter()
+            .map(|value| match ValueClass::of(text, value) {
+                ValueClass::Integer => self.evaluator.evaluate(value, statement).ok(),
+                _ => None,
+            })
+            .collect();
+        let mut out = Vec::new();
+
+        match (mnemonic.as_str(), operands.as_slice(), numbers.as_slice()) {
+            ("li", [rd, _], [_, Some(value)]) => self.load(&mut out, rd, *value),
+            ("la", [rd, _], [_, Some(value)]) => self.load(&mut out, rd, *value),
+            ("la", [rd, _], _) => {
+                let ValueNode::ParenthesizedExpression { head, body, .. } = values[1] else {
+                    let address = self.address(values[1], statement);
+                    match self.dialect {
+                        Dialect::Gas | Dialect::Unspecified => {
+                            out.push(format!("lui {}, {}", rd, address.upper_signed()));
+                            out.push(format!("addiu {}, {}, {}", rd, rd, address.lower_signed()));
+                        }
+                        Dialect::Mars | Dialect::Spim => {
+                            out.push(format!("lui {}, {}", AT, address.upper()));
+                            out.push(format!("ori {}, {}, {}", rd, AT, address.lower()));
+                        }
+                    }
+                    return Some(finish(out, &operands));
+                };
+                let base = match body.as_slice() {
+                    [OperandListItem::Operand(base)] => get_text_in_ts_range(text, base.range()),
+                    _ => return None,
+                };
+                let offset = head.as_ref().map_or(Some(0), |head| {
+                    self.evaluator.evaluate(head, statement).ok()
+                })?;
+                out.push(format!("addiu {}, {}, {}", rd, base, offset));
+            }
+            ("abs", [rd, rs], _) => {
+                out.push(format!("sra {}, {}, 31", AT, rs));
+                out.push(format!("xor {}, {}, {}", rd, rs, AT));
+                out.push(format!("subu {}, {}, {}", rd, rd, AT));
+            }
+            ("b", [label], _) => out.push(format!("beq $zero, $zero, {}", label)),
+            ("beqz", [rs, label], _) => out.push(format!("beq {}, $zero, {}", rs, label)),
+            ("bnez", [rs, label], _) => out.push(format!("bne {}, $zero, {}", rs, label)),
+            ("beq" | "bne", [rs, _, label], [_, Some(value), _]) => {
+                let rt = self.register_for(&mut out, *value);
+                out.push(format!("{} {}, {}, {}", mnemonic, rs, rt, label));
+            }
+            (
+                "blt" | "bltu" | "bgt" | "bgtu" | "ble" | "bleu" | "bge" | "bgeu",
+                [rs, rt, label],
+                [_, number, _],
+            ) => {
+                let unsigned = if mnemonic.ends_with('u') { "u" } else { "" };
+                let (less_than, taken) = match &mnemonic[..3] {
+                    "blt" => (true, "bne"),
+                    "bge" => (true, "beq"),
+                    "bgt" => (false, "bne"),
+                    _ => (false, "beq"),
+                };
+                // `bgt rs, 5` is `bge rs, 6`, which compares with `slti`
+                let comparison = number.map(|value| if less_than { value } else { value + 1 });
+                match comparison {
+                    Some(value) if fits_signed_16(value) => {
+                        let taken = if less_than { taken } else { invert(taken) };
+                        out.push(format!("slti{} {}, {}, {}", unsigned, AT, rs, value));
+                        out.push(format!("{} {}, $zero, {}", taken, AT, label));
+                    }
+                    _ => {
+                        let rt = match number {
+                            Some(value) => {
+                                self.load(&mut out, AT, *value);
+                                AT
+                            }
+                            None => rt,
+                        };
+                        let (left, right) = if less_than { (*rs, rt) } else { (rt, *rs) };
+                        out.push(format!("slt{} {}, {}, {}", unsigned, AT, left, right));
+                        out.push(format!("{} {}, $zero, {}", taken, AT, label));
+                    }
+                }
+            }
+            ("mul", [rd, rs, _], [_, _, Some(value)]) => {
+                let rt = self.register_for(&mut out, *value);
+                let native_mul = self
+                    .definitions
+                    .instructions
+                    .get("mul")
+                    .is_some_and(|mul| mul.variants.iter().any(|variant| !variant.pseudo));
+                if native_mul {
+                    out.push(format!("mul {}, {}, {}", rd, rs, rt));
+                } else {
+                    out.push(format!("mult {}, {}", rs, rt));
+                    out.push(format!("mflo {}", rd));
+                }
+            }
+            ("mul", [rd, rs, rt], _) => {
+                out.push(format!("mult {}, {}", rs, rt));
+                out.push(format!("mflo {}", rd));
+            }
+            ("div" | "divu" | "rem" | "remu", [rd, rs, rt], [_, _, number]) => {
+                let divide = if mnemonic.ends_with('u') {
+                    "divu"
+                } else {
+                    "div"
+                };
+                let result = if mnemonic.starts_with("rem") {
+                    "mfhi"
+                } else {
+                    "mflo"
+                };
+                let rt = match number {
+                    Some(value) => self.register_for(&mut out, *value),
+                    None => {
+                        // Division by zero traps
+                        out.push(format!("bne {}, $zero, 1f", rt));
+                        out.push("break".to_string());
+                        rt
+                    }
+                };
+                out.push(format!("{} {}, {}", divide, rs, rt));
+                out.push(format!("{} {}", result, rd));
+            }
+            (_, [rd, rs, _], [_, _, Some(value)]) => {
+                let (immediate_form, register_form) = match IMMEDIATE_OPERATIONS
+                    .iter()
+                    .find(|(immediate, register)| *immediate == mnemonic || *register == mnemonic)
+                {
+                    Some(operations) => *operations,
+                    None if mnemonic == "sub" || mnemonic == "subu" => {
+                        let add = if mnemonic == "sub" { "addi" } else { "addiu" };
+                        out.push(format!("{} {}, {}, {}", add, rd, rs, -value));
+                        return Some(finish(out, &operands));
+                    }
+                    None => return None,
+                };
+                let logical = matches!(immediate_form, "andi" | "ori" | "xori");
+                let fits = if logical {
+                    (0..=0xffff).contains(value)
+                } else {
+                    fits_signed_16(*value)
+                };
+                if fits {
+                    out.push(format!("{} {}, {}, {}", immediate_form, rd, rs, value));
+                } else {
+                    self.load(&mut out, AT, *value);
+                    out.push(format!("{} {}, {}, {}", register_form, rd, rs, AT));
+                }
+            }
+            (_, [rt, _], _) if MEMORY.contains(&mnemonic.as_str()) => {
+                let (offset, base) = match values[1] {
+                    ValueNode::ParenthesizedExpression { head, body, .. } => {
+                        let base = match body.as_slice() {
+                            [OperandListItem::Operand(base)] => {
+                                get_text_in_ts_range(text, base.range())
+                            }
+                            _ => return None,
+                        };
+                        (head.as_deref()?, Some(base))
+                    }
+                    value => (value, None),
+                };
+                let address = self.address(offset, statement);
+                out.push(format!("lui {}, {}", AT, address.upper_signed()));
+                if let Some(base) = base {
+                    out.push(format!("addu {}, {}, {}", AT, AT, base));
+                }
+                out.push(format!(
+                    "{} {}, {}({})",
+                    mnemonic,
+                    rt,
+                    address.lower_signed(),
+                    AT
+                ));
+            }
+            _ => return None,
+        }
+
+        Some(finish(out, &operands))
+    }
+
+    /// Emits the instructions loading a constant into a register.
+    fn load(&self, out: &mut Vec<String>, register: &str, value: i64) {
+        let address = Address::Known(value);
+        if fits_signed_16(value) {
+            out.push(format!("addiu {}, $zero, {}", register, value));
+        } else if (0..=0xffff).contains(&value) {
+            out.push(format!("ori {}, $zero, {}", register, immediate(value)));
+        } else if value & 0xffff == 0 {
+            out.push(format!("lui {}, {}", register, address.upper()));
+        } else {
+            let temporary = match self.dialect {
+                Dialect::Mars | Dialect::Spim => AT,
+                Dialect::Gas | Dialect::Unspecified => register,
+            };
+            out.push(format!("lui {}, {}", temporary, address.upper()));
+            out.push(format!(
+                "ori {}, {}, {}",
+                register,
+                temporary,
+                address.lower()
+            ));
+        }
+    }
+
+    /// Returns the register holding a constant operand: `$zero` for 0, else `$at` loaded with it.
+    fn register_for(&self, out: &mut Vec<String>, value: i64) -> &'static str {
+        if value == 0 {
+            return "$zero";
+        }
+        self.load(out, AT, value);
+        AT
+    }
+
+    fn address(&self, value: &ValueNode, statement: usize) -> Address {
+        match self.addresses.evaluate(value, statement) {
+            Ok(address) => Address::Known(address),
+            Err(_) => Address::Symbolic(get_text_in_ts_range(&self.doc.text, value.range()).into()),
+        }
+    }
+}
+
+fn finish(instructions: Vec<String>, operands: &[&str]) -> Expansion {
+    // `$at` written as operand is the user's own use
+    let explicit = operands
+        .iter()
+        .any(|operand| effects::location(operand) == Some(Location::Gpr(1)));
+    Expansion {
+        uses_at: !explicit && instructions.iter().any(|line| line.contains(AT)),
+        instructions,
+    }
+}
+
+fn invert(branch: &str) -> &'static str {
+    if branch == "beq" {
+        "bne"
+    } else {
+        "beq"
+    }
+}
+
+fn immediate(value: i64) -> String {
+    if (0..10).contains(&value) {
+        value.to_string()
+    } else {
+        format!("{:#x}", value)
+    }
+}
+
+fn fits_signed_16(value: i64) -> bool {
+    (-0x8000..=0x7fff).contains(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    fn expansions(text: &str, dialect: Dialect) -> Vec<Option<Vec<String>>> {
+        let mut settings = Settings::default();
+        settings.dialect = dialect;
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+        let layout = Layout::compute(&doc, &definitions, &settings);
+        let expander = Expander::new(&doc, &definitions, &settings, &layout);
+        (0..doc.semantic_model.instructions.len())
+            .map(|instruction| {
+                expander
+                    .expand(instruction)
+                    .map(|expansion| expansion.instructions)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expands_pseudo_instructions() {
+        let text = ".data\nx: .word 0, 0\n.text\nmain:\n  li $t0, 0x12345678\n  la $a0, x\n  lw $t1, x+4\n  blt $t0, $t1, main\n  bgt $t0, 5, main\n  addi $t0, $t0, 100000\n  beqz $t0, main\n  addu $t2, $t0, $t1\n";
+        let lines = |lines: &[&str]| Some(lines.iter().map(|l| l.to_string()).collect());
+        assert_eq!(
+            expansions(text, Dialect::Mars),
+            vec![
+                lines(&["lui $at, 0x1234", "ori $t0, $at, 0x5678"]),
+                lines(&["lui $at, 0x1001", "ori $a0, $at, 0"]),
+                lines(&["lui $at, 0x1001", "lw $t1, 4($at)"]),
+                lines(&["slt $at, $t0, $t1", "bne $at, $zero, main"]),
+                lines(&["slti $at, $t0, 6", "beq $at, $zero, main"]),
+                lines(&["lui $at, 1", "ori $at, $at, 0x86a0", "add $t0, $t0, $at"]),
+                lines(&["beq $t0, $zero, main"]),
+                None,
+            ]
+        );
+        assert_eq!(
+            expansions(text, Dialect::Gas)[..3],
+            vec![
+                lines(&["lui $t0, 0x1234", "ori $t0, $t0, 0x5678"]),
+                lines(&["lui $a0, %hi(x)", "addiu $a0, $a0, %lo(x)"]),
+                lines(&["lui $at, %hi(x+4)", "lw $t1, %lo(x+4)($at)"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Stack frames of routines: adjustments of `$sp`, registers saved on the stack and changes to
+//! the registers a routine has to preserve.
+
+use std::collections::HashMap;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::settings::Settings;
+
+use super::cfg::Cfg;
+use super::effects::{self, instruction_effects, Location};
+use super::eval::Evaluator;
+use super::liveness::LocationSet;
+
+pub const SP: Location = Location::Gpr(29);
+pub const FP: Location = Location::Gpr(30);
+pub const RA: Location = Location::Gpr(31);
+
+/// Loads and stores of whole registers, which save and restore them on the stack.
+const LOADS: &[&str] = &["lw", "ld", "lwc1", "ldc1", "l.s", "l.d"];
+const STORES: &[&str] = &["sw", "sd", "swc1", "sdc1", "s.s", "s.d"];
+
+/// Value of `$sp` relative to its value when the routine was entered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackPointer {
+    Offset {
+        offset: i64,
+        /// instruction that adjusted `$sp` last
+        adjusted: Option<usize>,
+    },
+    /// `$sp` is written in a way that is not tracked, or adjusted differently on different paths
+    Unknown(usize),
+}
+
+/// Load or store of a register at a constant offset from `$sp` or `$fp`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackAccess {
+    pub load: bool,
+    pub location: Location,
+    /// offset relative to `$sp` on entry
+    pub slot: i64,
+}
+
+/// State of the frame of a routine at some point in the routine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameState {
+    /// modified registers from the set passed to `step`, with the instruction that modified them
+    /// last
+    pub modified: HashMap<Location, usize>,
+    pub sp: StackPointer,
+    /// offset of `$sp` copied to `$fp`
+    pub fp: Option<i64>,
+    /// slots stored on some path, with the register and instruction that stored them first
+    pub stored: HashMap<i64, (Location, usize)>,
+}
+
+impl FrameState {
+    pub fn entry() -> FrameState {
+        FrameState {
+            modified: HashMap::new(),
+            sp: StackPointer::Offset {
+                offset: 0,
+                adjusted: None,
+            },
+            fp: None,
+            stored: HashMap::new(),
+        }
+    }
+
+    /// Combines the states of two paths.
+    fn merge(&self, other: &FrameState) -> FrameState {
+        let mut modified = self.modified.clone();
+        for (location, instruction) in &other.modified {
+            modified
+                .entry(*location)
+                .and_modify(|existing| *existing = (*existing).min(*instruction))
+                .or_insert(*instruction);
+        }
+        let mut stored = self.stored.clone();
+        for (slot, store) in &other.stored {
+            stored
+                .entry(*slot)
+                .and_modify(|existing| {
+                    if store.1 < existing.1 {
+                        *existing = *store;
+                    }
+                })
+                .or_insert(*store);
+        }
+        let sp = match (self.sp, other.sp) {
+            (
+                StackPointer::Offset { offset, adjusted },
+                StackPointer::Offset {
+                    offset: other_offset,
+                    adjusted: other_adjusted,
+                },
+            ) => {
+                let adjusted = match (adjusted, other_adjusted) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                if offset == other_offset {
+                    StackPointer::Offset { offset, adjusted }
+                } else {
+                    StackPointer::Unknown(adjusted.unwrap_or_default())
+                }
+            }
+            (StackPointer::Unknown(a), StackPointer::Unknown(b)) => StackPointer::Unknown(a.min(b)),
+            (StackPointer::Unknown(a), _) | (_, StackPointer::Unknown(a)) => {
+                StackPointer::Unknown(a)
+            }
+        };
+        FrameState {
+            modified,
+            sp,
+            fp: self.fp.filter(|fp| other.fp == Some(*fp)),
+            stored,
+        }
+    }
+
+    pub fn sp_offset(&self) -> Option<i64> {
+        match self.sp {
+            StackPointer::Offset { offset, .. } => Some(offset),
+            StackPointer::Unknown(_) => None,
+        }
+    }
+
+    fn offset_of(&self, text: &str, base: &ValueNode) -> Option<i64> {
+        match effects::location(get_text_in_ts_range(text, base.range()))? {
+            SP => self.sp_offset(),
+            FP => self.fp,
+            _ => None,
+        }
+    }
+
+    /// Applies an instruction to the state and returns the stack slot it accesses. Changes to
+    /// registers in `preserved` are recorded in `modified`. Macros are ignored.
+    pub fn step(
+        &mut self,
+        doc: &Document,
+        evaluator: &Evaluator,
+        instruction: usize,
+        preserved: LocationSet,
+    ) -> Option<StackAccess> {
+        let text = &doc.text;
+        let model = &doc.semantic_model;
+        let statement = model.instructions[instruction].statement_index;
+        let Some(SyntaxNode::Instruction(node)) = doc.ast.items.get(statement) else {
+            return None;
+        };
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+        if model.macros.contains_key(mnemonic) {
+            return None;
+        }
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        let operands: Vec<&ValueNode> = node
+            .operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+        let effects = instruction_effects(text, &mnemonic, &operands);
+
+        // New offset of `$sp` or `$fp` for `addiu $sp, $sp, -8`, `subu $sp, $sp, 8` or
+        // `move $sp, $fp`
+        let offset = match (mnemonic.as_str(), operands.as_slice()) {
+            ("move", [_, base]) => self.offset_of(text, base),
+            (
+                "addiu" | "addi" | "addu" | "add" | "daddiu" | "daddi" | "daddu" | "dadd",
+                [_, base, value],
+            ) if !matches!(value, ValueNode::Register { .. }) => self
+                .offset_of(text, base)
+                .zip(evaluator.evaluate(value, statement).ok())
+                .map(|(offset, value)| offset + value),
+            ("subu" | "sub" | "dsubu" | "dsub", [_, base, value])
+                if !matches!(value, ValueNode::Register { .. }) =>
+            {
+                self.offset_of(text, base)
+                    .zip(evaluator.evaluate(value, statement).ok())
+                    .map(|(offset, value)| offset - value)
+            }
+            _ => None,
+        };
+
+        // `sw $s0, 4($sp)` saves a register on the stack, `lw $s0, 4($sp)` restores it
+        let load = LOADS.contains(&mnemonic.as_str());
+        let access = match operands.as_slice() {
+            [ValueNode::Register { range }, ValueNode::ParenthesizedExpression { head, body, .. }]
+                if load || STORES.contains(&mnemonic.as_str()) =>
+            {
+                let [OperandListItem::Operand(base)] = body.as_slice() else {
+                    return None;
+                };
+                let displacement = match head {
+                    Some(head) => evaluator.evaluate(head, statement).ok(),
+                    None => Some(0),
+                };
+                effects::location(get_text_in_ts_range(text, *range))
+                    .zip(self.offset_of(text, base).zip(displacement))
+                    .map(|(location, (base, displacement))| StackAccess {
+                        load,
+                        location,
+                        slot: base + displacement,
+                    })
+            }
+            _ => None,
+        };
+        let restores = load
+            && matches!(operands.last(), Some(ValueNode::ParenthesizedExpression { body, .. })
+                if matches!(body.as_slice(), [OperandListItem::Operand(base)]
+                    if matches!(effects::location(get_text_in_ts_range(text, base.range())), Some(SP | FP))));
+
+        if let Some(access) = access.filter(|access| !access.load) {
+            self.stored
+                .entry(access.slot)
+                .or_insert((access.location, instruction));
+        }
+        for location in effects.writes {
+            match location {
+                SP => {
+                    self.sp = match offset {
+                        Some(offset) => StackPointer::Offset {
+                            offset,
+                            adjusted: Some(instruction),
+                        },
+                        None => StackPointer::Unknown(instruction),
+                    };
+                }
+                _ if !preserved.contains(location) => {}
+                _ if restores => {
+                    self.modified.remove(&location);
+                }
+                _ => {
+                    self.modified.insert(location, instruction);
+                }
+            }
+            if location == FP {
+                self.fp = if restores { None } else { offset };
+            }
+        }
+        access
+    }
+}
+
+/// Saved register in the frame of a routine.
+#[derive(Debug, PartialEq)]
+pub struct Slot {
+    /// offset from `$sp` after the frame is allocated
+    pub offset: i64,
+    pub location: Location,
+    pub instruction: usize,
+}
+
+pub struct Frame {
+    /// state at the start of each block reached from the entry of the routine
+    pub entries: HashMap<usize, FrameState>,
+    /// state at the end of each block reached from the entry of the routine
+    pub exits: HashMap<usize, FrameState>,
+    /// bytes `$sp` is lowered by at most
+    pub size: i64,
+    /// registers saved in the frame, ordered by offset
+    pub slots: Vec<Slot>,
+}
+
+impl Frame {
+    /// Follows the frame through the blocks of a routine. Blocks only reached from other routines
+    /// are left out.
+    pub fn compute(
+        doc: &Document,
+        settings: &Settings,
+        cfg: &Cfg,
+        routine: usize,
+        preserved: LocationSet,
+    ) -> Frame {
+        let evaluator = Evaluator::new(doc, settings);
+        let routine = &cfg.routines[routine];
+        let predecessors = cfg.predecessors();
+        let mut entries: HashMap<usize, FrameState> = HashMap::new();
+        let mut exits: HashMap<usize, FrameState> = HashMap::new();
+
+        let mut lowest = 0;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &routine.blocks {
+                let incoming = predecessors[block]
+                    .iter()
+                    .filter(|predecessor| routine.blocks.contains(predecessor))
+                    .filter_map(|predecessor| exits.get(predecessor));
+                let entry = (block == routine.entry).then(FrameState::entry);
+                let Some(start) = entry
+                    .into_iter()
+                    .chain(incoming.cloned())
+                    .reduce(|state, other| state.merge(&other))
+                else {
+                    continue;
+                };
+                let mut state = start.clone();
+                for &instruction in &cfg.blocks[block].instructions {
+                    state.step(doc, &evaluator, instruction, preserved);
+                    lowest = lowest.min(state.sp_offset().unwrap_or_default());
+                }
+                entries.insert(block, start);
+                if exits.get(&block) != Some(&state) {
+                    exits.insert(block, state);
+                    changed = true;
+                }
+            }
+        }
+
+        let size = -lowest;
+        let mut slots: Vec<Slot> = exits
+            .values()
+            .flat_map(|state| &state.stored)
+            .filter(|(slot, _)| **slot < 0)
+            .map(|(slot, (location, instruction))| Slot {
+                offset: slot + size,
+                location: *location,
+                instruction: *instruction,
+            })
+            .collect();
+        slots.sort_by_key(|slot| (slot.offset, slot.instruction));
+        slots.dedup_by_key(|slot| slot.offset);
+
+        Frame {
+            entries,
+            exits,
+            size,
+            slots,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    #[test]
+    fn tracks_stack_pointer_and_saved_registers() {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            "f:\n  addiu $sp, $sp, -8\n  sw $ra, 4($sp)\n  sw $s0, 0($sp)\n  move $s0, $a0\n  beqz $a0, 1f\n  lw $s0, 0($sp)\n1:\n  jr $ra\n".to_string(),
+        );
+        doc.update_model();
+        let settings = Settings::default();
+        let cfg = Cfg::build(&doc, &settings);
+
+        let preserved = LocationSet::of(&[Location::Gpr(16)]);
+        let frame = Frame::compute(&doc, &settings, &cfg, 0, preserved);
+        let end = &frame.exits[&2];
+        assert_eq!(
+            end.sp,
+            StackPointer::Offset {
+                offset: -8,
+                adjusted: Some(0)
+            }
+        );
+        assert_eq!(end.modified, HashMap::from([(Location::Gpr(16), 3)]));
+        assert!(frame.exits[&1].modified.is_empty());
+
+        assert_eq!(frame.size, 8);
+        assert_eq!(
+            frame.slots,
+            vec![
+                Slot {
+                    offset: 0,
+                    location: Location::Gpr(16),
+                    instruction: 2
+                },
+                Slot {
+                    offset: 4,
+                    location: Location::Gpr(31),
+                    instruction: 1
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,671 @@
+//! Assignment of addresses to statements and labels.
+//!
+//! MARS and SPIM place sections at fixed addresses of their memory map; for other dialects the
+//! addresses are offsets relative to the start of each section, as the linker decides the rest.
+
+use std::collections::HashMap;
+
+use smol_str::{SmolStr, ToSmolStr};
+
+use crate::ast::*;
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{Dialect, InstructionVariant, LanguageDefinitions};
+use crate::operands::{self, OperandKind, ValueClass};
+use crate::settings::Settings;
+
+use super::eval::Evaluator;
+use super::{is_macro_end, parse_section, Section};
+
+pub struct Layout {
+    /// address of every label
+    pub labels: HashMap<SmolStr, i64>,
+    /// placement of every statement that emits code or data or defines a label
+    pub statements: Vec<Option<Placement>>,
+    /// sections in order of first use
+    pub sections: Vec<SectionLayout>,
+    /// whether addresses are absolute or relative to their section
+    pub absolute: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub section: Section,
+    pub address: i64,
+    pub size: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionLayout {
+    pub section: Section,
+    pub start: i64,
+    pub size: i64,
+}
+
+/// Location counter of a section.
+struct Counter {
+    start: i64,
+    location: i64,
+    end: i64,
+}
+
+struct LayoutBuilder<'a> {
+    doc: &'a Document,
+    definitions: &'a LanguageDefinitions,
+    settings: &'a Settings,
+    evaluator: Evaluator<'a>,
+    layout: Layout,
+    counters: HashMap<Section, Counter>,
+    section: Section,
+    /// `.align 0` turns off the automatic alignment of data until the next section directive
+    auto_align: bool,
+    /// labels waiting for the address of the next statement
+    pending_labels: Vec<usize>,
+    macro_words: HashMap<usize, i64>,
+}
+
+impl Layout {
+    pub fn compute(
+        doc: &Document,
+        definitions: &LanguageDefinitions,
+        settings: &Settings,
+    ) -> Layout {
+        let mut builder = LayoutBuilder {
+            doc,
+            definitions,
+            settings,
+            evaluator: Evaluator::new(doc, settings),
+            layout: Layout {
+                labels: HashMap::new(),
+                statements: vec![None; doc.ast.items.len()],
+                sections: Vec::new(),
+                absolute: section_base(settings.dialect, Section::Text).is_some(),
+            },
+            counters: HashMap::new(),
+            section: Section::Text,
+            auto_align: true,
+            pending_labels: Vec::new(),
+            macro_words: HashMap::new(),
+        };
+        builder.run();
+        builder.layout
+    }
+
+    pub fn label(&self, name: &str) -> Option<i64> {
+        self.labels.get(name).copied()
+    }
+}
+
+/// Start addresses of the default memory maps of MARS and SPIM.
+pub fn section_base(dialect: Dialect, section: Section) -> Option<i64> {
+    match (dialect, section) {
+        (Dialect::Mars | Dialect::Spim, Section::Text) => Some(0x0040_0000),
+        (Dialect::Mars, Section::Data) => Some(0x1001_0000),
+        (Dialect::Spim, Section::Data) => Some(0x1000_0000),
+        (Dialect::Mars | Dialect::Spim, Section::KText) => Some(0x8000_0000),
+        (Dialect::Mars | Dialect::Spim, Section::KData) => Some(0x9000_0000),
+        _ => None,
+    }
+}
+
+/// Returns the size and natural alignment of one value emitted by a data directive.
+pub fn data_size(directive_mnemonic: &str) -> Option<i64> {
+    match directive_mnemonic {
+        ".byte" => Some(1),
+        ".half" | ".hword" | ".short" | ".2byte" => Some(2),
+        ".word" | ".int" | ".long" | ".4byte" | ".float" | ".single" => Some(4),
+        ".dword" | ".quad" | ".8byte" | ".double" => Some(8),
+        _ => None,
+    }
+}
+
+impl LayoutBuilder<'_> {
+    fn run(&mut self) {
+        let text = &self.doc.text;
+        let model = &self.doc.semantic_model;
+        self.enter_section(Section::Text);
+
+        for (index, statement) in self.doc.ast.items.iter().enumerate() {
+            // Macro bodies are laid out where they are expanded
+            if model.macro_body[index] {
+                continue;
+            }
+            match statement {
+                SyntaxNode::Label(_) => self.pending_labels.push(index),
+                SyntaxNode::Directive(node) => {
+                    let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+                    self.directive(index, &mnemonic.to_ascii_lowercase(), &node.operands);
+                }
+                SyntaxNode::Instruction(node) => {
+                    let words = self.instruction_words(index, node);
+                    self.align(4);
+                    self.emit(index, words * 4);
+                }
+                SyntaxNode::MacroInvocation(node) => {
+                    let name = get_text_in_ts_range(text, node.mnemonic.range);
+                    let words = model
+                        .macros
+                        .get(name)
+                        .map_or(0, |definition| self.macro_words(definition.statement_index));
+                    self.align(4);
+                    self.emit(index, words * 4);
+                }
+                _ => {}
+            }
+        }
+        self.place_labels();
+
+        for section in &mut self.layout.sections {
+            let counter = &self.counters[&section.section];
+            section.size = counter.end - counter.start;
+        }
+    }
+
+    fn directive(&mut self, index: usize, mnemonic: &str, operands: &[OperandListItem]) {
+        let values: Vec<&ValueNode> = operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+        let first = values
+            .first()
+            .and_then(|value| self.evaluator.evaluate(value, index).ok());
+
+        if let Some(section) = parse_section(mnemonic) {
+            self.place_labels();
+            self.enter_section(section);
+            self.auto_align = true;
+            // MARS and SPIM accept a start address, e.g. `.data 0x10010100`
+            if self.layout.absolute {
+                if let Some(address) = first {
+                    self.counter().location = address;
+                }
+            }
+            return;
+        }
+
+        match mnemonic {
+            ".align" | ".p2align" => {
+                let Some(power) = first else {
+                    return;
+                };
+                self.place_labels();
+                if power == 0 {
+                    self.auto_align = false;
+                }
+                self.align(1 << power.clamp(0, 16));
+            }
+            ".balign" => {
+                if let Some(bytes) = first {
+                    self.place_labels();
+                    self.align(bytes.clamp(1, 1 << 16));
+                }
+            }
+            ".space" | ".skip" => {
+                let size = first.unwrap_or(0);
+                self.emit(index, size.max(0));
+            }
+            ".ascii" | ".asciiz" | ".asciz" | ".string" => {
+                let terminator = (mnemonic != ".ascii") as i64;
+                let size = values
+                    .iter()
+                    .map(|value| match value {
+                        ValueNode::String { range, .. } => {
+                            string_length(get_text_in_ts_range(&self.doc.text, *range)) + terminator
+                        }
+                        _ => 0,
+                    })
+                    .sum();
+                self.emit(index, size);
+            }
+            _ => {
+                let Some(size) = data_size(mnemonic) else {
+                    return;
+                };
+                if self.auto_align {
+                    self.align(size);
+                }
+                self.emit(index, size * values.len() as i64);
+            }
+        }
+    }
+
+    fn enter_section(&mut self, section: Section) {
+        self.section = section;
+        if !self.counters.contains_key(&section) {
+            let start = section_base(self.settings.dialect, section).unwrap_or(0);
+            self.counters.insert(
+                section,
+                Counter {
+                    start,
+                    location: start,
+                    end: start,
+                },
+            );
+            self.layout.sections.push(SectionLayout {
+                section,
+                start,
+                size: 0,
+            });
+        }
+    }
+
+    fn counter(&mut self) -> &mut Counter {
+        self.counters
+            .get_mut(&self.section)
+            .expect("section is entered before use")
+    }
+
+    fn align(&mut self, bytes: i64) {
+        let counter = self.counter();
+        let misalignment = counter.location.rem_euclid(bytes);
+        if misalignment != 0 {
+            counter.location += bytes - misalignment;
+            counter.end = counter.end.max(counter.location);
+        }
+    }
+
+    /// Places the statement and pending labels at the current location and advances it.
+    fn emit(&mut self, index: usize, size: i64) {
+        self.place_labels();
+        let section = self.section;
+        let counter = self.counter();
+        let address = counter.location;
+        counter.location += size;
+        counter.end = counter.end.max(counter.location);
+        self.layout.statements[index] = Some(Placement {
+            section,
+            address,
+            size,
+        });
+    }
+
+    fn place_labels(&mut self) {
+        let section = self.section;
+        let address = self.counter().location;
+        for index in std::mem::take(&mut self.pending_labels) {
+            let Some(SyntaxNode::Label(node)) = self.doc.ast.items.get(index) else {
+                continue;
+            };
+            self.layout.statements[index] = Some(Placement {
+                section,
+                address,
+                size: 0,
+            });
+            if matches!(node.kind, LabelKind::Numeric) {
+                continue;
+            }
+            let name = get_text_in_ts_range(&self.doc.text, node.name.range).to_smolstr();
+            self.layout.labels.entry(name).or_insert(address);
+        }
+    }
+
+    /// Sums up the instructions of a macro body, assuming arguments that fit into a single
+    /// instruction.
+    fn macro_words(&mut self, definition_index: usize) -> i64 {
+        if let Some(words) = self.macro_words.get(&definition_index) {
+            return *words;
+        }
+        // Guards against recursive macros
+        self.macro_words.insert(definition_index, 0);
+
+        let text = &self.doc.text;
+        let model = &self.doc.semantic_model;
+        let mut words = 0;
+        for index in definition_index + 1..self.doc.ast.items.len() {
+            match &self.doc.ast.items[index] {
+                SyntaxNode::Directive(node)
+                    if is_macro_end(get_text_in_ts_range(text, node.mnemonic.range)) =>
+                {
+                    break
+                }
+                SyntaxNode::Instruction(node) => words += self.instruction_words(index, node),
+                SyntaxNode::MacroInvocation(node) => {
+                    if let Some(definition) = model
+                        .macros
+                        .get(get_text_in_ts_range(text, node.mnemonic.range))
+                    {
+                        words += self.macro_words(definition.statement_index);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.macro_words.insert(definition_index, words);
+        words
+    }
+
+    /// Returns the number of machine instructions an instruction statement assembles to.
+    fn instruction_words(&mut self, index: usize, node: &InstructionNode) -> i64 {
+        let text = &self.doc.text;
+        let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+        // GAS macros are called like instructions
+        if let Some(definition) = self.doc.semantic_model.macros.get(mnemonic) {
+            return self.macro_words(definition.statement_index);
+        }
+        let values: Vec<&ValueNode> = node
+            .operands
+            .iter()
+            .filter_map(|item| match item {
+                OperandListItem::Operand(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+        instruction_words(
+            self.definitions,
+            &self.evaluator,
+            text,
+            &mnemonic.to_ascii_lowercase(),
+            &values,
+            index,
+        )
+    }
+}
+
+/// Returns the variant an instruction is assembled with and whether it is a machine instruction
+/// whose immediates fit: the first such variant, or else the first variant whose immediates fit,
+/// or else the first one accepting the operands.
+pub fn select_variant<'a>(
+    definitions: &'a LanguageDefinitions,
+    evaluator: &Evaluator,
+    text: &str,
+    mnemonic: &str,
+    values: &[&ValueNode],
+    statement_index: usize,
+) -> Option<(&'a InstructionVariant, bool)> {
+    let definition = definitions.instructions.get(mnemonic)?;
+    let classes: Vec<ValueClass> = values
+        .iter()
+        .map(|value| ValueClass::of(text, value))
+        .collect();
+    let numbers: Vec<Option<i64>> = values
+        .iter()
+        .map(|value| evaluator.evaluate(value, statement_index).ok())
+        .collect();
+    let fits = |variant: &InstructionVariant| {
+        variant.operands.iter().zip(&numbers).all(|(spec, number)| {
+            match (OperandKind::parse(spec).value_range(), number) {
+                (Some((min, max)), Some(number)) => (min..=max).contains(number),
+                _ => true,
+            }
+        })
+    };
+    let mut candidates: Vec<&InstructionVariant> = definition
+        .variants
+        .iter()
+        .filter(|variant| operands::variant_accepts(variant, &classes))
+        .collect();
+    // Variants that only differ in their operand names, like the release 6 `addiu $rs, $rt,
+    // offset`, may lack an encoding
+    candidates.sort_by_key(|variant| variant.encoding.is_none() && variant.encoding_r6.is_none());
+
+    if let Some(native) = candidates
+        .iter()
+        .find(|variant| !variant.pseudo && fits(variant))
+    {
+        return Some((native, true));
+    }
+    candidates
+        .iter()
+        .find(|variant| fits(variant))
+        .or(candidates.first())
+        .map(|variant| (*variant, false))
+}
+
+/// Returns the number of machine instructions an instruction assembles to, following the
+/// expansions of MARS and GAS for pseudo-instructions.
+pub fn instruction_words(
+    definitions: &LanguageDefinitions,
+    evaluator: &Evaluator,
+    text: &str,
+    mnemonic: &str,
+    values: &[&ValueNode],
+    statement_index: usize,
+) -> i64 {
+    let Some((variant, native)) = select_variant(
+        definitions,
+        evaluator,
+        text,
+        mnemonic,
+        values,
+        statement_index,
+    ) else {
+        return 1;
+    };
+    if native {
+        return variant
+            .operands
+            .iter()
+            .zip(values)
+            .filter(|(spec, _)| OperandKind::parse(spec) == OperandKind::Memory)
+            .map(|(_, value)| memory_words(evaluator, text, value, statement_index))
+            .max()
+            .unwrap_or(1);
+    }
+
+    let classes: Vec<ValueClass> = values
+        .iter()
+        .map(|value| ValueClass::of(text, value))
+        .collect();
+    let numbers: Vec<Option<i64>> = values
+        .iter()
+        .map(|value| evaluator.evaluate(value, statement_index).ok())
+        .collect();
+    let kinds: Vec<OperandKind> = variant
+        .operands
+        .iter()
+        .map(|spec| OperandKind::parse(spec))
+        .collect();
+    let immediate = kinds
+        .iter()
+        .zip(&numbers)
+        .find(|(kind, _)| matches!(kind, OperandKind::Immediate { bits: 17.., .. }))
+        .map(|(_, number)| *number);
+    let has_label = kinds
+        .iter()
+        .zip(&classes)
+        .any(|(kind, class)| *kind == OperandKind::Address || *class == ValueClass::Symbol);
+    let is_branch = kinds
+        .iter()
+        .any(|kind| matches!(kind, OperandKind::Address | OperandKind::BranchOffset))
+        && mnemonic.starts_with('b');
+
+    match mnemonic {
+        "li" => immediate.map_or(1, load_words),
+        "la" if has_label => 2,
+        "la" => immediate.map_or(1, load_words),
+        "abs" => 3,
+        "div" | "divu" | "rem" | "remu" => match immediate {
+            Some(number) => load_words(number) + 2,
+            None => 4,
+        },
+        "mulo" => 5,
+        "mulou" => 4,
+        "rol" | "ror" => 4,
+        "ulw" | "usw" => 2,
+        "ulh" | "ulhu" => 4,
+        "ush" => 3,
+        "seq" | "sne" | "sge" | "sgeu" | "sle" | "sleu" => 2 + immediate.map_or(0, load_words),
+        _ if is_branch => match immediate {
+            Some(Some(number)) if fits_signed_16(number) && !matches!(mnemonic, "beq" | "bne") => 2,
+            Some(number) => load_words(number) + 1,
+            None if kinds.len() > 2 => 2,
+            None => 1,
+        },
+        // Loads and stores from a label
+        _ if has_label => 2,
+        _ => immediate.map_or(1, |number| load_words(number) + 1),
+    }
+}
+
+/// Number of instructions needed to access a memory operand; labels and large offsets need the
+/// upper half of the address in `$at`.
+fn memory_words(
+    evaluator: &Evaluator,
+    text: &str,
+    value: &ValueNode,
+    statement_index: usize,
+) -> i64 {
+    let offset = match value {
+        ValueNode::ParenthesizedExpression { head: None, .. } => return 1,
+        ValueNode::ParenthesizedExpression {
+            head: Some(head), ..
+        } => head,
+        value => value,
+    };
+    // `%lo(label)` already selects the lower half
+    if get_text_in_ts_range(text, offset.range()).contains('%')
+        || evaluator
+            .evaluate(offset, statement_index)
+            .is_ok_and(fits_signed_16)
+    {
+        return 1;
+    }
+    // `lui $at` and, with a base register, `addu $at, $at, base`
+    match value {
+        ValueNode::ParenthesizedExpression { .. } => 3,
+        _ => 2,
+    }
+}
+
+/// Number of instructions needed to load a constant into a register.
+fn load_words(value: Option<i64>) -> i64 {
+    match value {
+        Some(value) if fits_signed_16(value) || (0..=0xffff).contains(&value) => 1,
+        Some(value) if value & 0xffff == 0 => 1,
+        _ => 2,
+    }
+}
+
+fn fits_signed_16(value: i64) -> bool {
+    (-0x8000..=0x7fff).contains(&value)
+}
+
+/// Returns the number of bytes a quoted string literal assembles to, without terminator.
+pub fn string_length(literal: &str) -> i64 {
+    string_bytes(literal).len() as i64
+}
+
+/// Returns the bytes a quoted string literal assembles to, without terminator.
+pub fn string_bytes(literal: &str) -> Vec<u8> {
+    let content = literal
+        .strip_prefix('"')
+        .map(|s| s.strip_suffix('"').unwrap_or(s))
+        .unwrap_or(literal);
+    let mut bytes = Vec::new();
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('a') => 0x07,
+            Some('b') => 0x08,
+            Some('f') => 0x0c,
+            Some('v') => 0x0b,
+            Some('x') => {
+                let mut value: u32 = 0;
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_hexdigit()) {
+                    value = (value << 4) | digit.to_digit(16).unwrap_or(0);
+                }
+                value as u8
+            }
+            Some(first @ '0'..='7') => {
+                let mut value = first.to_digit(8).unwrap_or(0);
+                for _ in 0..2 {
+                    if let Some(digit) = chars.next_if(|c| ('0'..='7').contains(c)) {
+                        value = (value << 3) | digit.to_digit(8).unwrap_or(0);
+                    }
+                }
+                value as u8
+            }
+            Some(other) if other.is_ascii() => other as u8,
+            Some(_) | None => b'\\',
+        };
+        bytes.push(byte);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    fn layout(text: &str, dialect: Dialect) -> Layout {
+        let mut settings = Settings::default();
+        settings.dialect = dialect;
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+        Layout::compute(&doc, &definitions, &settings)
+    }
+
+    #[test]
+    fn places_data_at_mars_addresses() {
+        let text = ".data\nmsg: .asciiz \"hi\\n\"\nvalue: .word 1, 2\nbytes: .byte 1\nhalf: .half 2\n.align 3\nbuf: .space 10\n.text\nmain: li $t0, 1\n";
+        let layout = layout(text, Dialect::Mars);
+        assert_eq!(layout.label("msg"), Some(0x1001_0000));
+        assert_eq!(layout.label("value"), Some(0x1001_0004));
+        assert_eq!(layout.label("bytes"), Some(0x1001_000c));
+        assert_eq!(layout.label("half"), Some(0x1001_000e));
+        assert_eq!(layout.label("buf"), Some(0x1001_0010));
+        assert_eq!(layout.label("main"), Some(0x0040_0000));
+        assert_eq!(
+            layout.sections,
+            vec![
+                SectionLayout {
+                    section: Section::Text,
+                    start: 0x0040_0000,
+                    size: 4
+                },
+                SectionLayout {
+                    section: Section::Data,
+                    start: 0x1001_0000,
+                    size: 0x1a
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_pseudo_instruction_expansions() {
+        let text = ".data\nx: .word 0\n.text\nmain:\n  li $t0, 100\n  li $t0, 0x12345678\na: la $a0, x\nb: lw $t0, x\nc: blt $t0, $t1, main\nd: addi $t0, $t0, 100000\ne: nop\n";
+        let layout = layout(text, Dialect::Mars);
+        assert_eq!(layout.label("a"), Some(0x0040_000c));
+        assert_eq!(layout.label("b"), Some(0x0040_0014));
+        assert_eq!(layout.label("c"), Some(0x0040_001c));
+        assert_eq!(layout.label("d"), Some(0x0040_0024));
+        assert_eq!(layout.label("e"), Some(0x0040_0030));
+    }
+
+    #[test]
+    fn uses_section_offsets_for_gas() {
+        let text = ".text\nf:\n  addiu $sp, $sp, -8\n  .macro twice\n  nop\n  nop\n  .endm\ng:\n  twice\nh:\n  jr $ra\n.data\nv: .byte 1\n";
+        let layout = layout(text, Dialect::Gas);
+        assert!(!layout.absolute);
+        assert_eq!(layout.label("g"), Some(4));
+        assert_eq!(layout.label("h"), Some(12));
+        assert_eq!(layout.label("v"), Some(0));
+    }
+
+    #[test]
+    fn measures_string_literals() {
+        assert_eq!(string_length("\"abc\""), 3);
+        assert_eq!(string_length("\"a\\n\\x41\\101\\\"\""), 5);
+    }
+}
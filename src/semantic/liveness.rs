@@ -0,0 +1,491 @@
+//! Register liveness and reads of registers that are not written before, computed per routine on
+//! the control-flow graph.
+
+use smol_str::{SmolStr, ToSmolStr};
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+
+use super::cfg::{Cfg, Exit};
+use super::effects::{instruction_effects, is_call, Effects, Location};
+
+/// Set of registers, including HI and LO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LocationSet(u128);
+
+impl LocationSet {
+    pub const ALL: LocationSet = LocationSet((1 << 66) - 1);
+
+    pub fn of(locations: &[Location]) -> LocationSet {
+        let mut set = LocationSet::default();
+        for location in locations {
+            set.insert(*location);
+        }
+        set
+    }
+
+    pub fn insert(&mut self, location: Location) {
+        self.0 |= 1 << index(location);
+    }
+
+    pub fn contains(&self, location: Location) -> bool {
+        self.0 & (1 << index(location)) != 0
+    }
+
+    pub fn union(self, other: LocationSet) -> LocationSet {
+        LocationSet(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: LocationSet) -> LocationSet {
+        LocationSet(self.0 & other.0)
+    }
+
+    pub fn difference(self, other: LocationSet) -> LocationSet {
+        LocationSet(self.0 & !other.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterates over the registers in the order `$0`-`$31`, `$f0`-`$f31`, HI, LO.
+    pub fn iter(self) -> impl Iterator<Item = Location> {
+        (0..66)
+            .filter(move |bit| self.0 & (1 << bit) != 0)
+            .map(from_index)
+    }
+}
+
+fn index(location: Location) -> u32 {
+    match location {
+        Location::Gpr(n) => n as u32,
+        Location::Fpr(n) => 32 + n as u32,
+        Location::Hi => 64,
+        Location::Lo => 65,
+    }
+}
+
+fn from_index(index: u32) -> Location {
+    match index {
+        0..32 => Location::Gpr(index as u8),
+        32..64 => Location::Fpr((index - 32) as u8),
+        64 => Location::Hi,
+        _ => Location::Lo,
+    }
+}
+
+/// Registers a routine is not expected to preserve: `$at`, `$a0`-`$a3`, `$t0`-`$t9`, `$k0`,
+/// `$k1`, HI and LO. Values left in them when returning are never read.
+fn temporaries() -> LocationSet {
+    let mut set = LocationSet::of(&[Location::Hi, Location::Lo]);
+    for number in [1, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 24, 25, 26, 27] {
+        set.insert(Location::Gpr(number));
+    }
+    set
+}
+
+/// Registers that hold no meaningful value when a routine starts: `$v0`, `$v1`, `$t0`-`$t9`,
+/// HI and LO.
+fn uninitialized() -> LocationSet {
+    let mut set = LocationSet::of(&[Location::Hi, Location::Lo]);
+    for number in [2, 3, 8, 9, 10, 11, 12, 13, 14, 15, 24, 25] {
+        set.insert(Location::Gpr(number));
+    }
+    set
+}
+
+fn return_values() -> LocationSet {
+    LocationSet::of(&[Location::Gpr(2), Location::Gpr(3)])
+}
+
+/// Effects of a single instruction; macros and unknown instructions have unknown effects.
+struct Step {
+    reads: LocationSet,
+    writes: LocationSet,
+    /// index into `Cfg::routines` of the routine called, if it is in the document
+    callee: Option<usize>,
+    call: bool,
+    syscall: bool,
+}
+
+/// Read of a register no path in the routine writes before.
+#[derive(Debug, PartialEq)]
+pub struct UndefinedRead {
+    pub instruction: usize,
+    pub location: Location,
+    /// name of the routine called before, for return values it does not set
+    pub after_call: Option<SmolStr>,
+}
+
+pub struct Liveness {
+    /// registers live at the start of each block
+    pub live_in: Vec<LocationSet>,
+    /// instructions with a register they write that is never read afterwards
+    pub dead_writes: Vec<(usize, Location)>,
+    pub undefined_reads: Vec<UndefinedRead>,
+    /// registers read or written by each routine
+    pub used: Vec<LocationSet>,
+}
+
+impl Liveness {
+    pub fn compute(doc: &Document, definitions: &LanguageDefinitions, cfg: &Cfg) -> Liveness {
+        let steps: Vec<Option<Step>> = (0..doc.semantic_model.instructions.len())
+            .map(|instruction| step(doc, definitions, cfg, instruction))
+            .collect();
+        let unreachable = cfg.unreachable();
+        let orders: Vec<Vec<usize>> = cfg.blocks.iter().map(|b| b.execution_order()).collect();
+
+        // The registers a system call may use are not counted
+        let mut used = vec![LocationSet::default(); cfg.routines.len()];
+        for block in &cfg.blocks {
+            let steps = block.instructions.iter().filter_map(|&i| steps[i].as_ref());
+            for step in steps.filter(|step| !step.syscall) {
+                used[block.routine] = used[block.routine].union(step.reads).union(step.writes);
+            }
+        }
+
+        let live_in = live_in(cfg, &steps, &orders);
+        let mut dead_writes = Vec::new();
+        for (index, block) in cfg.blocks.iter().enumerate() {
+            if unreachable[index] {
+                continue;
+            }
+            let mut live = live_out(cfg, index, &live_in);
+            for &instruction in orders[index].iter().rev() {
+                if let Some(step) = &steps[instruction] {
+                    if !step.call && !step.syscall {
+                        for location in step.writes.difference(live).iter() {
+                            if !matches!(location, Location::Hi | Location::Lo) {
+                                dead_writes.push((instruction, location));
+                            }
+                        }
+                    }
+                }
+                live = transfer_live(&steps[instruction], live, cfg, &live_in);
+            }
+        }
+        dead_writes.sort_by_key(|(instruction, _)| *instruction);
+
+        let undefined_reads = undefined_reads(cfg, &steps, &orders, &unreachable);
+        Liveness {
+            live_in,
+            dead_writes,
+            undefined_reads,
+            used,
+        }
+    }
+}
+
+fn step(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    cfg: &Cfg,
+    instruction: usize,
+) -> Option<Step> {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+    let SyntaxNode::Instruction(node) = doc
+        .ast
+        .items
+        .get(model.instructions[instruction].statement_index)?
+    else {
+        return None;
+    };
+    let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+    if model.macros.contains_key(mnemonic) {
+        return None;
+    }
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    if !definitions.instructions.contains_key(mnemonic.as_str()) {
+        return None;
+    }
+    let operands: Vec<&ValueNode> = node
+        .operands
+        .iter()
+        .filter_map(|item| match item {
+            OperandListItem::Operand(value) => Some(value),
+            _ => None,
+        })
+        .collect();
+    let Effects { reads, writes } = instruction_effects(text, &mnemonic, &operands);
+
+    let call = is_call(&mnemonic);
+    let callee = match operands.last() {
+        Some(ValueNode::Symbol { range }) if call => {
+            let name = get_text_in_ts_range(text, *range);
+            cfg.routines
+                .iter()
+                .position(|routine| routine.name.as_deref() == Some(name))
+        }
+        _ => None,
+    };
+    Some(Step {
+        reads: LocationSet::of(&reads),
+        writes: LocationSet::of(&writes),
+        callee,
+        call,
+        syscall: mnemonic == "syscall",
+    })
+}
+
+/// Registers live when leaving a block. Registers are assumed to be live when control leaves the
+/// routine in an unknown way.
+fn live_out(cfg: &Cfg, index: usize, live_in: &[LocationSet]) -> LocationSet {
+    let block = &cfg.blocks[index];
+    if block.targets.is_empty() && block.fall_through.is_none() {
+        return match block.exit {
+            Some(Exit::Return | Exit::Terminate) => LocationSet::ALL.difference(temporaries()),
+            _ => LocationSet::ALL,
+        };
+    }
+    block
+        .successors()
+        .fold(LocationSet::default(), |live, successor| {
+            if cfg.blocks[successor].routine == block.routine {
+                live.union(live_in[successor])
+            } else {
+                LocationSet::ALL
+            }
+        })
+}
+
+fn transfer_live(
+    step: &Option<Step>,
+    live: LocationSet,
+    cfg: &Cfg,
+    live_in: &[LocationSet],
+) -> LocationSet {
+    let Some(step) = step else {
+        return LocationSet::ALL;
+    };
+    // The routine called runs after the call wrote `$ra`
+    let live = match (step.call, step.callee) {
+        (true, Some(callee)) => live.union(live_in[cfg.routines[callee].entry]),
+        (true, None) => LocationSet::ALL,
+        (false, _) => live,
+    };
+    live.difference(step.writes).union(step.reads)
+}
+
+/// Backward dataflow over all routines at once, so calls use the registers live at the start of
+/// the routine called. `orders` are the instructions of each block in execution order.
+fn live_in(cfg: &Cfg, steps: &[Option<Step>], orders: &[Vec<usize>]) -> Vec<LocationSet> {
+    let mut live_in = vec![LocationSet::default(); cfg.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in (0..cfg.blocks.len()).rev() {
+            let mut live = live_out(cfg, index, &live_in);
+            for &instruction in orders[index].iter().rev() {
+                live = transfer_live(&steps[instruction], live, cfg, &live_in);
+            }
+            if live != live_in[index] {
+                live_in[index] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+/// Registers each routine may write, including the ones written by the routines it calls.
+fn routine_writes(cfg: &Cfg, steps: &[Option<Step>]) -> Vec<LocationSet> {
+    let mut writes = vec![LocationSet::default(); cfg.routines.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (routine, blocks) in cfg.routines.iter().map(|r| &r.blocks).enumerate() {
+            let mut written = writes[routine];
+            for &block in blocks {
+                for step in cfg.blocks[block].instructions.iter().map(|&i| &steps[i]) {
+                    written = written.union(match step {
+                        Some(step) if step.call => match step.callee {
+                            Some(callee) => step.writes.union(writes[callee]),
+                            None => LocationSet::ALL,
+                        },
+                        Some(step) => step.writes,
+                        None => LocationSet::ALL,
+                    });
+                }
+            }
+            if written != writes[routine] {
+                writes[routine] = written;
+                changed = true;
+            }
+        }
+    }
+    writes
+}
+
+fn transfer_defined(
+    step: &Option<Step>,
+    defined: LocationSet,
+    routine_writes: &[LocationSet],
+) -> LocationSet {
+    match step {
+        // Return values are only set if the routine called writes them
+        Some(step) if step.call => match step.callee {
+            Some(callee) => defined
+                .difference(return_values())
+                .union(step.writes)
+                .union(routine_writes[callee]),
+            None => LocationSet::ALL,
+        },
+        Some(step) => defined.union(step.writes),
+        None => LocationSet::ALL,
+    }
+}
+
+/// Forward dataflow of the registers written on every path from the start of the routine.
+fn undefined_reads(
+    cfg: &Cfg,
+    steps: &[Option<Step>],
+    orders: &[Vec<usize>],
+    unreachable: &[bool],
+) -> Vec<UndefinedRead> {
+    let routine_writes = routine_writes(cfg, steps);
+    let predecessors = cfg.predecessors();
+    let defined_in = |index: usize, defined_out: &[LocationSet]| {
+        let block = &cfg.blocks[index];
+        let start = if cfg.routines[block.routine].entry == index {
+            LocationSet::ALL.difference(uninitialized())
+        } else {
+            LocationSet::ALL
+        };
+        predecessors[index]
+            .iter()
+            .filter(|&&predecessor| cfg.blocks[predecessor].routine == block.routine)
+            .fold(start, |defined, &predecessor| {
+                defined.intersection(defined_out[predecessor])
+            })
+    };
+
+    let mut defined_out = vec![LocationSet::ALL; cfg.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..cfg.blocks.len() {
+            let mut defined = defined_in(index, &defined_out);
+            for &instruction in &orders[index] {
+                defined = transfer_defined(&steps[instruction], defined, &routine_writes);
+            }
+            if defined != defined_out[index] {
+                defined_out[index] = defined;
+                changed = true;
+            }
+        }
+    }
+
+    let mut reads = Vec::new();
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        if unreachable[index] {
+            continue;
+        }
+        let mut defined = defined_in(index, &defined_out);
+        let mut last_call = None;
+        for &instruction in &orders[index] {
+            let step = &steps[instruction];
+            if let Some(step) = step {
+                for location in step.reads.difference(defined).iter() {
+                    let after_call = last_call
+                        .filter(|_| return_values().contains(location))
+                        .and_then(|callee: usize| cfg.routines[callee].name.clone());
+                    reads.push(UndefinedRead {
+                        instruction,
+                        location,
+                        after_call,
+                    });
+                }
+                // Report every register once
+                defined = defined.union(step.reads);
+                if step.call {
+                    last_call = step.callee;
+                }
+            }
+            defined = transfer_defined(step, defined, &routine_writes);
+        }
+    }
+    reads.sort_by_key(|read| read.instruction);
+    reads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    fn liveness(text: &str) -> (Cfg, Liveness) {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+        let settings = Settings::default();
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+        let cfg = Cfg::build(&doc, &settings);
+        let liveness = Liveness::compute(&doc, &definitions, &cfg);
+        (cfg, liveness)
+    }
+
+    const PROGRAM: &str = ".text\nmain:\n  li $a0, 4\n  jal square\n  move $a0, $v0\n  jal print\n  move $t1, $v0\n  li $v0, 10\n  syscall\nsquare:\n  mul $v0, $a0, $a0\n  jr $ra\nprint:\n  addi $t0, $t0, 1\n  sw $a0, 0($gp)\n  li $t2, 3\n  jr $ra\n";
+
+    #[test]
+    fn finds_live_registers_at_routine_entries() {
+        let (cfg, liveness) = liveness(PROGRAM);
+        let square = cfg.routines[1].entry;
+        let live: Vec<_> = liveness.live_in[square]
+            .intersection(liveness.used[1])
+            .iter()
+            .collect();
+        assert_eq!(live, vec![Location::Gpr(4), Location::Gpr(31)]);
+    }
+
+    #[test]
+    fn finds_dead_writes_and_undefined_reads() {
+        let (_, liveness) = liveness(PROGRAM);
+        assert_eq!(
+            liveness.dead_writes,
+            vec![
+                (4, Location::Gpr(9)),
+                (9, Location::Gpr(8)),
+                (11, Location::Gpr(10))
+            ]
+        );
+        assert_eq!(
+            liveness.undefined_reads,
+            vec![
+                UndefinedRead {
+                    instruction: 4,
+                    location: Location::Gpr(2),
+                    after_call: Some("print".into()),
+                },
+                UndefinedRead {
+                    instruction: 9,
+                    location: Location::Gpr(8),
+                    after_call: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_delay_slots_before_the_call() {
+        let (_, liveness) = liveness(".text\n.set noreorder\nmain:\n  move $s0, $a1\n  jal memcpy_words\n  move $a0, $s0\n  jr $ra\n  nop\nmemcpy_words:\n  lw $t0, 0($a0)\n  jr $ra\n  move $v0, $t0\n");
+        assert!(
+            liveness.dead_writes.is_empty(),
+            "{:?}",
+            liveness.dead_writes
+        );
+        assert!(
+            liveness.undefined_reads.is_empty(),
+            "{:?}",
+            liveness.undefined_reads
+        );
+    }
+}
@@ -6,10 +6,29 @@ use tree_sitter::Range;
 use crate::ast::*;
 use crate::document::utils::get_text_in_ts_range;
 
+pub mod abi;
+pub mod cfg;
+pub mod effects;
+pub mod encode;
+pub mod eval;
+pub mod expand;
+pub mod frame;
+pub mod layout;
+pub mod liveness;
+pub mod syscall;
+
 pub struct SemanticModel {
     pub syntax_errors: Vec<Error>,
     pub labels: HashMap<SmolStr, Label>,
+    /// numeric local labels like `1:`, which may be defined any number of times, in source order
+    pub numeric_labels: Vec<NumericLabel>,
     pub macros: HashMap<SmolStr, MacroDefinition>,
+    /// symbols defined with `.eqv`, `.set`, `.equ`, `.equiv` or `=`, in order of definition
+    pub constants: HashMap<SmolStr, Vec<Constant>>,
+    /// ranges of all symbol operands, grouped by symbol name
+    pub symbol_references: HashMap<SmolStr, Vec<Range>>,
+    /// For each statement, whether it is part of a macro definition
+    pub macro_body: Vec<bool>,
     pub directives: Vec<Directive>,
     pub instructions: Vec<Instruction>,
 }
@@ -28,10 +47,20 @@ pub struct Label {
     pub statement_index: usize,
 }
 
+pub struct NumericLabel {
+    pub name: SmolStr,
+    pub statement_index: usize,
+}
+
 pub struct MacroDefinition {
     pub statement_index: usize,
 }
 
+pub struct Constant {
+    pub statement_index: usize,
+    pub name_range: Range,
+}
+
 pub struct Directive {
     pub section: Section,
     pub statement_index: usize,
@@ -41,9 +70,44 @@ pub struct Instruction {
     pub section: Section,
     pub statement_index: usize,
     pub real_operand_indices: Vec<usize>,
+    /// assembler options set with `.set` at this instruction
+    pub options: AssemblerOptions,
 }
 
+/// Options changed with `.set`, e.g. `.set noreorder`.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AssemblerOptions {
+    /// whether the assembler fills delay slots and avoids hazards itself
+    pub reorder: bool,
+    /// whether pseudo-instructions may use `$at`, turned off with `.set noat`
+    pub at: bool,
+}
+
+impl Default for AssemblerOptions {
+    fn default() -> Self {
+        AssemblerOptions {
+            reorder: true,
+            at: true,
+        }
+    }
+}
+
+impl AssemblerOptions {
+    /// Applies the option of a `.set` directive. `push` and `pop` save and restore the options.
+    fn apply(&mut self, option: &str, saved: &mut Vec<AssemblerOptions>) {
+        match option {
+            "reorder" => self.reorder = true,
+            "noreorder" => self.reorder = false,
+            "at" => self.at = true,
+            "noat" => self.at = false,
+            "push" => saved.push(*self),
+            "pop" => *self = saved.pop().unwrap_or_default(),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Section {
     Bss,
     Data,
@@ -55,7 +119,7 @@ pub enum Section {
     Text,
 }
 
-fn parse_section(directive_mnemonic: &str) -> Option<Section> {
+pub fn parse_section(directive_mnemonic: &str) -> Option<Section> {
     match directive_mnemonic {
         ".bss" => Some(Section::Bss),
         ".data" => Some(Section::Data),
@@ -69,12 +133,88 @@ fn parse_section(directive_mnemonic: &str) -> Option<Section> {
     }
 }
 
+impl Section {
+    /// Name of the directive that starts the section.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Bss => ".bss",
+            Section::Data => ".data",
+            Section::KData => ".kdata",
+            Section::KText => ".ktext",
+            Section::RData => ".rdata",
+            Section::SBss => ".sbss",
+            Section::SData => ".sdata",
+            Section::Text => ".text",
+        }
+    }
+}
+
+/// Returns true for directives that close a macro body.
+pub fn is_macro_end(directive_mnemonic: &str) -> bool {
+    matches!(directive_mnemonic, ".end_macro" | ".endm")
+}
+
+/// Returns true for directives that emit data into the current section.
+pub fn is_data_directive(directive_mnemonic: &str) -> bool {
+    matches!(
+        directive_mnemonic,
+        ".2byte"
+            | ".4byte"
+            | ".8byte"
+            | ".ascii"
+            | ".asciiz"
+            | ".asciz"
+            | ".byte"
+            | ".double"
+            | ".dword"
+            | ".float"
+            | ".half"
+            | ".hword"
+            | ".int"
+            | ".long"
+            | ".quad"
+            | ".short"
+            | ".single"
+            | ".skip"
+            | ".space"
+            | ".string"
+            | ".word"
+    )
+}
+
+/// Returns the name defined by a constant definition like `.eqv NAME, value`.
+///
+/// `.set` is only a definition when it has a value; `.set noreorder` changes an assembler option.
+pub fn constant_definition_name(
+    directive_mnemonic: &str,
+    operands: &[OperandListItem],
+) -> Option<Range> {
+    if !matches!(
+        directive_mnemonic.to_ascii_lowercase().as_str(),
+        ".eqv" | ".set" | ".equ" | ".equiv"
+    ) {
+        return None;
+    }
+    let mut values = operands.iter().filter_map(|item| match item {
+        OperandListItem::Operand(value) => Some(value),
+        _ => None,
+    });
+    match (values.next(), values.next()) {
+        (Some(ValueNode::Symbol { range }), Some(_)) => Some(*range),
+        _ => None,
+    }
+}
+
 impl SemanticModel {
     pub fn new() -> SemanticModel {
         SemanticModel {
             syntax_errors: Vec::new(),
             labels: HashMap::new(),
+            numeric_labels: Vec::new(),
             macros: HashMap::new(),
+            constants: HashMap::new(),
+            symbol_references: HashMap::new(),
+            macro_body: Vec::new(),
             directives: Vec::new(),
             instructions: Vec::new(),
         }
@@ -83,7 +223,11 @@ impl SemanticModel {
     fn clear(&mut self) {
         self.syntax_errors.clear();
         self.labels.clear();
+        self.numeric_labels.clear();
         self.macros.clear();
+        self.constants.clear();
+        self.symbol_references.clear();
+        self.macro_body.clear();
         self.directives.clear();
         self.instructions.clear();
     }
@@ -93,14 +237,37 @@ impl SemanticModel {
 
         let statements = &ast.items;
         let mut current_section = Section::Text;
+        let mut in_macro = false;
+        let mut options = AssemblerOptions::default();
+        let mut saved_options = Vec::new();
 
         for (statement_index, statement) in statements.iter().enumerate() {
+            // The macro header and end directive are part of the macro body
+            if matches!(statement, SyntaxNode::MacroDefinition(_)) {
+                in_macro = true;
+            }
+            self.macro_body.push(in_macro);
+            if let SyntaxNode::Directive(node) = statement {
+                if is_macro_end(get_text_in_ts_range(text, node.mnemonic.range)) {
+                    in_macro = false;
+                }
+            }
+
             match statement {
                 SyntaxNode::Error(node) => {
                     self.syntax_errors.push(Error::InvalidSyntax(node.range));
                 }
                 SyntaxNode::Label(node) => {
-                    let name = get_text_in_ts_range(text, node.range).to_smolstr();
+                    // Numeric labels are local and may be redefined any number of times
+                    if matches!(node.kind, LabelKind::Numeric) {
+                        self.numeric_labels.push(NumericLabel {
+                            name: get_text_in_ts_range(text, node.name.range).to_smolstr(),
+                            statement_index,
+                        });
+                        continue;
+                    }
+
+                    let name = get_text_in_ts_range(text, node.name.range).to_smolstr();
 
                     if self.labels.contains_key(&name) {
                         self.syntax_errors.push(Error::DuplicateLabel {
@@ -124,11 +291,28 @@ impl SemanticModel {
                         current_section = section;
                     }
 
+                    self.collect_symbol_references(text, &node.operands);
+
+                    if let Some(name_range) = constant_definition_name(mnemonic, &node.operands) {
+                        self.add_constant(text, statement_index, name_range);
+                    } else if mnemonic.eq_ignore_ascii_case(".set") {
+                        if let Some(OperandListItem::Operand(ValueNode::Symbol { range })) =
+                            node.operands.first()
+                        {
+                            let option = get_text_in_ts_range(text, *range);
+                            options.apply(option, &mut saved_options);
+                        }
+                    }
+
                     self.directives.push(Directive {
                         section: current_section,
                         statement_index,
                     });
                 }
+                SyntaxNode::Assignment(node) => {
+                    self.collect_symbol_references(text, &node.operands);
+                    self.add_constant(text, statement_index, node.name.range);
+                }
                 SyntaxNode::Instruction(node) => {
                     let real_operand_indices = node
                         .operands
@@ -143,10 +327,13 @@ impl SemanticModel {
                             }
                         })
                         .collect();
+                    self.collect_symbol_references(text, &node.operands);
+
                     self.instructions.push(Instruction {
                         section: current_section,
                         statement_index,
                         real_operand_indices,
+                        options,
                     });
                 }
                 SyntaxNode::MacroInvocation(node) => {
@@ -163,10 +350,13 @@ impl SemanticModel {
                             }
                         })
                         .collect();
+                    self.collect_symbol_references(text, &node.operands);
+
                     self.instructions.push(Instruction {
                         section: current_section,
                         statement_index,
                         real_operand_indices,
+                        options,
                     });
                 }
                 SyntaxNode::MacroDefinition(node) => {
@@ -195,4 +385,41 @@ impl SemanticModel {
             }
         }
     }
+
+    /// Returns the statement of the numeric label a reference like `1b` or `1f` in the given
+    /// statement refers to: the nearest definition before or after it.
+    pub fn resolve_numeric_label(&self, statement_index: usize, reference: &str) -> Option<usize> {
+        let (number, forward) = match reference.as_bytes().last()? {
+            b'f' | b'F' => (&reference[..reference.len() - 1], true),
+            b'b' | b'B' => (&reference[..reference.len() - 1], false),
+            _ => return None,
+        };
+        let mut candidates = self
+            .numeric_labels
+            .iter()
+            .filter(|label| label.name == number);
+        let found = if forward {
+            candidates.find(|label| label.statement_index > statement_index)
+        } else {
+            candidates.rfind(|label| label.statement_index < statement_index)
+        };
+        found.map(|label| label.statement_index)
+    }
+
+    fn add_constant(&mut self, text: &str, statement_index: usize, name_range: Range) {
+        let name = get_text_in_ts_range(text, name_range).to_smolstr();
+        self.constants.entry(name).or_default().push(Constant {
+            statement_index,
+            name_range,
+        });
+    }
+
+    fn collect_symbol_references(&mut self, text: &str, operands: &[OperandListItem]) {
+        visit_operands(operands, &mut |value| {
+            if let ValueNode::Symbol { range } = value {
+                let name = get_text_in_ts_range(text, *range).to_smolstr();
+                self.symbol_references.entry(name).or_default().push(*range);
+            }
+        });
+    }
 }
@@ -0,0 +1,117 @@
+//! Services requested by `syscall` instructions through the value set in `$v0` before them.
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::settings::Settings;
+
+use super::cfg::Cfg;
+use super::effects::{self, instruction_effects, Location};
+use super::eval::Evaluator;
+use super::liveness::LocationSet;
+
+const V0: Location = Location::Gpr(2);
+
+/// A `syscall` with the state of its basic block in front of it.
+#[derive(Debug, PartialEq)]
+pub struct SyscallSite {
+    pub instruction: usize,
+    /// service number loaded into `$v0` in the same block
+    pub service: Option<i64>,
+    /// registers written in the same block before the `syscall`
+    pub written: LocationSet,
+}
+
+/// Finds all `syscall` instructions and the services they request. Only `li`, `addiu`, `addi`
+/// and `ori` with `$zero` are recognized as setting the service number.
+pub fn syscall_sites(doc: &Document, settings: &Settings, cfg: &Cfg) -> Vec<SyscallSite> {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+    let evaluator = Evaluator::new(doc, settings);
+    let mut sites = Vec::new();
+
+    for block in &cfg.blocks {
+        let mut service = None;
+        let mut written = LocationSet::default();
+        for &instruction in &block.instructions {
+            let statement = model.instructions[instruction].statement_index;
+            let node = match doc.ast.items.get(statement) {
+                Some(SyntaxNode::Instruction(node))
+                    if !model
+                        .macros
+                        .contains_key(get_text_in_ts_range(text, node.mnemonic.range)) =>
+                {
+                    node
+                }
+                // Macros may set any register
+                _ => {
+                    service = None;
+                    written = LocationSet::ALL;
+                    continue;
+                }
+            };
+            let mnemonic = get_text_in_ts_range(text, node.mnemonic.range).to_ascii_lowercase();
+            let operands: Vec<&ValueNode> = node
+                .operands
+                .iter()
+                .filter_map(|item| match item {
+                    OperandListItem::Operand(value) => Some(value),
+                    _ => None,
+                })
+                .collect();
+            let writes = instruction_effects(text, &mnemonic, &operands).writes;
+
+            if mnemonic == "syscall" {
+                sites.push(SyscallSite {
+                    instruction,
+                    service,
+                    written,
+                });
+            }
+            if writes.contains(&V0) {
+                let value = match (mnemonic.as_str(), operands.as_slice()) {
+                    ("li", [_, value]) => Some(value),
+                    ("addi" | "addiu" | "ori", [_, ValueNode::Register { range }, value])
+                        if effects::location(get_text_in_ts_range(text, *range))
+                            == Some(Location::Gpr(0)) =>
+                    {
+                        Some(value)
+                    }
+                    _ => None,
+                };
+                service = value.and_then(|value| evaluator.evaluate(value, statement).ok());
+            }
+            written = written.union(LocationSet::of(&writes));
+        }
+    }
+
+    sites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    #[test]
+    fn finds_service_numbers() {
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            ".eqv PRINT_STRING, 4\nmain:\n  la $a0, msg\n  li $v0, PRINT_STRING\n  syscall\n  syscall\n  addiu $v0, $zero, 10\n  syscall\n".to_string(),
+        );
+        doc.update_model();
+        let settings = Settings::default();
+        let cfg = Cfg::build(&doc, &settings);
+
+        let sites = syscall_sites(&doc, &settings, &cfg);
+        let services: Vec<_> = sites
+            .iter()
+            .map(|site| (site.instruction, site.service))
+            .collect();
+        assert_eq!(services, vec![(2, Some(4)), (3, None), (5, Some(10))]);
+        assert!(sites[0].written.contains(Location::Gpr(4)));
+        assert!(!sites[0].written.contains(Location::Gpr(5)));
+    }
+}
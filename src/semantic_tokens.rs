@@ -0,0 +1,406 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{visit_operands, LabelKind, OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::server::Backend;
+
+pub const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::MACRO,
+    SemanticTokenType::new("directive"),
+    SemanticTokenType::new("register"),
+    SemanticTokenType::new("label"),
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::STRING,
+];
+
+pub const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::new("pseudo"),
+    SemanticTokenModifier::new("numeric"),
+    SemanticTokenModifier::new("common"),
+    SemanticTokenModifier::new("float"),
+    SemanticTokenModifier::new("local"),
+];
+
+#[derive(Clone, Copy)]
+enum TokenType {
+    Instruction = 0,
+    Macro = 1,
+    Directive = 2,
+    Register = 3,
+    Label = 4,
+    MacroVariable = 5,
+    Number = 6,
+    String = 7,
+}
+
+const DECLARATION: u32 = 1 << 0;
+const PSEUDO: u32 = 1 << 1;
+const NUMERIC: u32 = 1 << 2;
+const COMMON: u32 = 1 << 3;
+const FLOAT: u32 = 1 << 4;
+const LOCAL: u32 = 1 << 5;
+
+/// Source of unique result ids for delta requests.
+static RESULT_ID: AtomicU64 = AtomicU64::new(0);
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.into(),
+        token_modifiers: TOKEN_MODIFIERS.into(),
+    }
+}
+
+/// Token with an absolute position, before relative encoding.
+struct AbsoluteToken {
+    line: u32,
+    start: u32,
+    length: u32,
+    token_type: TokenType,
+    modifiers: u32,
+}
+
+impl Backend {
+    pub async fn handle_semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensResult>> {
+        log!("textDocument/semanticTokens/full");
+
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let mut doc = doc_arc.write().await;
+
+        let (_, definitions) = self.document_settings(&doc.uri).await;
+        let tokens = encode(&collect_tokens(&doc, &definitions, None));
+
+        let result = SemanticTokens {
+            result_id: Some(next_result_id()),
+            data: tokens,
+        };
+        doc.semantic_tokens = Some(result.clone());
+
+        Ok(Some(SemanticTokensResult::Tokens(result)))
+    }
+
+    pub async fn handle_semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensFullDeltaResult>> {
+        log!("textDocument/semanticTokens/full/delta");
+
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let mut doc = doc_arc.write().await;
+
+        let (_, definitions) = self.document_settings(&doc.uri).await;
+        let tokens = encode(&collect_tokens(&doc, &definitions, None));
+        let result_id = next_result_id();
+
+        let previous = doc
+            .semantic_tokens
+            .take()
+            .filter(|previous| previous.result_id.as_ref() == Some(&params.previous_result_id));
+
+        doc.semantic_tokens = Some(SemanticTokens {
+            result_id: Some(result_id.clone()),
+            data: tokens.clone(),
+        });
+
+        // Without matching previous tokens, the client needs the full set
+        let Some(previous) = previous else {
+            return Ok(Some(SemanticTokensFullDeltaResult::Tokens(
+                SemanticTokens {
+                    result_id: Some(result_id),
+                    data: tokens,
+                },
+            )));
+        };
+
+        Ok(Some(SemanticTokensFullDeltaResult::TokensDelta(
+            SemanticTokensDelta {
+                result_id: Some(result_id),
+                edits: diff_tokens(&previous.data, &tokens),
+            },
+        )))
+    }
+
+    pub async fn handle_semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensRangeResult>> {
+        log!("textDocument/semanticTokens/range");
+
+        let doc_arc = self
+            .documents
+            .get(&params.text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+
+        let (_, definitions) = self.document_settings(&doc.uri).await;
+        let tokens = encode(&collect_tokens(&doc, &definitions, Some(params.range)));
+
+        Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: tokens,
+        })))
+    }
+}
+
+fn next_result_id() -> String {
+    RESULT_ID.fetch_add(1, Ordering::Relaxed).to_string()
+}
+
+/// Collects tokens of all statements, optionally limited to statements overlapping `range`.
+fn collect_tokens(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    range: Option<Range>,
+) -> Vec<AbsoluteToken> {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+    let mut tokens = Vec::new();
+
+    let mut push = |range: &tree_sitter::Range, token_type: TokenType, modifiers: u32| {
+        // Tokens may not span multiple lines
+        if range.start_point.row != range.end_point.row {
+            return;
+        }
+        let start = doc.point_to_position(&range.start_point);
+        let end = doc.point_to_position(&range.end_point);
+        tokens.push(AbsoluteToken {
+            line: start.line,
+            start: start.character,
+            length: end.character - start.character,
+            token_type,
+            modifiers,
+        });
+    };
+
+    for statement in &doc.ast.items {
+        let statement_range = statement.range();
+        if let Some(range) = range {
+            let start = doc.point_to_position(&statement_range.start_point);
+            let end = doc.point_to_position(&statement_range.end_point);
+            if end < range.start || start > range.end {
+                continue;
+            }
+        }
+
+        match statement {
+            SyntaxNode::Instruction(node) => {
+                let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+                if model.macros.contains_key(mnemonic) {
+                    push(&node.mnemonic.range, TokenType::Macro, 0);
+                } else if let Some(instruction) =
+                    definitions.instructions.get(&mnemonic.to_lowercase())
+                {
+                    let pseudo = instruction.variants.iter().all(|variant| variant.pseudo);
+                    let modifiers = if pseudo { PSEUDO } else { 0 };
+                    push(&node.mnemonic.range, TokenType::Instruction, modifiers);
+                }
+                push_operand_tokens(doc, definitions, &node.operands, &mut push);
+            }
+            SyntaxNode::MacroInvocation(node) => {
+                push(&node.mnemonic.range, TokenType::Macro, 0);
+                push_operand_tokens(doc, definitions, &node.operands, &mut push);
+            }
+            SyntaxNode::MacroDefinition(node) => {
+                if let Some(name) = &node.name {
+                    push(&name.range, TokenType::Macro, DECLARATION);
+                }
+                for parameter in &node.parameters {
+                    push(&parameter.name.range, TokenType::MacroVariable, DECLARATION);
+                }
+            }
+            SyntaxNode::Directive(node) => {
+                push(&node.mnemonic.range, TokenType::Directive, 0);
+                push_operand_tokens(doc, definitions, &node.operands, &mut push);
+            }
+            SyntaxNode::Label(node) => {
+                let modifiers = match node.kind {
+                    LabelKind::Numeric => DECLARATION | LOCAL,
+                    _ => DECLARATION,
+                };
+                push(&node.name.range, TokenType::Label, modifiers);
+            }
+            SyntaxNode::Assignment(node) => {
+                push_operand_tokens(doc, definitions, &node.operands, &mut push);
+            }
+            SyntaxNode::Error(_) => {}
+        }
+    }
+
+    tokens.sort_by_key(|token| (token.line, token.start));
+    tokens
+}
+
+fn push_operand_tokens(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    operands: &[OperandListItem],
+    push: &mut impl FnMut(&tree_sitter::Range, TokenType, u32),
+) {
+    let text = &doc.text;
+    let registers = &definitions.registers;
+
+    visit_operands(operands, &mut |value| match value {
+        ValueNode::Register { range } => {
+            let name = get_text_in_ts_range(text, *range);
+            let modifiers = if registers.numeric.contains_key(name) {
+                NUMERIC
+            } else if registers.common.contains_key(name) {
+                COMMON
+            } else if registers.float.contains_key(name) {
+                FLOAT
+            } else {
+                0
+            };
+            push(range, TokenType::Register, modifiers);
+        }
+        ValueNode::Symbol { range }
+            if doc
+                .semantic_model
+                .labels
+                .contains_key(get_text_in_ts_range(text, *range)) =>
+        {
+            push(range, TokenType::Label, 0);
+        }
+        ValueNode::NumericLabelReference { range } => push(range, TokenType::Label, LOCAL),
+        ValueNode::MacroVariable { range } => push(range, TokenType::MacroVariable, 0),
+        ValueNode::Decimal { range, .. }
+        | ValueNode::Hexadecimal { range, .. }
+        | ValueNode::Octal { range, .. }
+        | ValueNode::Binary { range, .. }
+        | ValueNode::Float { range, .. } => push(range, TokenType::Number, 0),
+        ValueNode::String { range, .. } | ValueNode::Char { range, .. } => {
+            push(range, TokenType::String, 0)
+        }
+        _ => {}
+    });
+}
+
+/// Encodes tokens relative to their predecessor, as required by the protocol.
+fn encode(tokens: &[AbsoluteToken]) -> Vec<SemanticToken> {
+    let mut previous_line = 0;
+    let mut previous_start = 0;
+
+    tokens
+        .iter()
+        .map(|token| {
+            let delta_line = token.line - previous_line;
+            let delta_start = if delta_line == 0 {
+                token.start - previous_start
+            } else {
+                token.start
+            };
+            previous_line = token.line;
+            previous_start = token.start;
+
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: token.length,
+                token_type: token.token_type as u32,
+                token_modifiers_bitset: token.modifiers,
+            }
+        })
+        .collect()
+}
+
+/// Computes a single edit replacing the changed middle part of the token list.
+fn diff_tokens(previous: &[SemanticToken], current: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let deleted = previous.len() - prefix - suffix;
+    let inserted = &current[prefix..current.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+
+    // Edits index into the flattened integer array, with five integers per token
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::test_document;
+    use crate::settings::Settings;
+
+    #[test]
+    fn classifies_tokens() {
+        let doc = test_document(
+            ".macro exit\n  li $v0, 10\n.end_macro\nmain:\n  add $t0, $8, $f2\n  blt $t0, $t1, main\n  exit\n",
+        );
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&Settings::default());
+
+        let tokens = collect_tokens(&doc, &definitions, None);
+        let token_at = |byte: usize| {
+            let position = doc.byte_to_position(byte);
+            tokens
+                .iter()
+                .find(|token| token.line == position.line && token.start == position.character)
+                .map(|token| (token.token_type as u32, token.modifiers))
+                .unwrap()
+        };
+        let find = |text: &str| token_at(doc.text.find(text).unwrap());
+
+        assert_eq!(find("exit\n  li"), (TokenType::Macro as u32, DECLARATION));
+        assert_eq!(find("add"), (TokenType::Instruction as u32, 0));
+        assert_eq!(find("blt"), (TokenType::Instruction as u32, PSEUDO));
+        assert_eq!(find("$t0, $8"), (TokenType::Register as u32, COMMON));
+        assert_eq!(find("$8"), (TokenType::Register as u32, NUMERIC));
+        assert_eq!(find("$f2"), (TokenType::Register as u32, FLOAT));
+        assert_eq!(find("main\n  exit"), (TokenType::Label as u32, 0));
+        assert_eq!(
+            token_at(doc.text.rfind("exit").unwrap()),
+            (TokenType::Macro as u32, 0)
+        );
+    }
+
+    #[test]
+    fn diff_replaces_changed_tokens() {
+        let token = |length| SemanticToken {
+            delta_line: 1,
+            delta_start: 0,
+            length,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        };
+        let previous = vec![token(1), token(2), token(3)];
+        let current = vec![token(1), token(4), token(5), token(3)];
+
+        let edits = diff_tokens(&previous, &current);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].start, 5);
+        assert_eq!(edits[0].delete_count, 5);
+        assert_eq!(edits[0].data, Some(vec![token(4), token(5)]));
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::OnceLock;
 use tokio::sync::{Mutex, RwLock};
@@ -11,13 +13,17 @@ use tree_sitter::{InputEdit, Query, QueryCursor};
 
 use crate::ast;
 use crate::completion;
+use crate::config::{self, ProjectConfig};
 use crate::document;
 use crate::document::Document;
 use crate::goto_definition;
 use crate::hover;
-use crate::lang::LanguageDefinitions;
+use crate::lang::{Dialect, LanguageDefinitions};
 use crate::semantic;
+use crate::semantic_tokens;
 use crate::settings::Settings;
+use crate::version::{Extension, VersionLabel};
+use crate::workspace::{self, WorkspaceIndex};
 
 fn get_server_info() -> ServerInfo {
     ServerInfo {
@@ -28,10 +34,28 @@ fn get_server_info() -> ServerInfo {
 
 fn get_server_capabilities() -> ServerCapabilities {
     ServerCapabilities {
-        // inlay_hint_provider: Some(OneOf::Left(true)),
+        inlay_hint_provider: Some(OneOf::Left(true)),
         definition_provider: Some(OneOf::Left(true)),
-        // references_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(OneOf::Left(true)),
+            }),
+            file_operations: None,
+        }),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
         hover_provider: Some(HoverProviderCapability::Simple(true)),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec![" ".to_string(), ",".to_string()]),
+            retrigger_characters: Some(vec![",".to_string()]),
+            work_done_progress_options: Default::default(),
+        }),
         completion_provider: Some(CompletionOptions {
             resolve_provider: Some(false),
             trigger_characters: Some(vec![".".to_string(), "$".to_string()]),
@@ -51,18 +75,45 @@ fn get_server_capabilities() -> ServerCapabilities {
         )),
         // Enables client diagnostic pulling, but we should prefer diagnostic pushing
         // diagnostic_provider: Some(DiagnosticServerCapabilities::Options({ DiagnosticOptions { identifier: None, inter_file_dependencies: false, workspace_diagnostics: true, work_done_progress_options: WorkDoneProgressOptions { work_done_progress: None, }, } })),
-
-        // Maybe implement semantic token provider for vscode
-        // semantic_tokens_provider: Some( SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions( SemanticTokensRegistrationOptions { text_document_registration_options: { TextDocumentRegistrationOptions { document_selector: Some(vec![DocumentFilter { language: Some("asm".to_string()), scheme: Some("file".to_string()), pattern: None, }]), } }, semantic_tokens_options: SemanticTokensOptions { work_done_progress_options: WorkDoneProgressOptions::default(), legend: SemanticTokensLegend { token_types: LEGEND_TYPE.into(), token_modifiers: vec![], }, range: Some(true), full: Some(SemanticTokensFullOptions::Bool(true)), }, static_registration_options: StaticRegistrationOptions::default(), },),),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+                legend: semantic_tokens::legend(),
+                range: Some(true),
+                full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+            },
+        )),
         ..ServerCapabilities::default()
     }
 }
 
+/// Assembly sources for the workspace index and the project configuration files.
+fn file_watchers() -> Vec<FileSystemWatcher> {
+    let sources = format!("**/*.{{{}}}", workspace::EXTENSIONS.join(","));
+    let configs = config::FILE_NAMES.iter().map(|name| format!("**/{}", name));
+    std::iter::once(sources)
+        .chain(configs)
+        .map(|glob| FileSystemWatcher {
+            glob_pattern: GlobPattern::String(glob),
+            kind: None,
+        })
+        .collect()
+}
+
+/// Dialect, version and extensions the language definitions are filtered for
+type DefinitionsKey = (Dialect, VersionLabel, Vec<Extension>);
+
 pub struct Backend {
     pub client: Client,
+    /// Settings sent by the client, before the project configuration is applied
     pub settings: RwLock<Settings>,
     pub documents: dashmap::DashMap<tower_lsp_server::ls_types::Uri, Arc<RwLock<Document>>>,
-    pub definitions: RwLock<LanguageDefinitions>,
+    /// Definitions for each dialect, version and set of extensions in use
+    pub definitions: RwLock<HashMap<DefinitionsKey, Arc<LanguageDefinitions>>>,
+    pub workspace: RwLock<WorkspaceIndex>,
+    pub workspace_folders: RwLock<Vec<PathBuf>>,
+    /// Project configuration found from the directory of each file
+    pub configs: RwLock<HashMap<PathBuf, Option<Arc<ProjectConfig>>>>,
 }
 
 impl Backend {
@@ -70,16 +121,56 @@ impl Backend {
         let documents = dashmap::DashMap::new();
 
         let default_settings = Settings::default();
-        let default_definitions = LanguageDefinitions::new();
-
         let settings = RwLock::new(default_settings);
-        let definitions = RwLock::new(default_definitions);
 
         Self {
             client,
             settings,
             documents,
-            definitions,
+            definitions: RwLock::new(HashMap::new()),
+            workspace: RwLock::new(WorkspaceIndex::default()),
+            workspace_folders: RwLock::new(Vec::new()),
+            configs: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the definitions for the dialect, version and extensions of the settings, parsing
+    /// them once.
+    pub async fn definitions_for(&self, settings: &Settings) -> Arc<LanguageDefinitions> {
+        let key = (
+            settings.dialect,
+            settings.version.label(),
+            settings.extensions.clone(),
+        );
+        if let Some(definitions) = self.definitions.read().await.get(&key) {
+            return definitions.clone();
+        }
+
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(settings);
+        self.definitions
+            .write()
+            .await
+            .entry(key)
+            .or_insert(Arc::new(definitions))
+            .clone()
+    }
+
+    /// Re-runs the analysis of all open documents, e.g. after the settings changed.
+    pub async fn analyze_open_documents(&self) {
+        let documents: Vec<_> = self
+            .documents
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+
+        for doc_arc in documents {
+            let mut doc = doc_arc.write().await;
+            let (settings, definitions) = self.document_settings(&doc.uri).await;
+            let diagnostics = doc.analyze(&definitions, &settings).await;
+            self.client
+                .publish_diagnostics(doc.uri.clone(), diagnostics, Some(doc.version))
+                .await;
         }
     }
 }
@@ -96,8 +187,16 @@ impl LanguageServer for Backend {
                 .map_err(|e| jsonrpc::Error::invalid_params(e.to_string()))?;
         }
 
-        let unpacked_settings = &self.settings.read().await;
-        self.definitions.write().await.parse(unpacked_settings);
+        #[allow(deprecated)]
+        let folders = match (params.workspace_folders, params.root_uri) {
+            (Some(folders), _) => folders.into_iter().map(|folder| folder.uri).collect(),
+            (None, Some(root)) => vec![root],
+            (None, None) => Vec::new(),
+        };
+        *self.workspace_folders.write().await = folders
+            .iter()
+            .filter_map(|uri| uri.to_file_path().map(|path| path.into_owned()))
+            .collect();
 
         Ok(InitializeResult {
             server_info: Some(get_server_info()),
@@ -108,6 +207,24 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         log!("Server initialized");
+
+        // Keep the workspace index and the project configuration up to date with changes made
+        // outside of the editor; fails for clients without dynamic registration, which then only
+        // pick up those changes after a restart
+        let registration = Registration {
+            id: "mipsls-watched-files".into(),
+            method: "workspace/didChangeWatchedFiles".into(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers: file_watchers(),
+            })
+            .ok(),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            log!("Failed to watch files: {}", e);
+        }
+
+        let folders = self.workspace_folders.read().await.clone();
+        self.index_workspace_folders(folders).await;
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
@@ -125,9 +242,7 @@ impl LanguageServer for Backend {
             return;
         }
 
-        let unpacked_settings = &self.settings.read().await;
-
-        self.definitions.write().await.parse(unpacked_settings);
+        self.analyze_open_documents().await;
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
@@ -140,15 +255,32 @@ impl LanguageServer for Backend {
         let mut document = Document::new(uri.clone(), version, text);
 
         // Analyze document and publish diagnostics
-        let diagnostics = document.analyze().await;
+        let (settings, definitions) = self.document_settings(&uri).await;
+        let diagnostics = document.analyze(&definitions, &settings).await;
         self.client
             .publish_diagnostics(uri.clone(), diagnostics, None)
             .await;
 
+        // Index symbols of the open document
+        self.workspace
+            .write()
+            .await
+            .update(uri.clone(), workspace::index_document(&document));
+
         // Store document text and tree
         self.documents.insert(uri, Arc::new(RwLock::new(document)));
     }
 
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        log!("textDocument/didClose");
+
+        let uri = params.text_document.uri;
+        self.documents.remove(&uri);
+
+        // Unsaved changes are discarded, fall back to the contents on disk
+        self.reindex_file_from_disk(&uri).await;
+    }
+
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
         let version = params.text_document.version;
@@ -177,10 +309,67 @@ impl LanguageServer for Backend {
             }
 
             // Analyze document and publish diagnostics
-            let diagnostics = doc.analyze().await;
+            let (settings, definitions) = self.document_settings(&uri).await;
+            let diagnostics = doc.analyze(&definitions, &settings).await;
             self.client
-                .publish_diagnostics(uri, diagnostics, None)
+                .publish_diagnostics(uri.clone(), diagnostics, None)
                 .await;
+
+            self.workspace
+                .write()
+                .await
+                .update(uri, workspace::index_document(&doc));
+        }
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        log!("workspace/didChangeWorkspaceFolders");
+
+        let to_path = |folder: WorkspaceFolder| folder.uri.to_file_path().map(|p| p.into_owned());
+        let removed: Vec<PathBuf> = params
+            .event
+            .removed
+            .into_iter()
+            .filter_map(to_path)
+            .collect();
+        let added: Vec<PathBuf> = params.event.added.into_iter().filter_map(to_path).collect();
+
+        {
+            let mut folders = self.workspace_folders.write().await;
+            folders.retain(|folder| !removed.contains(folder));
+            folders.extend(added.iter().cloned());
+        }
+
+        self.workspace.write().await.files.retain(|uri, _| {
+            uri.to_file_path()
+                .is_none_or(|path| !removed.iter().any(|folder| path.starts_with(folder)))
+        });
+        self.index_workspace_folders(added).await;
+
+        // Files may now belong to another folder with its own project configuration
+        self.reload_project_configs().await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        log!("workspace/didChangeWatchedFiles");
+
+        if params.changes.iter().any(|change| {
+            change
+                .uri
+                .to_file_path()
+                .is_some_and(|path| config::is_config_file(&path))
+        }) {
+            self.reload_project_configs().await;
+        }
+
+        for change in params.changes {
+            let is_assembly = change
+                .uri
+                .to_file_path()
+                .is_some_and(|path| workspace::is_assembly_file(&path));
+            if is_assembly && !self.documents.contains_key(&change.uri) {
+                self.reindex_file_from_disk(&change.uri).await;
+            }
         }
     }
 
@@ -202,6 +391,67 @@ impl LanguageServer for Backend {
         self.handle_hover(params).await
     }
 
+    async fn inlay_hint(&self, params: InlayHintParams) -> jsonrpc::Result<Option<Vec<InlayHint>>> {
+        self.handle_inlay_hint(params).await
+    }
+
+    async fn signature_help(
+        &self,
+        params: SignatureHelpParams,
+    ) -> jsonrpc::Result<Option<SignatureHelp>> {
+        self.handle_signature_help(params).await
+    }
+
+    async fn references(&self, params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
+        self.handle_references(params).await
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        self.handle_document_symbol(params).await
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<WorkspaceSymbolResponse>> {
+        self.handle_workspace_symbol(params).await
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensResult>> {
+        self.handle_semantic_tokens_full(params).await
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensFullDeltaResult>> {
+        self.handle_semantic_tokens_full_delta(params).await
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensRangeResult>> {
+        self.handle_semantic_tokens_range(params).await
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<PrepareRenameResponse>> {
+        self.handle_prepare_rename(params).await
+    }
+
+    async fn rename(&self, params: RenameParams) -> jsonrpc::Result<Option<WorkspaceEdit>> {
+        self.handle_rename(params).await
+    }
+
     // Used for diagnostic pulling, but we prefer pushing model
     // async fn diagnostic(
     //     &self,
@@ -223,7 +473,14 @@ pub async fn serve() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(Backend::new);
+    let (service, socket) = LspService::build(Backend::new)
+        .custom_method("mipsls/memoryLayout", Backend::handle_memory_layout)
+        .custom_method(
+            "mipsls/controlFlowGraph",
+            Backend::handle_control_flow_graph,
+        )
+        .custom_method("mipsls/run", Backend::handle_run)
+        .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 
     log!("Stopping MIPS language server");
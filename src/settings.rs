@@ -1,15 +1,18 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{Number, Value};
 use std::fmt;
+use std::path::PathBuf;
 
-use crate::lang::{Dialect, InstructionVariant};
+use crate::lang::{Abi, Dialect, InstructionVariant};
 use crate::version;
 
 #[derive(Debug)]
 pub enum SettingsError {
     UnknownDialect(String),
+    UnknownAbi(String),
     UnknownISA(String),
     UnknownVersion(String),
+    UnknownExtension(String),
     InvalidRevision(u32),
     InvalidSyntax,
 }
@@ -18,10 +21,12 @@ impl fmt::Display for SettingsError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             SettingsError::UnknownDialect(d) => write!(f, "unknown dialect: `{}`", d),
+            SettingsError::UnknownAbi(a) => write!(f, "unknown ABI: `{}`", a),
             SettingsError::UnknownISA(i) => write!(f, "unknown ISA: `{}`", i),
             SettingsError::UnknownVersion(i) => write!(f, "unknown version: `{}`", i),
+            SettingsError::UnknownExtension(e) => write!(f, "unknown extension: `{}`", e),
             SettingsError::InvalidRevision(r) => write!(f, "invalid revision: {}", r),
-            SettingsError::InvalidSyntax => write!(f, "failed to parse initialization options"),
+            SettingsError::InvalidSyntax => write!(f, "failed to parse settings"),
         }
     }
 }
@@ -36,12 +41,33 @@ impl From<serde_json::Error> for SettingsError {
 pub struct RawSettings {
     dialect: Option<String>,
     version: Option<String>,
+    extensions: Option<Vec<String>>,
+    abi: Option<String>,
+    #[serde(rename = "expansionHints")]
+    expansion_hints: Option<bool>,
+    #[serde(rename = "encodingHints")]
+    encoding_hints: Option<bool>,
+    #[serde(rename = "includePaths")]
+    include_paths: Option<Vec<PathBuf>>,
+    #[serde(rename = "disabledDiagnostics")]
+    disabled_diagnostics: Option<Vec<String>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Settings {
     pub dialect: Dialect,
     pub version: &'static version::Version,
+    /// application-specific extensions the target implements, sorted
+    pub extensions: Vec<version::Extension>,
+    pub abi: Abi,
+    /// whether pseudo-instructions get their expansion as inlay hint
+    pub expansion_hints: bool,
+    /// whether machine instructions get their encoding as inlay hint
+    pub encoding_hints: bool,
+    /// directories searched for files included with `.include`
+    pub include_paths: Vec<PathBuf>,
+    /// codes of diagnostics that are not reported, e.g. `E011`
+    pub disabled_diagnostics: Vec<String>,
 }
 
 impl Settings {
@@ -49,6 +75,12 @@ impl Settings {
         Settings {
             dialect: Dialect::Unspecified,
             version: &version::MIPS64R5,
+            extensions: Vec::new(),
+            abi: Abi::None,
+            expansion_hints: false,
+            encoding_hints: false,
+            include_paths: Vec::new(),
+            disabled_diagnostics: Vec::new(),
         }
     }
     // pub fn new(options: Option<Value>) -> Result<Self, SettingsError> {
@@ -82,6 +114,35 @@ impl Settings {
             self.version = version::Version::parse(v)?;
         };
 
+        if let Some(extensions) = raw_settings.extensions {
+            self.extensions = extensions
+                .iter()
+                .map(|e| version::Extension::parse(e))
+                .collect::<Result<_, _>>()?;
+            self.extensions.sort();
+            self.extensions.dedup();
+        }
+
+        if let Some(a) = raw_settings.abi.as_deref() {
+            self.abi = Abi::parse(a)?;
+        }
+
+        if let Some(hints) = raw_settings.expansion_hints {
+            self.expansion_hints = hints;
+        }
+
+        if let Some(hints) = raw_settings.encoding_hints {
+            self.encoding_hints = hints;
+        }
+
+        if let Some(paths) = raw_settings.include_paths {
+            self.include_paths = paths;
+        }
+
+        if let Some(codes) = raw_settings.disabled_diagnostics {
+            self.disabled_diagnostics = codes;
+        }
+
         Ok(())
     }
 
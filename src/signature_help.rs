@@ -0,0 +1,224 @@
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{InstructionNode, OperandListItem, SyntaxNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{InstructionVariant, LanguageDefinitions};
+use crate::operands::{self, ValueClass};
+use crate::server::Backend;
+
+impl Backend {
+    pub async fn handle_signature_help(
+        &self,
+        params: SignatureHelpParams,
+    ) -> jsonrpc::Result<Option<SignatureHelp>> {
+        log!("textDocument/signatureHelp");
+
+        let TextDocumentPositionParams {
+            position,
+            text_document,
+        } = params.text_document_position_params;
+
+        let doc_arc = self
+            .documents
+            .get(&text_document.uri)
+            .ok_or(jsonrpc::Error::invalid_request())?;
+        let doc = doc_arc.read().await;
+
+        let (_, definitions) = self.document_settings(&doc.uri).await;
+
+        Ok(signature_help(&doc, &definitions, position))
+    }
+}
+
+/// Lists the operand forms of the instruction at the given position.
+///
+/// Variants that cannot match the operands already written before the cursor are left out.
+/// The active parameter is the operand the cursor is in, counted by the preceding commas.
+pub fn signature_help(
+    doc: &Document,
+    definitions: &LanguageDefinitions,
+    position: Position,
+) -> Option<SignatureHelp> {
+    let text = &doc.text;
+    let cursor = doc.position_to_byte(&position);
+    let row = position.line as usize;
+
+    let node = doc.ast.items.iter().find_map(|statement| match statement {
+        SyntaxNode::Instruction(node) if node.mnemonic.range.start_point.row == row => Some(node),
+        _ => None,
+    })?;
+
+    // Only show help after the mnemonic, not while it is still being typed
+    if cursor <= node.mnemonic.range.end_byte {
+        return None;
+    }
+
+    let mnemonic = get_text_in_ts_range(text, node.mnemonic.range);
+    let instruction = definitions
+        .instructions
+        .get(&mnemonic.to_ascii_lowercase())?;
+
+    let (active_parameter, written) = written_operands(text, node, cursor);
+
+    let variants: Vec<&InstructionVariant> = instruction
+        .variants
+        .iter()
+        .filter(|variant| {
+            variant.operands.len() > active_parameter
+                && operands::variant_accepts_prefix(variant, &written)
+        })
+        .collect();
+
+    if variants.is_empty() {
+        return None;
+    }
+
+    let signatures = variants
+        .iter()
+        .map(|variant| create_signature(mnemonic, variant, active_parameter))
+        .collect();
+
+    Some(SignatureHelp {
+        signatures,
+        active_signature: Some(0),
+        active_parameter: Some(active_parameter as u32),
+    })
+}
+
+/// Returns the index of the operand under the cursor and the classes of the operands
+/// completed before it.
+///
+/// Commas are located in the source text, since a trailing comma while typing is not part of
+/// a well-formed operand list.
+fn written_operands(text: &str, node: &InstructionNode, cursor: usize) -> (usize, Vec<ValueClass>) {
+    let start = node.mnemonic.range.end_byte;
+    let commas = top_level_commas(&text[start..cursor.min(text.len())])
+        .into_iter()
+        .map(|offset| start + offset)
+        .collect::<Vec<_>>();
+
+    let written = commas
+        .iter()
+        .enumerate()
+        .map(|(index, &comma)| {
+            let segment_start = if index == 0 { start } else { commas[index - 1] };
+            node.operands
+                .iter()
+                .find_map(|item| match item {
+                    OperandListItem::Operand(value)
+                        if (segment_start..comma).contains(&value.range().start_byte) =>
+                    {
+                        Some(ValueClass::of(text, value))
+                    }
+                    _ => None,
+                })
+                .unwrap_or(ValueClass::Unknown)
+        })
+        .collect();
+
+    (commas.len(), written)
+}
+
+/// Byte offsets of the commas outside of parentheses, strings and comments.
+fn top_level_commas(operands: &str) -> Vec<usize> {
+    let mut commas = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (offset, c) in operands.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => commas.push(offset),
+            '#' | '\n' => break,
+            _ => {}
+        }
+    }
+
+    commas
+}
+
+fn create_signature(
+    mnemonic: &str,
+    variant: &InstructionVariant,
+    active_parameter: usize,
+) -> SignatureInformation {
+    let mut label = mnemonic.to_string();
+    let mut parameters = Vec::new();
+
+    for (index, operand) in variant.operands.iter().enumerate() {
+        label.push_str(if index == 0 { " " } else { ", " });
+        let start = label.encode_utf16().count() as u32;
+        label.push_str(operand);
+        let end = label.encode_utf16().count() as u32;
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, end]),
+            documentation: None,
+        });
+    }
+
+    SignatureInformation {
+        label,
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: variant.description.clone(),
+        })),
+        parameters: Some(parameters),
+        active_parameter: Some(active_parameter as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::test_document;
+    use crate::settings::Settings;
+
+    fn help_at(text: &str, line: u32, character: u32) -> Option<SignatureHelp> {
+        let doc = test_document(text);
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&Settings::default());
+
+        signature_help(&doc, &definitions, Position::new(line, character))
+    }
+
+    #[test]
+    fn active_parameter_follows_commas() {
+        let help = help_at("main:\n  addi $t0, $t1, 4\n", 1, 7).unwrap();
+        assert_eq!(help.active_parameter, Some(0));
+        assert_eq!(help.signatures[0].label, "addi rt, rs, imm16_s");
+
+        let help = help_at("main:\n  addi $t0, $t1, 4\n", 1, 18).unwrap();
+        assert_eq!(help.active_parameter, Some(2));
+
+        // Operand list still being typed
+        let help = help_at("main:\n  add $t0, \n  jr $ra\n", 1, 11).unwrap();
+        assert_eq!(help.active_parameter, Some(1));
+    }
+
+    #[test]
+    fn filters_variants_by_written_operands() {
+        let help = help_at("  li $t0, 4\n", 0, 10).unwrap();
+        assert_eq!(help.signatures.len(), 3);
+
+        let help = help_at("  beq $t0, 4, end\n", 0, 14).unwrap();
+        assert_eq!(help.signatures.len(), 1);
+        assert_eq!(help.signatures[0].label, "beq rs, imm32_s, label");
+
+        assert!(help_at("  addi $f0, $t1, 4\n", 0, 12).is_none());
+    }
+}
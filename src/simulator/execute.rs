@@ -0,0 +1,591 @@
+//! Semantics of the instructions, including the pseudo-instructions of MARS and GAS.
+
+use super::program::{Instruction, Operand};
+use super::syscall::Console;
+use super::{Flow, Machine};
+
+const RA: usize = 31;
+
+type Result<T> = std::result::Result<T, String>;
+
+fn operand_error(mnemonic: &str) -> String {
+    format!("unexpected operands for `{}`", mnemonic)
+}
+
+impl Machine {
+    pub(super) fn execute(
+        &mut self,
+        instruction: &Instruction,
+        console: &mut dyn Console,
+    ) -> Result<Flow> {
+        let ops = instruction.operands.as_ref().map_err(Clone::clone)?;
+        let mnemonic = instruction.mnemonic.as_str();
+        let next = self.pc.wrapping_add(instruction.size);
+
+        match mnemonic {
+            "nop" | "ssnop" | "ehb" | "sync" | "pause" => {}
+            "add" | "addi" | "sub" | "subi" => {
+                let (d, a, b) = self.binary(mnemonic, ops)?;
+                let result = if mnemonic.starts_with("add") {
+                    (a as i32).checked_add(b as i32)
+                } else {
+                    (a as i32).checked_sub(b as i32)
+                };
+                let result = result.ok_or("arithmetic overflow")?;
+                self.set(d, result as u32);
+            }
+            "addu" | "addiu" | "subu" | "subiu" | "and" | "andi" | "or" | "ori" | "xor"
+            | "xori" | "nor" | "slt" | "slti" | "sltu" | "sltiu" | "sll" | "sllv" | "srl"
+            | "srlv" | "sra" | "srav" | "rotr" | "rotrv" | "ror" | "rol" | "mul" | "seq"
+            | "sne" | "sge" | "sgeu" | "sgt" | "sgtu" | "sle" | "sleu" => {
+                let (d, a, b) = self.binary(mnemonic, ops)?;
+                self.set(d, alu(mnemonic, a, b));
+            }
+            "mult" | "multu" | "madd" | "maddu" | "msub" | "msubu" => {
+                let [a, b] = ops.as_slice() else {
+                    return Err(operand_error(mnemonic));
+                };
+                let (a, b) = (self.value(a)?, self.value(b)?);
+                let product = if mnemonic.ends_with('u') {
+                    (a as u64 * b as u64) as i64
+                } else {
+                    a as i32 as i64 * b as i32 as i64
+                };
+                let accumulator = ((self.hi as u64) << 32 | self.lo as u64) as i64;
+                let result = match mnemonic.trim_end_matches('u') {
+                    "madd" => accumulator.wrapping_add(product),
+                    "msub" => accumulator.wrapping_sub(product),
+                    _ => product,
+                };
+                self.hi = (result >> 32) as u32;
+                self.lo = result as u32;
+            }
+            "div" | "divu" if ops.len() == 2 => {
+                let (a, b) = (self.value(&ops[0])?, self.value(&ops[1])?);
+                // The result of a division by zero is undefined; the registers keep their values
+                if b != 0 {
+                    (self.lo, self.hi) = divide(mnemonic == "divu", a, b);
+                }
+            }
+            "div" | "divu" | "rem" | "remu" => {
+                let (d, a, b) = self.binary(mnemonic, ops)?;
+                if b == 0 {
+                    return Err("division by zero".to_string());
+                }
+                (self.lo, self.hi) = divide(mnemonic.ends_with('u'), a, b);
+                let result = if mnemonic.starts_with("rem") {
+                    self.hi
+                } else {
+                    self.lo
+                };
+                self.set(d, result);
+            }
+            "mfhi" => self.set(self.register(mnemonic, ops, 0)?, self.hi),
+            "mflo" => self.set(self.register(mnemonic, ops, 0)?, self.lo),
+            "mthi" => self.hi = self.value(ops.first().ok_or(operand_error(mnemonic))?)?,
+            "mtlo" => self.lo = self.value(ops.first().ok_or(operand_error(mnemonic))?)?,
+            "lui" => {
+                let value = self.value(ops.get(1).ok_or(operand_error(mnemonic))?)?;
+                self.set(self.register(mnemonic, ops, 0)?, value << 16);
+            }
+            "li" | "la" | "move" | "not" | "neg" | "negu" | "abs" | "clz" | "clo" | "seb"
+            | "seh" | "wsbh" => {
+                let [d, s] = ops.as_slice() else {
+                    return Err(operand_error(mnemonic));
+                };
+                let d = self.register(mnemonic, ops, 0)?;
+                let value = match s {
+                    Operand::Memory { .. } => self.address(s)?,
+                    s => self.value(s)?,
+                };
+                let result = match mnemonic {
+                    "not" => !value,
+                    "neg" => (0i32)
+                        .checked_sub(value as i32)
+                        .ok_or("arithmetic overflow")? as u32,
+                    "negu" => value.wrapping_neg(),
+                    "abs" => (value as i32).wrapping_abs() as u32,
+                    "clz" => value.leading_zeros(),
+                    "clo" => value.leading_ones(),
+                    "seb" => value as u8 as i8 as u32,
+                    "seh" => value as u16 as i16 as u32,
+                    "wsbh" => (value & 0xff00_ff00) >> 8 | (value & 0x00ff_00ff) << 8,
+                    _ => value,
+                };
+                self.set(d, result);
+            }
+            "movn" | "movz" => {
+                let (d, a, b) = self.binary(mnemonic, ops)?;
+                if (b != 0) == (mnemonic == "movn") {
+                    self.set(d, a);
+                }
+            }
+            "ext" | "ins" => {
+                let [_, s, position, size] = ops.as_slice() else {
+                    return Err(operand_error(mnemonic));
+                };
+                let d = self.register(mnemonic, ops, 0)?;
+                let (s, position, size) =
+                    (self.value(s)?, self.value(position)?, self.value(size)?);
+                if position + size > 32 || size == 0 {
+                    return Err(format!("invalid bit field for `{}`", mnemonic));
+                }
+                let mask = (u64::MAX >> (64 - size)) as u32;
+                let result = if mnemonic == "ext" {
+                    (s >> position) & mask
+                } else {
+                    self.registers[d] & !(mask << position) | (s & mask) << position
+                };
+                self.set(d, result);
+            }
+
+            // Branches and jumps
+            "b" | "j" => return Ok(Flow::Jump(self.target(mnemonic, ops, 0)?)),
+            "bal" | "jal" => {
+                self.set(RA, next);
+                return Ok(Flow::Jump(self.target(mnemonic, ops, 0)?));
+            }
+            "jr" => {
+                return Ok(Flow::Jump(
+                    self.value(ops.first().ok_or(operand_error(mnemonic))?)?,
+                ))
+            }
+            "jalr" => {
+                let (link, target) = match ops.as_slice() {
+                    [target] => (RA, target),
+                    [link, target] => (self.register(mnemonic, ops, 0)?, target),
+                    _ => return Err(operand_error(mnemonic)),
+                };
+                let target = self.value(target)?;
+                self.set(link, next);
+                return Ok(Flow::Jump(target));
+            }
+            "beq" | "bne" | "blt" | "bltu" | "bgt" | "bgtu" | "ble" | "bleu" | "bge" | "bgeu" => {
+                let [a, b, _] = ops.as_slice() else {
+                    return Err(operand_error(mnemonic));
+                };
+                let (a, b) = (self.value(a)?, self.value(b)?);
+                let (signed_a, signed_b) = (a as i32, b as i32);
+                let taken = match mnemonic {
+                    "beq" => a == b,
+                    "bne" => a != b,
+                    "blt" => signed_a < signed_b,
+                    "bltu" => a < b,
+                    "bgt" => signed_a > signed_b,
+                    "bgtu" => a > b,
+                    "ble" => signed_a <= signed_b,
+                    "bleu" => a <= b,
+                    "bge" => signed_a >= signed_b,
+                    _ => a >= b,
+                };
+                if taken {
+                    return Ok(Flow::Jump(self.target(mnemonic, ops, 2)?));
+                }
+            }
+            "beqz" | "bnez" | "blez" | "bgtz" | "bltz" | "bgez" | "bltzal" | "bgezal" => {
+                let [a, _] = ops.as_slice() else {
+                    return Err(operand_error(mnemonic));
+                };
+                let a = self.value(a)? as i32;
+                let taken = match mnemonic {
+                    "beqz" => a == 0,
+                    "bnez" => a != 0,
+                    "blez" => a <= 0,
+                    "bgtz" => a > 0,
+                    "bltz" | "bltzal" => a < 0,
+                    _ => a >= 0,
+                };
+                if mnemonic.ends_with("al") {
+                    self.set(RA, next);
+                }
+                if taken {
+                    return Ok(Flow::Jump(self.target(mnemonic, ops, 1)?));
+                }
+            }
+
+            // Memory
+            "lb" | "lbu" | "lh" | "lhu" | "lw" | "ll" | "ulw" => {
+                let d = self.register(mnemonic, ops, 0)?;
+                let address = self.address(ops.get(1).ok_or(operand_error(mnemonic))?)?;
+                let value = match mnemonic {
+                    "lb" => self.memory.load_byte(address) as i8 as u32,
+                    "lbu" => self.memory.load_byte(address) as u32,
+                    "lh" => self.memory.load_half(aligned(address, 2)?) as i16 as u32,
+                    "lhu" => self.memory.load_half(aligned(address, 2)?) as u32,
+                    "ulw" => self.memory.load_word(address),
+                    _ => self.memory.load_word(aligned(address, 4)?),
+                };
+                self.set(d, value);
+            }
+            "sb" | "sh" | "sw" | "sc" | "usw" => {
+                let value = self.value(ops.first().ok_or(operand_error(mnemonic))?)?;
+                let address = self.address(ops.get(1).ok_or(operand_error(mnemonic))?)?;
+                match mnemonic {
+                    "sb" => self.memory.store_byte(address, value as u8),
+                    "sh" => self.memory.store_half(aligned(address, 2)?, value as u16),
+                    "usw" => self.memory.store_word(address, value),
+                    _ => self.memory.store_word(aligned(address, 4)?, value),
+                }
+                if mnemonic == "sc" {
+                    self.set(self.register(mnemonic, ops, 0)?, 1);
+                }
+            }
+
+            // Exceptions
+            "teq" | "teqi" | "tne" | "tnei" | "tge" | "tgei" | "tgeu" | "tgeiu" | "tlt"
+            | "tlti" | "tltu" | "tltiu" => {
+                let [a, b, ..] = ops.as_slice() else {
+                    return Err(operand_error(mnemonic));
+                };
+                let (a, b) = (self.value(a)?, self.value(b)?);
+                let trapped = match mnemonic.trim_end_matches('u').trim_end_matches('i') {
+                    "teq" => a == b,
+                    "tne" => a != b,
+                    "tge" if mnemonic.ends_with('u') => a >= b,
+                    "tge" => a as i32 >= b as i32,
+                    _ if mnemonic.ends_with('u') => a < b,
+                    _ => (a as i32) < b as i32,
+                };
+                if trapped {
+                    return Err(format!("trap raised by `{}`", mnemonic));
+                }
+            }
+            "break" => return Err("break instruction executed".to_string()),
+            "syscall" => return self.syscall(console),
+
+            _ if mnemonic.contains('.') || mnemonic.contains("c1") => {
+                return self.execute_float(mnemonic, ops, next)
+            }
+            _ => return Err(format!("`{}` is not supported by the simulator", mnemonic)),
+        }
+        Ok(Flow::Next)
+    }
+
+    fn execute_float(&mut self, mnemonic: &str, ops: &[Operand], next: u32) -> Result<Flow> {
+        let double = mnemonic.ends_with(".d");
+        match mnemonic {
+            "mfc1" | "mtc1" => {
+                let d = self.register(mnemonic, ops, 0)?;
+                let f = self.float_register(mnemonic, ops, 1)?;
+                if mnemonic == "mfc1" {
+                    self.set(d, self.fpr[f]);
+                } else {
+                    self.fpr[f] = self.registers[d];
+                }
+            }
+            "lwc1" | "l.s" | "swc1" | "s.s" | "ldc1" | "l.d" | "sdc1" | "s.d" => {
+                let f = self.float_register(mnemonic, ops, 0)?;
+                let address = self.address(ops.get(1).ok_or(operand_error(mnemonic))?)?;
+                let words = if matches!(mnemonic, "ldc1" | "l.d" | "sdc1" | "s.d") {
+                    even(f)?;
+                    2
+                } else {
+                    1
+                };
+                let address = aligned(address, 4 * words)?;
+                for word in 0..words {
+                    let address = address + 4 * word;
+                    let register = f + word as usize;
+                    if mnemonic.starts_with('l') {
+                        self.fpr[register] = self.memory.load_word(address);
+                    } else {
+                        self.memory.store_word(address, self.fpr[register]);
+                    }
+                }
+            }
+            "bc1t" | "bc1f" => {
+                let flag = match ops {
+                    [_] => 0,
+                    [flag, _] => self.value(flag)?,
+                    _ => return Err(operand_error(mnemonic)),
+                };
+                let set = self.condition_flags & (1 << (flag & 7)) != 0;
+                if set == (mnemonic == "bc1t") {
+                    return Ok(Flow::Jump(self.target(mnemonic, ops, ops.len() - 1)?));
+                }
+            }
+            _ if mnemonic.starts_with("c.") => {
+                let (flag, s, t) = match ops {
+                    [_, _] => (0, 0, 1),
+                    [flag, _, _] => (self.value(flag)?, 1, 2),
+                    _ => return Err(operand_error(mnemonic)),
+                };
+                let (a, b) = (
+                    self.float(mnemonic, ops, s, double)?,
+                    self.float(mnemonic, ops, t, double)?,
+                );
+                let condition = match mnemonic.split('.').nth(1) {
+                    Some("eq") => a == b,
+                    Some("lt") => a < b,
+                    Some("le") => a <= b,
+                    Some("un") => a.is_nan() || b.is_nan(),
+                    Some("ueq") => a == b || a.is_nan() || b.is_nan(),
+                    Some("olt") => a < b,
+                    Some("ult") => a < b || a.is_nan() || b.is_nan(),
+                    Some("ole") => a <= b,
+                    Some("ule") => a <= b || a.is_nan() || b.is_nan(),
+                    Some("f") => false,
+                    _ => return Err(format!("`{}` is not supported by the simulator", mnemonic)),
+                };
+                let bit = 1 << (flag & 7);
+                if condition {
+                    self.condition_flags |= bit;
+                } else {
+                    self.condition_flags &= !bit;
+                }
+            }
+            _ => {
+                let Some((operation, _)) = mnemonic.split_once('.') else {
+                    return Err(format!("`{}` is not supported by the simulator", mnemonic));
+                };
+                let d = self.float_register(mnemonic, ops, 0)?;
+                match (operation, ops.len()) {
+                    ("add" | "sub" | "mul" | "div", 3) => {
+                        let a = self.float(mnemonic, ops, 1, double)?;
+                        let b = self.float(mnemonic, ops, 2, double)?;
+                        let result = match operation {
+                            "add" => a + b,
+                            "sub" => a - b,
+                            "mul" => a * b,
+                            _ => a / b,
+                        };
+                        self.set_float(d, result, double)?;
+                    }
+                    ("sqrt" | "abs" | "neg" | "mov", 2) => {
+                        let a = self.float(mnemonic, ops, 1, double)?;
+                        let result = match operation {
+                            "sqrt" => a.sqrt(),
+                            "abs" => a.abs(),
+                            "neg" => -a,
+                            _ => a,
+                        };
+                        self.set_float(d, result, double)?;
+                    }
+                    ("movn" | "movz", 3) => {
+                        let condition = self.value(&ops[2])? != 0;
+                        if condition == (operation == "movn") {
+                            let a = self.float(mnemonic, ops, 1, double)?;
+                            self.set_float(d, a, double)?;
+                        }
+                    }
+                    ("movf" | "movt", 2 | 3) => {
+                        let flag = match ops.get(2) {
+                            Some(flag) => self.value(flag)?,
+                            None => 0,
+                        };
+                        let set = self.condition_flags & (1 << (flag & 7)) != 0;
+                        if set == (operation == "movt") {
+                            let a = self.float(mnemonic, ops, 1, double)?;
+                            self.set_float(d, a, double)?;
+                        }
+                    }
+                    ("cvt" | "round" | "trunc" | "floor" | "ceil", 2) => {
+                        self.convert(mnemonic, ops, d)?;
+                    }
+                    _ => return Err(format!("`{}` is not supported by the simulator", mnemonic)),
+                }
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Conversions between words, singles and doubles: `cvt.d.s`, `trunc.w.d`, ...
+    fn convert(&mut self, mnemonic: &str, ops: &[Operand], d: usize) -> Result<()> {
+        let parts: Vec<&str> = mnemonic.split('.').collect();
+        let [operation, to, from] = parts.as_slice() else {
+            return Err(format!("`{}` is not supported by the simulator", mnemonic));
+        };
+        let value = match *from {
+            "w" => self.fpr[self.float_register(mnemonic, ops, 1)?] as i32 as f64,
+            "s" => self.float(mnemonic, ops, 1, false)?,
+            "d" => self.float(mnemonic, ops, 1, true)?,
+            _ => return Err(format!("`{}` is not supported by the simulator", mnemonic)),
+        };
+        match *to {
+            "w" => {
+                let rounded = match *operation {
+                    "trunc" => value.trunc(),
+                    "floor" => value.floor(),
+                    "ceil" => value.ceil(),
+                    _ => value.round_ties_even(),
+                };
+                self.fpr[d] = rounded as i32 as u32;
+            }
+            "s" => self.set_float(d, value, false)?,
+            "d" => self.set_float(d, value, true)?,
+            _ => return Err(format!("`{}` is not supported by the simulator", mnemonic)),
+        }
+        Ok(())
+    }
+
+    /// Operands of three-operand instructions, where the destination may be left out, as in
+    /// `addu $t0, $t1` or `addi $t0, 4`.
+    fn binary(&self, mnemonic: &str, ops: &[Operand]) -> Result<(usize, u32, u32)> {
+        let (d, a, b) = match ops {
+            [d, a, b] => (d, a, b),
+            [d, b] => (d, d, b),
+            _ => return Err(operand_error(mnemonic)),
+        };
+        let Operand::Gpr(d) = d else {
+            return Err(operand_error(mnemonic));
+        };
+        Ok((*d as usize, self.value(a)?, self.value(b)?))
+    }
+
+    /// General purpose register written by an instruction.
+    fn register(&self, mnemonic: &str, ops: &[Operand], index: usize) -> Result<usize> {
+        match ops.get(index) {
+            Some(Operand::Gpr(number)) => Ok(*number as usize),
+            _ => Err(operand_error(mnemonic)),
+        }
+    }
+
+    fn float_register(&self, mnemonic: &str, ops: &[Operand], index: usize) -> Result<usize> {
+        match ops.get(index) {
+            Some(Operand::Fpr(number)) => Ok(*number as usize),
+            _ => Err(operand_error(mnemonic)),
+        }
+    }
+
+    /// Value of a register or immediate operand.
+    fn value(&self, operand: &Operand) -> Result<u32> {
+        match operand {
+            Operand::Gpr(number) => Ok(self.registers[*number as usize]),
+            Operand::Value(value) => Ok(*value as u32),
+            Operand::Fpr(_) | Operand::Memory { .. } => {
+                Err("expected a general purpose register or a value".to_string())
+            }
+        }
+    }
+
+    /// Address of a memory operand, a label or an absolute address.
+    fn address(&self, operand: &Operand) -> Result<u32> {
+        match operand {
+            Operand::Memory { offset, base } => {
+                Ok(self.registers[*base as usize].wrapping_add(*offset as u32))
+            }
+            Operand::Value(value) => Ok(*value as u32),
+            _ => Err("expected an address".to_string()),
+        }
+    }
+
+    fn target(&self, mnemonic: &str, ops: &[Operand], index: usize) -> Result<u32> {
+        match ops.get(index) {
+            Some(Operand::Value(value)) => Ok(*value as u32),
+            _ => Err(operand_error(mnemonic)),
+        }
+    }
+
+    fn set(&mut self, register: usize, value: u32) {
+        if register != 0 {
+            self.registers[register] = value;
+        }
+    }
+
+    fn float(&self, mnemonic: &str, ops: &[Operand], index: usize, double: bool) -> Result<f64> {
+        let register = self.float_register(mnemonic, ops, index)?;
+        if double {
+            even(register)?;
+            let bits = (self.fpr[register + 1] as u64) << 32 | self.fpr[register] as u64;
+            Ok(f64::from_bits(bits))
+        } else {
+            Ok(f32::from_bits(self.fpr[register]) as f64)
+        }
+    }
+
+    pub(super) fn set_float(&mut self, register: usize, value: f64, double: bool) -> Result<()> {
+        if double {
+            even(register)?;
+            let bits = value.to_bits();
+            self.fpr[register] = bits as u32;
+            self.fpr[register + 1] = (bits >> 32) as u32;
+        } else {
+            self.fpr[register] = (value as f32).to_bits();
+        }
+        Ok(())
+    }
+}
+
+/// Result of the register-register and register-immediate operations that cannot fail.
+fn alu(mnemonic: &str, a: u32, b: u32) -> u32 {
+    let (signed_a, signed_b) = (a as i32, b as i32);
+    match mnemonic {
+        "addu" | "addiu" => a.wrapping_add(b),
+        "subu" | "subiu" => a.wrapping_sub(b),
+        "and" | "andi" => a & b,
+        "or" | "ori" => a | b,
+        "xor" | "xori" => a ^ b,
+        "nor" => !(a | b),
+        "slt" | "slti" => (signed_a < signed_b) as u32,
+        "sltu" | "sltiu" => (a < b) as u32,
+        "sll" | "sllv" => a << (b & 31),
+        "srl" | "srlv" => a >> (b & 31),
+        "sra" | "srav" => (signed_a >> (b & 31)) as u32,
+        "rotr" | "rotrv" | "ror" => a.rotate_right(b & 31),
+        "rol" => a.rotate_left(b & 31),
+        "mul" => signed_a.wrapping_mul(signed_b) as u32,
+        "seq" => (a == b) as u32,
+        "sne" => (a != b) as u32,
+        "sge" => (signed_a >= signed_b) as u32,
+        "sgeu" => (a >= b) as u32,
+        "sgt" => (signed_a > signed_b) as u32,
+        "sgtu" => (a > b) as u32,
+        "sle" => (signed_a <= signed_b) as u32,
+        _ => (a <= b) as u32,
+    }
+}
+
+/// Quotient and remainder, wrapping for the overflow of `0x80000000 / -1`.
+fn divide(unsigned: bool, a: u32, b: u32) -> (u32, u32) {
+    if unsigned {
+        (a / b, a % b)
+    } else {
+        let (a, b) = (a as i32, b as i32);
+        (a.wrapping_div(b) as u32, a.wrapping_rem(b) as u32)
+    }
+}
+
+fn aligned(address: u32, bytes: u32) -> Result<u32> {
+    if address.is_multiple_of(bytes) {
+        Ok(address)
+    } else {
+        Err(format!(
+            "address 0x{:08x} is not aligned to a {}-byte boundary",
+            address, bytes
+        ))
+    }
+}
+
+fn even(register: usize) -> Result<()> {
+    if register.is_multiple_of(2) && register < 31 {
+        Ok(())
+    } else {
+        Err(format!(
+            "`$f{}` cannot hold a double, which needs an even register",
+            register
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::run;
+    use super::super::Status;
+    use crate::lang::Dialect;
+
+    #[test]
+    fn executes_integer_and_float_instructions() {
+        let text = ".data\npi: .double 3.5\nhalf: .float 0.5\nbytes: .byte -1, 2\n.text\nmain:\n  li $t0, -7\n  li $t1, 2\n  div $t0, $t1\n  mflo $s0\n  mfhi $s1\n  sra $s2, $t0, 1\n  srl $s3, $t0, 28\n  lb $s4, bytes\n  lbu $s5, bytes\n  sltu $s6, $t1, $t0\n  l.d $f2, pi\n  l.s $f4, half\n  cvt.d.s $f6, $f4\n  mul.d $f8, $f2, $f6\n  c.lt.d $f6, $f2\n  bc1f end\n  trunc.w.d $f10, $f8\n  mfc1 $s7, $f10\nend:\n";
+        let (simulator, _) = run(text, Dialect::Mars, "");
+        assert_eq!(simulator.status, Status::Exited(0));
+        let registers = &simulator.machine.registers;
+        assert_eq!(registers[16] as i32, -3);
+        assert_eq!(registers[17] as i32, -1);
+        assert_eq!(registers[18] as i32, -4);
+        assert_eq!(registers[19], 0xf);
+        assert_eq!(registers[20] as i32, -1);
+        assert_eq!(registers[21], 0xff);
+        assert_eq!(registers[22], 1);
+        assert_eq!(registers[23], 1);
+    }
+}
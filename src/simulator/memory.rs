@@ -0,0 +1,83 @@
+//! Sparse little-endian memory of the simulated machine.
+
+use std::collections::HashMap;
+
+const PAGE_BITS: u32 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+/// Byte-addressed memory whose pages are allocated on first write; unwritten bytes read as zero.
+#[derive(Default)]
+pub struct Memory {
+    pages: HashMap<u32, Box<[u8; PAGE_SIZE]>>,
+}
+
+impl Memory {
+    pub fn load_byte(&self, address: u32) -> u8 {
+        self.pages
+            .get(&(address >> PAGE_BITS))
+            .map_or(0, |page| page[address as usize % PAGE_SIZE])
+    }
+
+    pub fn store_byte(&mut self, address: u32, value: u8) {
+        let page = self
+            .pages
+            .entry(address >> PAGE_BITS)
+            .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+        page[address as usize % PAGE_SIZE] = value;
+    }
+
+    pub fn load_half(&self, address: u32) -> u16 {
+        u16::from_le_bytes([
+            self.load_byte(address),
+            self.load_byte(address.wrapping_add(1)),
+        ])
+    }
+
+    pub fn store_half(&mut self, address: u32, value: u16) {
+        self.store_bytes(address, &value.to_le_bytes());
+    }
+
+    pub fn load_word(&self, address: u32) -> u32 {
+        let mut bytes = [0; 4];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.load_byte(address.wrapping_add(offset as u32));
+        }
+        u32::from_le_bytes(bytes)
+    }
+
+    pub fn store_word(&mut self, address: u32, value: u32) {
+        self.store_bytes(address, &value.to_le_bytes());
+    }
+
+    pub fn store_bytes(&mut self, address: u32, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.store_byte(address.wrapping_add(offset as u32), *byte);
+        }
+    }
+
+    /// Reads a null-terminated string, giving up after `limit` bytes.
+    pub fn load_string(&self, address: u32, limit: usize) -> Vec<u8> {
+        (0..limit as u32)
+            .map(|offset| self.load_byte(address.wrapping_add(offset)))
+            .take_while(|byte| *byte != 0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_little_endian_values_across_pages() {
+        let mut memory = Memory::default();
+        memory.store_word(0x1000_0ffe, 0x1234_5678);
+        assert_eq!(memory.load_byte(0x1000_0ffe), 0x78);
+        assert_eq!(memory.load_half(0x1000_1000), 0x1234);
+        assert_eq!(memory.load_word(0x1000_0ffe), 0x1234_5678);
+        assert_eq!(memory.load_word(0x2000_0000), 0);
+
+        memory.store_bytes(0x1001_0000, b"hi\0there");
+        assert_eq!(memory.load_string(0x1001_0000, 100), b"hi");
+    }
+}
@@ -0,0 +1,213 @@
+//! Instruction-level simulator for running programs without MARS or SPIM.
+//!
+//! The simulator follows MARS: memory is little-endian, branches and jumps take effect
+//! immediately without delay slots, pseudo-instructions execute as a whole and system calls use
+//! the MARS/SPIM services.
+
+mod execute;
+pub mod memory;
+pub mod program;
+mod syscall;
+
+use crate::document::Document;
+use crate::lang::LanguageDefinitions;
+use crate::settings::Settings;
+
+use memory::Memory;
+use program::Program;
+pub use syscall::{BufferConsole, Console};
+
+/// Initial stack pointer of MARS.
+const STACK_POINTER: u32 = 0x7fff_effc;
+/// Initial global pointer of MARS.
+const GLOBAL_POINTER: u32 = 0x1000_8000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Running,
+    /// the program ended with the exit code
+    Exited(i32),
+    Failed(RuntimeError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    /// statement of the instruction that failed
+    pub statement_index: Option<usize>,
+}
+
+/// Registers and memory of the simulated machine.
+pub struct Machine {
+    pub registers: [u32; 32],
+    pub hi: u32,
+    pub lo: u32,
+    /// floating point registers as bits; doubles use an even register and the next one
+    pub fpr: [u32; 32],
+    /// floating point condition flags, one bit per flag
+    pub condition_flags: u8,
+    pub pc: u32,
+    pub memory: Memory,
+    /// end of the memory allocated by `sbrk`
+    heap: u32,
+    /// state of the random number generator of the random syscalls
+    random: u64,
+}
+
+/// Effect of an instruction on the program counter.
+enum Flow {
+    Next,
+    Jump(u32),
+    Exit(i32),
+}
+
+pub struct Simulator {
+    pub program: Program,
+    pub machine: Machine,
+    pub status: Status,
+    /// number of instructions executed
+    pub steps: u64,
+}
+
+impl Simulator {
+    pub fn new(doc: &Document, definitions: &LanguageDefinitions, settings: &Settings) -> Self {
+        let (program, memory) = Program::load(doc, definitions, settings);
+        let mut registers = [0; 32];
+        registers[28] = GLOBAL_POINTER;
+        registers[29] = STACK_POINTER;
+        let machine = Machine {
+            registers,
+            hi: 0,
+            lo: 0,
+            fpr: [0; 32],
+            condition_flags: 0,
+            pc: program.entry,
+            memory,
+            heap: program.heap_start,
+            random: 0x2545_f491_4f6c_dd1d,
+        };
+        Simulator {
+            program,
+            machine,
+            status: Status::Running,
+            steps: 0,
+        }
+    }
+
+    /// Statement of the instruction at the program counter.
+    pub fn statement_index(&self) -> Option<usize> {
+        self.program
+            .instructions
+            .get(&self.machine.pc)
+            .map(|instruction| instruction.statement_index)
+    }
+
+    /// Executes the instruction at the program counter.
+    pub fn step(&mut self, console: &mut dyn Console) -> &Status {
+        if self.status != Status::Running {
+            return &self.status;
+        }
+        let pc = self.machine.pc;
+        let Some(instruction) = self.program.instructions.get(&pc) else {
+            // Running past the last instruction ends the program, as in MARS
+            self.status = if pc == self.program.text_end {
+                Status::Exited(0)
+            } else {
+                Status::Failed(RuntimeError {
+                    message: format!("no instruction at address 0x{:08x}", pc),
+                    statement_index: None,
+                })
+            };
+            return &self.status;
+        };
+
+        self.steps += 1;
+        match self.machine.execute(instruction, console) {
+            Ok(Flow::Next) => self.machine.pc = pc.wrapping_add(instruction.size),
+            Ok(Flow::Jump(target)) => self.machine.pc = target,
+            Ok(Flow::Exit(code)) => self.status = Status::Exited(code),
+            Err(message) => {
+                self.status = Status::Failed(RuntimeError {
+                    message,
+                    statement_index: Some(instruction.statement_index),
+                })
+            }
+        }
+        &self.status
+    }
+
+    /// Runs until the program ends or `max_steps` instructions were executed.
+    pub fn run(&mut self, console: &mut dyn Console, max_steps: u64) -> &Status {
+        while self.status == Status::Running {
+            if self.steps >= max_steps {
+                self.status = Status::Failed(RuntimeError {
+                    message: format!("stopped after {} instructions", max_steps),
+                    statement_index: self.statement_index(),
+                });
+                break;
+            }
+            self.step(console);
+        }
+        &self.status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::Dialect;
+    use std::str::FromStr;
+    use tower_lsp_server::ls_types::Uri;
+
+    pub(super) fn run(text: &str, dialect: Dialect, input: &str) -> (Simulator, String) {
+        let mut settings = Settings::default();
+        settings.dialect = dialect;
+        let mut definitions = LanguageDefinitions::new();
+        definitions.parse(&settings);
+        let mut doc = Document::new(
+            Uri::from_str("file:///test.asm").unwrap(),
+            1,
+            text.to_string(),
+        );
+        doc.update_model();
+
+        let mut simulator = Simulator::new(&doc, &definitions, &settings);
+        let mut console = BufferConsole::new(input);
+        simulator.run(&mut console, 10_000);
+        (simulator, console.output)
+    }
+
+    #[test]
+    fn runs_programs_with_syscalls() {
+        let text = ".data\nprompt: .asciiz \"n = \"\nvalues: .word 3, 4, 5\nend:\n.text\nmain:\n  li $v0, 4\n  la $a0, prompt\n  syscall\n  li $v0, 5\n  syscall\n  move $t0, $v0\n  la $t1, values\n  la $t4, end\n  li $t2, 0\nloop:\n  lw $t3, 0($t1)\n  mul $t3, $t3, $t0\n  addu $t2, $t2, $t3\n  addiu $t1, $t1, 4\n  blt $t1, $t4, loop\n  move $a0, $t2\n  jal print\n  li $v0, 10\n  syscall\nprint:\n  li $v0, 1\n  syscall\n  jr $ra\n";
+        let (simulator, output) = run(text, Dialect::Mars, "2\n");
+        assert_eq!(simulator.status, Status::Exited(0));
+        assert_eq!(output, "n = 24");
+        assert_eq!(simulator.machine.registers[10], 24);
+    }
+
+    #[test]
+    fn places_relative_layouts_at_mars_addresses() {
+        let text = ".data\nvalue: .word 7\n.text\nmain:\n  lw $t0, value\n  addiu $t0, $t0, 1\n";
+        let (simulator, _) = run(text, Dialect::Gas, "");
+        assert_eq!(simulator.status, Status::Exited(0));
+        assert_eq!(simulator.machine.registers[8], 8);
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        let text = ".text\nmain:\n  li $t0, 0x7fffffff\n  addi $t0, $t0, 1\n";
+        let (simulator, _) = run(text, Dialect::Mars, "");
+        assert_eq!(
+            simulator.status,
+            Status::Failed(RuntimeError {
+                message: "arithmetic overflow".to_string(),
+                statement_index: Some(3),
+            })
+        );
+
+        let (simulator, _) = run(".text\nmain:\n  j main\n", Dialect::Mars, "");
+        assert_eq!(simulator.steps, 10_000);
+        assert!(matches!(simulator.status, Status::Failed(_)));
+    }
+}
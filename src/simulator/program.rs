@@ -0,0 +1,278 @@
+//! Assembly of a document into the instructions and initial memory of the simulator.
+//!
+//! Addresses come from the layout of the document. Layouts relative to their sections, as for
+//! GAS, are placed at the addresses MARS uses.
+
+use std::collections::HashMap;
+
+use smol_str::SmolStr;
+
+use crate::ast::{OperandListItem, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::lang::{Dialect, LanguageDefinitions, Register};
+use crate::semantic::eval::Evaluator;
+use crate::semantic::layout::{data_size, section_base, string_bytes, Layout};
+use crate::semantic::{parse_section, Section};
+use crate::settings::Settings;
+
+use super::memory::Memory;
+
+/// Start of the heap used by `sbrk` in MARS.
+const HEAP_BASE: u32 = 0x1004_0000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Gpr(u8),
+    Fpr(u8),
+    /// constants, labels and expressions, resolved to their value
+    Value(i64),
+    /// `offset(base)`
+    Memory {
+        offset: i64,
+        base: u8,
+    },
+}
+
+#[derive(Debug)]
+pub struct Instruction {
+    /// lowercase mnemonic
+    pub mnemonic: String,
+    /// resolved operands, or why they could not be resolved
+    pub operands: Result<Vec<Operand>, String>,
+    pub statement_index: usize,
+    /// bytes the instruction occupies, more than 4 for pseudo-instructions
+    pub size: u32,
+}
+
+pub struct Program {
+    pub instructions: HashMap<u32, Instruction>,
+    /// `main` if it is defined, otherwise the start of `.text`
+    pub entry: u32,
+    /// address after the last instruction of `.text`, where programs end by falling off
+    pub text_end: u32,
+    pub heap_start: u32,
+    /// address of every label
+    pub labels: HashMap<SmolStr, i64>,
+}
+
+impl Program {
+    /// Assembles a document and returns the program with the memory holding its data.
+    pub fn load(
+        doc: &Document,
+        definitions: &LanguageDefinitions,
+        settings: &Settings,
+    ) -> (Program, Memory) {
+        let layout = Layout::compute(doc, definitions, settings);
+        let bases = section_bases(&layout);
+        let base = |section: Section| bases.get(&section).copied().unwrap_or(0);
+
+        let model = &doc.semantic_model;
+        let labels: HashMap<SmolStr, i64> = model
+            .labels
+            .iter()
+            .filter_map(|(name, label)| {
+                Some((name.clone(), layout.label(name)? + base(label.section)))
+            })
+            .collect();
+        let evaluator = Evaluator::new(doc, settings).with_labels(&labels);
+
+        let mut instructions = HashMap::new();
+        let mut memory = Memory::default();
+        let mut text_start = None;
+        let mut text_end = base(Section::Text) as u32;
+        let mut data_end = 0;
+        for (index, statement) in doc.ast.items.iter().enumerate() {
+            if model.macro_body[index] {
+                continue;
+            }
+            let Some(placement) = layout.statements[index] else {
+                continue;
+            };
+            let address = (placement.address + base(placement.section)) as u32;
+            match statement {
+                SyntaxNode::Instruction(node) => {
+                    let mnemonic = get_text_in_ts_range(&doc.text, node.mnemonic.range);
+                    let operands = if model.macros.contains_key(mnemonic) {
+                        Err("macros are not supported by the simulator".to_string())
+                    } else {
+                        operands(doc, &evaluator, &node.operands, index)
+                    };
+                    instructions.insert(
+                        address,
+                        Instruction {
+                            mnemonic: mnemonic.to_ascii_lowercase(),
+                            operands,
+                            statement_index: index,
+                            size: placement.size as u32,
+                        },
+                    );
+                }
+                SyntaxNode::MacroInvocation(node) => {
+                    instructions.insert(
+                        address,
+                        Instruction {
+                            mnemonic: get_text_in_ts_range(&doc.text, node.mnemonic.range)
+                                .to_ascii_lowercase(),
+                            operands: Err("macros are not supported by the simulator".to_string()),
+                            statement_index: index,
+                            size: placement.size as u32,
+                        },
+                    );
+                }
+                SyntaxNode::Directive(node) => {
+                    let mnemonic = get_text_in_ts_range(&doc.text, node.mnemonic.range);
+                    let bytes = data(doc, &evaluator, &mnemonic.to_ascii_lowercase(), node, index);
+                    memory.store_bytes(address, &bytes);
+                    data_end = data_end.max(address + placement.size as u32);
+                    continue;
+                }
+                _ => continue,
+            }
+            if placement.section == Section::Text {
+                text_start = Some(text_start.map_or(address, |start: u32| start.min(address)));
+                text_end = text_end.max(address + placement.size as u32);
+            }
+        }
+
+        let entry = ["main", "__start"]
+            .iter()
+            .find_map(|name| labels.get(*name))
+            .map(|address| *address as u32)
+            .or(text_start)
+            .unwrap_or(text_end);
+        let program = Program {
+            instructions,
+            entry,
+            text_end,
+            heap_start: HEAP_BASE.max((data_end + 7) & !7),
+            labels,
+        };
+        (program, memory)
+    }
+}
+
+/// Start address of every section; sections without a place in the MARS memory map follow
+/// `.data`.
+fn section_bases(layout: &Layout) -> HashMap<Section, i64> {
+    let mut bases = HashMap::new();
+    if layout.absolute {
+        return bases;
+    }
+    let mut next_data = section_base(Dialect::Mars, Section::Data).unwrap_or(0);
+    for section in &layout.sections {
+        let base = match section_base(Dialect::Mars, section.section) {
+            Some(base) if section.section != Section::Data => base,
+            _ => {
+                let base = next_data;
+                next_data = (base + section.size + 15) & !15;
+                base
+            }
+        };
+        bases.insert(section.section, base);
+    }
+    bases
+}
+
+fn operands(
+    doc: &Document,
+    evaluator: &Evaluator,
+    items: &[OperandListItem],
+    index: usize,
+) -> Result<Vec<Operand>, String> {
+    let register = |value: &ValueNode| -> Option<Operand> {
+        let ValueNode::Register { range } = value else {
+            return None;
+        };
+        match Register::parse(get_text_in_ts_range(&doc.text, *range))? {
+            Register::Gpr(number) => Some(Operand::Gpr(number)),
+            Register::Fpr(number) => Some(Operand::Fpr(number)),
+        }
+    };
+    let unresolved = |value: &ValueNode| {
+        format!(
+            "`{}` cannot be resolved",
+            get_text_in_ts_range(&doc.text, value.range())
+        )
+    };
+
+    items
+        .iter()
+        .filter_map(|item| match item {
+            OperandListItem::Operand(value) => Some(value),
+            _ => None,
+        })
+        .map(|value| {
+            if let ValueNode::Register { .. } = value {
+                return register(value).ok_or_else(|| unresolved(value));
+            }
+            if let ValueNode::ParenthesizedExpression { head, body, .. } = value {
+                if let [OperandListItem::Operand(inner)] = body.as_slice() {
+                    if let Some(Operand::Gpr(base)) = register(inner) {
+                        let offset = match head {
+                            Some(head) => evaluator
+                                .evaluate(head, index)
+                                .map_err(|_| unresolved(head))?,
+                            None => 0,
+                        };
+                        return Ok(Operand::Memory { offset, base });
+                    }
+                }
+            }
+            evaluator
+                .evaluate(value, index)
+                .map(Operand::Value)
+                .map_err(|_| unresolved(value))
+        })
+        .collect()
+}
+
+/// Bytes emitted by a data directive.
+fn data(
+    doc: &Document,
+    evaluator: &Evaluator,
+    mnemonic: &str,
+    node: &crate::ast::DirectiveNode,
+    index: usize,
+) -> Vec<u8> {
+    let values = node.operands.iter().filter_map(|item| match item {
+        OperandListItem::Operand(value) => Some(value),
+        _ => None,
+    });
+    let mut bytes = Vec::new();
+    match mnemonic {
+        ".ascii" | ".asciiz" | ".asciz" | ".string" => {
+            for value in values {
+                if let ValueNode::String { range, .. } = value {
+                    bytes.extend(string_bytes(get_text_in_ts_range(&doc.text, *range)));
+                    if mnemonic != ".ascii" {
+                        bytes.push(0);
+                    }
+                }
+            }
+        }
+        ".float" | ".single" | ".double" => {
+            for value in values {
+                let number = match value {
+                    ValueNode::Float { value, .. } => *value,
+                    value => evaluator.evaluate(value, index).unwrap_or(0) as f64,
+                };
+                if mnemonic == ".double" {
+                    bytes.extend(number.to_le_bytes());
+                } else {
+                    bytes.extend((number as f32).to_le_bytes());
+                }
+            }
+        }
+        _ => {
+            let Some(size) = data_size(mnemonic) else {
+                return bytes;
+            };
+            for value in values {
+                let number = evaluator.evaluate(value, index).unwrap_or(0);
+                bytes.extend(&number.to_le_bytes()[..size as usize]);
+            }
+        }
+    }
+    bytes
+}
@@ -0,0 +1,214 @@
+//! System call services of MARS and SPIM, selected by `$v0`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Flow, Machine};
+
+const V0: usize = 2;
+const A0: usize = 4;
+const A1: usize = 5;
+
+/// Standard input and output of the simulated program.
+pub trait Console {
+    fn write(&mut self, text: &str);
+    /// Reads a line without its line break, or `None` at the end of the input.
+    fn read_line(&mut self) -> Option<String>;
+    fn read_char(&mut self) -> Option<char>;
+}
+
+/// Console reading from a given input and collecting the output.
+pub struct BufferConsole {
+    input: Vec<char>,
+    position: usize,
+    pub output: String,
+}
+
+impl BufferConsole {
+    pub fn new(input: &str) -> Self {
+        BufferConsole {
+            input: input.chars().collect(),
+            position: 0,
+            output: String::new(),
+        }
+    }
+}
+
+impl Console for BufferConsole {
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        if self.position >= self.input.len() {
+            return None;
+        }
+        let rest = &self.input[self.position..];
+        let length = rest.iter().position(|c| *c == '\n');
+        let line: String = rest[..length.unwrap_or(rest.len())].iter().collect();
+        self.position += length.map_or(rest.len(), |length| length + 1);
+        Some(line.trim_end_matches('\r').to_string())
+    }
+
+    fn read_char(&mut self) -> Option<char> {
+        let c = self.input.get(self.position).copied()?;
+        self.position += 1;
+        Some(c)
+    }
+}
+
+/// Formats a floating point number like Java, which MARS uses: `1.0`, `0.1`, `1.0E10`, `NaN`.
+fn format_float(value: f64, single: bool) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-3..1e7).contains(&magnitude) {
+        let text = if single {
+            format!("{:e}", value as f32)
+        } else {
+            format!("{:e}", value)
+        };
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        let mantissa = if mantissa.contains('.') {
+            mantissa.to_string()
+        } else {
+            format!("{}.0", mantissa)
+        };
+        return format!("{}E{}", mantissa, exponent);
+    }
+    if single {
+        format!("{:?}", value as f32)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+impl Machine {
+    pub(super) fn syscall(&mut self, console: &mut dyn Console) -> Result<Flow, String> {
+        let service = self.registers[V0];
+        let a0 = self.registers[A0];
+        match service {
+            1 => console.write(&(a0 as i32).to_string()),
+            2 => console.write(&format_float(f32::from_bits(self.fpr[12]) as f64, true)),
+            3 => {
+                let bits = (self.fpr[13] as u64) << 32 | self.fpr[12] as u64;
+                console.write(&format_float(f64::from_bits(bits), false));
+            }
+            4 => {
+                let bytes = self.memory.load_string(a0, 1 << 20);
+                console.write(&String::from_utf8_lossy(&bytes));
+            }
+            5 => {
+                let line = console.read_line().unwrap_or_default();
+                let value: i32 = line
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid integer input `{}`", line.trim()))?;
+                self.registers[V0] = value as u32;
+            }
+            6 | 7 => {
+                let line = console.read_line().unwrap_or_default();
+                let value: f64 = line
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid floating point input `{}`", line.trim()))?;
+                self.set_float(0, value, service == 7)?;
+            }
+            8 => {
+                let length = self.registers[A1] as i32;
+                if length < 1 {
+                    return Ok(Flow::Next);
+                }
+                let mut bytes = console.read_line().map_or(Vec::new(), |mut line| {
+                    line.push('\n');
+                    line.into_bytes()
+                });
+                bytes.truncate(length as usize - 1);
+                bytes.push(0);
+                self.memory.store_bytes(a0, &bytes);
+            }
+            9 => {
+                self.registers[V0] = self.heap;
+                self.heap = self.heap.wrapping_add((a0 + 3) & !3);
+            }
+            10 => return Ok(Flow::Exit(0)),
+            11 => console.write(&char::from(a0 as u8).to_string()),
+            12 => self.registers[V0] = console.read_char().map_or(0, |c| c as u32),
+            17 => return Ok(Flow::Exit(a0 as i32)),
+            30 => {
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_millis() as u64);
+                self.registers[A0] = millis as u32;
+                self.registers[A1] = (millis >> 32) as u32;
+            }
+            // MIDI output and sleeping have no effect
+            31..=33 => {}
+            34 => console.write(&format!("0x{:08x}", a0)),
+            35 => console.write(&format!("{:032b}", a0)),
+            36 => console.write(&a0.to_string()),
+            40 => self.random = (self.registers[A1] as u64) | 1,
+            41 => self.registers[A0] = self.next_random() as u32,
+            42 => {
+                let bound = self.registers[A1];
+                if bound == 0 {
+                    return Err("upper bound of the random range must be positive".to_string());
+                }
+                self.registers[A0] = (self.next_random() % bound as u64) as u32;
+            }
+            43 => {
+                let value = (self.next_random() >> 40) as f32 / (1u32 << 24) as f32;
+                self.fpr[0] = value.to_bits();
+            }
+            44 => {
+                let value = (self.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+                self.set_float(0, value, true)?;
+            }
+            _ => {
+                return Err(format!(
+                    "system call {} is not supported by the simulator",
+                    service
+                ))
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// xorshift64
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.random;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::run;
+    use super::*;
+    use crate::lang::Dialect;
+
+    #[test]
+    fn formats_floats_like_mars() {
+        assert_eq!(format_float(1.0, false), "1.0");
+        assert_eq!(format_float(0.1, true), "0.1");
+        assert_eq!(format_float(1e10, false), "1.0E10");
+        assert_eq!(format_float(f64::NEG_INFINITY, false), "-Infinity");
+    }
+
+    #[test]
+    fn reads_strings_and_allocates_memory() {
+        let text = ".data\nbuffer: .space 8\n.text\nmain:\n  li $v0, 8\n  la $a0, buffer\n  li $a1, 4\n  syscall\n  li $v0, 4\n  syscall\n  li $v0, 9\n  li $a0, 5\n  syscall\n  move $s0, $v0\n  li $v0, 9\n  syscall\n  move $s1, $v0\n  li $v0, 34\n  li $a0, 255\n  syscall\n  li $a0, 3\n  li $v0, 17\n  syscall\n";
+        let (simulator, output) = run(text, Dialect::Mars, "hello\n");
+        assert_eq!(output, "hel0x000000ff");
+        assert_eq!(simulator.status, super::super::Status::Exited(3));
+        let registers = &simulator.machine.registers;
+        assert_eq!(registers[17] - registers[16], 8);
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::settings::SettingsError;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -22,6 +24,9 @@ pub enum VersionLabel {
 impl VersionLabel {
     pub fn parse(s: &str) -> Result<Self, SettingsError> {
         match s {
+            // Labels without a release are the first release
+            "mips32" => Ok(VersionLabel::Mips32r1),
+            "mips64" => Ok(VersionLabel::Mips64r1),
             "mips1" => Ok(VersionLabel::Mips1),
             "mips2" => Ok(VersionLabel::Mips2),
             "mips3" => Ok(VersionLabel::Mips3),
@@ -42,6 +47,75 @@ impl VersionLabel {
     }
 }
 
+impl fmt::Display for VersionLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VersionLabel::Mips1 => "MIPS I",
+            VersionLabel::Mips2 => "MIPS II",
+            VersionLabel::Mips3 => "MIPS III",
+            VersionLabel::Mips4 => "MIPS IV",
+            VersionLabel::Mips5 => "MIPS V",
+            VersionLabel::Mips32r1 => "MIPS32 Release 1",
+            VersionLabel::Mips32r2 => "MIPS32 Release 2",
+            VersionLabel::Mips32r3 => "MIPS32 Release 3",
+            VersionLabel::Mips32r5 => "MIPS32 Release 5",
+            VersionLabel::Mips32r6 => "MIPS32 Release 6",
+            VersionLabel::Mips64r1 => "MIPS64 Release 1",
+            VersionLabel::Mips64r2 => "MIPS64 Release 2",
+            VersionLabel::Mips64r3 => "MIPS64 Release 3",
+            VersionLabel::Mips64r5 => "MIPS64 Release 5",
+            VersionLabel::Mips64r6 => "MIPS64 Release 6",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Application-specific extension, which a core may implement on top of its release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Extension {
+    Mdmx,
+    Eva,
+    Xpa,
+    Crc,
+}
+
+impl Extension {
+    pub fn parse(s: &str) -> Result<Self, SettingsError> {
+        match s.to_lowercase().as_str() {
+            "mdmx" => Ok(Extension::Mdmx),
+            "eva" => Ok(Extension::Eva),
+            "xpa" => Ok(Extension::Xpa),
+            "crc" => Ok(Extension::Crc),
+            _ => Err(SettingsError::UnknownExtension(s.into())),
+        }
+    }
+
+    /// Parses the `introduced` label of an instruction that belongs to an extension, returning
+    /// the extension and the first release it requires.
+    pub fn parse_introduced(label: &str) -> Option<(Self, VersionLabel)> {
+        match label {
+            "mdmx" => Some((Extension::Mdmx, VersionLabel::Mips64r1)),
+            "eva" => Some((Extension::Eva, VersionLabel::Mips32r3)),
+            "xpa" => Some((Extension::Xpa, VersionLabel::Mips32r5)),
+            "crc" => Some((Extension::Crc, VersionLabel::Mips32r6)),
+            "crc64" => Some((Extension::Crc, VersionLabel::Mips64r6)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Extension::Mdmx => "MDMX",
+            Extension::Eva => "EVA",
+            Extension::Xpa => "XPA",
+            Extension::Crc => "CRC",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Debug)]
 pub struct Version {
     label: VersionLabel,
@@ -242,6 +316,10 @@ Version inheritance tree:
         └── mips3
 */
 impl Version {
+    pub fn label(&self) -> VersionLabel {
+        self.label
+    }
+
     pub fn has_ancestor(&self, ancestor: VersionLabel) -> bool {
         self.ancestors.contains(&ancestor)
     }
@@ -0,0 +1,403 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use smol_str::{SmolStr, ToSmolStr};
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::*;
+
+use crate::ast::{visit_operands, SyntaxNode, ValueNode};
+use crate::document::utils::get_text_in_ts_range;
+use crate::document::Document;
+use crate::fuzzy::fuzzy_score;
+use crate::semantic::Section;
+use crate::server::Backend;
+
+/// File extensions of assembly sources picked up by the workspace index.
+pub const EXTENSIONS: &[&str] = &["asm", "s", "S", "mips"];
+
+/// Directories that are never scanned for assembly sources.
+const IGNORED_DIRECTORIES: &[&str] = &["target", "node_modules", "build"];
+
+/// Maximum number of symbols returned for a single workspace symbol query.
+const MAX_RESULTS: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct IndexedSymbol {
+    pub name: SmolStr,
+    pub kind: SymbolKind,
+    pub range: Range,
+    /// true if the symbol is exported with `.globl` or `.global`
+    pub global: bool,
+}
+
+/// Symbols of all assembly files in the workspace folders, keyed by file.
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    pub files: HashMap<Uri, Vec<IndexedSymbol>>,
+}
+
+impl WorkspaceIndex {
+    pub fn update(&mut self, uri: Uri, symbols: Vec<IndexedSymbol>) {
+        self.files.insert(uri, symbols);
+    }
+
+    pub fn remove(&mut self, uri: &Uri) {
+        self.files.remove(uri);
+    }
+
+    /// Returns all symbols matching the query, best matches first.
+    pub fn query(&self, query: &str) -> Vec<(&Uri, &IndexedSymbol)> {
+        let mut matches: Vec<_> = self
+            .files
+            .iter()
+            .flat_map(|(uri, symbols)| symbols.iter().map(move |symbol| (uri, symbol)))
+            .filter_map(|(uri, symbol)| {
+                fuzzy_score(query, &symbol.name).map(|score| (score, uri, symbol))
+            })
+            .collect();
+
+        matches.sort_by(|(a_score, _, a), (b_score, _, b)| {
+            b_score.cmp(a_score).then_with(|| a.name.cmp(&b.name))
+        });
+        matches.truncate(MAX_RESULTS);
+
+        matches
+            .into_iter()
+            .map(|(_, uri, symbol)| (uri, symbol))
+            .collect()
+    }
+}
+
+impl Backend {
+    pub async fn handle_workspace_symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<WorkspaceSymbolResponse>> {
+        log!("workspace/symbol");
+
+        let workspace = self.workspace.read().await;
+
+        #[allow(deprecated)]
+        let symbols = workspace
+            .query(&params.query)
+            .into_iter()
+            .map(|(uri, symbol)| SymbolInformation {
+                name: symbol.name.to_string(),
+                kind: symbol.kind,
+                tags: None,
+                deprecated: None,
+                location: Location {
+                    uri: uri.clone(),
+                    range: symbol.range,
+                },
+                container_name: symbol.global.then(|| "global".to_string()),
+            })
+            .collect();
+
+        Ok(Some(WorkspaceSymbolResponse::Flat(symbols)))
+    }
+
+    /// Scans the given folders and replaces the symbols of all files found in them.
+    pub async fn index_workspace_folders(&self, folders: Vec<PathBuf>) {
+        let indexed = tokio::task::spawn_blocking(move || {
+            let mut files = Vec::new();
+            for folder in &folders {
+                collect_assembly_files(folder, &mut files);
+            }
+
+            files
+                .into_iter()
+                .filter_map(|path| {
+                    let uri = Uri::from_file_path(&path)?;
+                    let text = std::fs::read_to_string(&path).ok()?;
+                    let mut doc = Document::new(uri.clone(), 0, text);
+                    doc.update_model();
+                    Some((uri, index_document(&doc)))
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+
+        let Ok(indexed) = indexed else {
+            log!("Failed to index workspace folders");
+            return;
+        };
+
+        log!("Indexed {} assembly files", indexed.len());
+
+        let mut workspace = self.workspace.write().await;
+        for (uri, symbols) in indexed {
+            // Open documents are indexed from their (possibly unsaved) contents instead
+            if !self.documents.contains_key(&uri) {
+                workspace.update(uri, symbols);
+            }
+        }
+    }
+
+    /// Re-indexes a file from disk, or drops it from the index if it no longer exists or is not in
+    /// one of the workspace folders.
+    pub async fn reindex_file_from_disk(&self, uri: &Uri) {
+        let folders = self.workspace_folders.read().await.clone();
+        let symbols = uri
+            .to_file_path()
+            .filter(|path| folders.iter().any(|folder| path.starts_with(folder)))
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| {
+                let mut doc = Document::new(uri.clone(), 0, text);
+                doc.update_model();
+                index_document(&doc)
+            });
+
+        let mut workspace = self.workspace.write().await;
+        match symbols {
+            Some(symbols) => workspace.update(uri.clone(), symbols),
+            None => workspace.remove(uri),
+        }
+    }
+}
+
+/// Collects the labels, macros and `.globl` names of a document.
+pub fn index_document(doc: &Document) -> Vec<IndexedSymbol> {
+    let text = &doc.text;
+    let model = &doc.semantic_model;
+
+    let mut globals: HashMap<SmolStr, tree_sitter::Range> = HashMap::new();
+    for directive in &model.directives {
+        let Some(SyntaxNode::Directive(node)) = doc.ast.items.get(directive.statement_index) else {
+            continue;
+        };
+        if !matches!(
+            get_text_in_ts_range(text, node.mnemonic.range),
+            ".globl" | ".global"
+        ) {
+            continue;
+        }
+        visit_operands(&node.operands, &mut |value| {
+            if let ValueNode::Symbol { range } = value {
+                globals.insert(get_text_in_ts_range(text, *range).to_smolstr(), *range);
+            }
+        });
+    }
+
+    let mut symbols = Vec::new();
+
+    for (name, label) in &model.labels {
+        let Some(SyntaxNode::Label(node)) = doc.ast.items.get(label.statement_index) else {
+            continue;
+        };
+        let kind = match label.section {
+            Section::Text | Section::KText => SymbolKind::FUNCTION,
+            _ => SymbolKind::VARIABLE,
+        };
+        symbols.push(IndexedSymbol {
+            name: name.clone(),
+            kind,
+            range: doc.ts_range_to_ls(&node.name.range),
+            global: globals.contains_key(name),
+        });
+    }
+
+    for (name, macro_def) in &model.macros {
+        let Some(SyntaxNode::MacroDefinition(node)) = doc.ast.items.get(macro_def.statement_index)
+        else {
+            continue;
+        };
+        let Some(name_node) = &node.name else {
+            continue;
+        };
+        symbols.push(IndexedSymbol {
+            name: name.clone(),
+            kind: SymbolKind::METHOD,
+            range: doc.ts_range_to_ls(&name_node.range),
+            global: false,
+        });
+    }
+
+    // Global names without a local definition are declared in another file
+    for (name, range) in &globals {
+        if !model.labels.contains_key(name) {
+            symbols.push(IndexedSymbol {
+                name: name.clone(),
+                kind: SymbolKind::INTERFACE,
+                range: doc.ts_range_to_ls(range),
+                global: true,
+            });
+        }
+    }
+
+    symbols
+}
+
+pub fn is_assembly_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension))
+}
+
+fn collect_assembly_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    let mut visited = HashSet::new();
+    collect_assembly_files_inner(directory, files, &mut visited);
+}
+
+fn collect_assembly_files_inner(
+    directory: &Path,
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) {
+    // Guard against symlink cycles
+    let Ok(canonical) = directory.canonicalize() else {
+        return;
+    };
+    if !visited.insert(canonical) {
+        return;
+    }
+
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if path.is_dir() {
+            if !name.starts_with('.') && !IGNORED_DIRECTORIES.contains(&name.as_ref()) {
+                collect_assembly_files_inner(&path, files, visited);
+            }
+        } else if is_assembly_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn indexes_labels_macros_and_globals() {
+        let uri = Uri::from_str("file:///test.asm").unwrap();
+        let mut doc = Document::new(
+            uri.clone(),
+            1,
+            ".globl main\n.globl print_list\n.macro exit\n  li $v0, 10\n.end_macro\n.data\nlist: .word 1\n.text\nmain:\n  exit\n"
+                .to_string(),
+        );
+        doc.update_model();
+
+        let mut index = WorkspaceIndex::default();
+        index.update(uri, index_document(&doc));
+
+        let find = |name: &str| {
+            index
+                .query(name)
+                .into_iter()
+                .map(|(_, symbol)| symbol.clone())
+                .find(|symbol| symbol.name == name)
+                .unwrap()
+        };
+
+        assert_eq!(find("main").kind, SymbolKind::FUNCTION);
+        assert!(find("main").global);
+        assert_eq!(find("list").kind, SymbolKind::VARIABLE);
+        assert_eq!(find("exit").kind, SymbolKind::METHOD);
+        assert_eq!(find("print_list").kind, SymbolKind::INTERFACE);
+
+        let results = index.query("lst");
+        assert_eq!(results[0].1.name, "list");
+    }
+
+    #[tokio::test]
+    async fn reindexes_files_changed_outside_the_editor() {
+        use tower_lsp_server::{LanguageServer, LspService};
+
+        let directory = std::env::temp_dir().join(format!("mipsls-index-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("lib.asm");
+        std::fs::write(
+            &path,
+            ".text
+old_routine:
+  jr $ra
+",
+        )
+        .unwrap();
+
+        let (service, _socket) = LspService::new(Backend::new);
+        let backend = service.inner();
+        *backend.workspace_folders.write().await = vec![directory.clone()];
+        backend
+            .index_workspace_folders(vec![directory.clone()])
+            .await;
+        assert_eq!(backend.workspace.read().await.query("old_routine").len(), 1);
+
+        std::fs::write(
+            &path,
+            ".text
+new_routine:
+  jr $ra
+",
+        )
+        .unwrap();
+        let uri = Uri::from_file_path(&path).unwrap();
+        backend
+            .did_change_watched_files(DidChangeWatchedFilesParams {
+                changes: vec![FileEvent::new(uri, FileChangeType::CHANGED)],
+            })
+            .await;
+
+        let workspace = backend.workspace.read().await;
+        assert!(workspace
+            .query("old_routine")
+            .iter()
+            .all(|(_, symbol)| symbol.name != "old_routine"));
+        assert_eq!(workspace.query("new_routine")[0].1.name, "new_routine");
+        drop(workspace);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drops_closed_files_outside_the_workspace() {
+        use tower_lsp_server::{LanguageServer, LspService};
+
+        let root = std::env::temp_dir().join(format!("mipsls-closed-{}", std::process::id()));
+        let folder = root.join("project");
+        std::fs::create_dir_all(&folder).unwrap();
+        let (service, _socket) = LspService::new(Backend::new);
+        let backend = service.inner();
+        *backend.workspace_folders.write().await = vec![folder.clone()];
+
+        for path in [folder.join("inside.asm"), root.join("outside.asm")] {
+            std::fs::write(&path, ".text\nroutine:\n  jr $ra\n").unwrap();
+            let uri = Uri::from_file_path(&path).unwrap();
+            backend
+                .did_open(DidOpenTextDocumentParams {
+                    text_document: TextDocumentItem::new(
+                        uri.clone(),
+                        "mips".to_string(),
+                        1,
+                        std::fs::read_to_string(&path).unwrap(),
+                    ),
+                })
+                .await;
+            backend
+                .did_close(DidCloseTextDocumentParams {
+                    text_document: TextDocumentIdentifier::new(uri),
+                })
+                .await;
+        }
+
+        let workspace = backend.workspace.read().await;
+        let files: Vec<_> = workspace.files.keys().collect();
+        assert_eq!(
+            files,
+            vec![&Uri::from_file_path(folder.join("inside.asm")).unwrap()]
+        );
+        drop(workspace);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}